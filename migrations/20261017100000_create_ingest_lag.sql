@@ -0,0 +1,9 @@
+-- records events dropped because the event receiver fell behind the broadcast channel
+CREATE TABLE IF NOT EXISTS ingest_lag (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    dropped_events INTEGER NOT NULL,
+    timestamp TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_ingest_lag_timestamp ON ingest_lag(timestamp);
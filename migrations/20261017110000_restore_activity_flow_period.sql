@@ -0,0 +1,15 @@
+-- 10 minute (by default) periods scored from the activity states they contain. times are in seconds
+CREATE TABLE IF NOT EXISTS activity_flow_period (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL,
+    score REAL NOT NULL,
+    app_switches INTEGER NOT NULL,
+    active_time INTEGER NOT NULL,
+    inactive_time INTEGER NOT NULL,
+    creating_time INTEGER NOT NULL,
+    consuming_time INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_activity_flow_period_times ON activity_flow_period(start_time, end_time);
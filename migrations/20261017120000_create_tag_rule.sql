@@ -0,0 +1,24 @@
+-- the url of window activities is needed again to match tag rules against the url path
+ALTER TABLE activity ADD COLUMN url TEXT;
+
+-- user defined rules that tag an app's activity based on its window title and/or url path.
+-- a NULL app_external_id matches every app and a NULL pattern matches any value
+CREATE TABLE IF NOT EXISTS tag_rule (
+  id TEXT PRIMARY KEY NOT NULL,
+  app_external_id TEXT,
+  title_pattern TEXT,
+  url_pattern TEXT,
+  tag_id TEXT NOT NULL,
+  weight REAL NOT NULL DEFAULT 1.0,
+  priority INTEGER NOT NULL DEFAULT 0,
+  is_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (tag_id) REFERENCES tag(id)
+);
+
+CREATE INDEX idx_tag_rule_app_external_id ON tag_rule(app_external_id);
+
+-- tags created by a rule record the rule and the app it matched instead of an app_tag
+ALTER TABLE activity_state_tag ADD COLUMN app_id TEXT REFERENCES app(id);
+ALTER TABLE activity_state_tag ADD COLUMN tag_rule_id TEXT REFERENCES tag_rule(id);
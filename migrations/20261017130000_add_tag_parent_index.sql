@@ -0,0 +1,2 @@
+-- tag hierarchy lookups walk parent_tag_id in both directions
+CREATE INDEX IF NOT EXISTS idx_tag_parent_tag_id ON tag(parent_tag_id);
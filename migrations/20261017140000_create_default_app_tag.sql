@@ -0,0 +1,12 @@
+-- the seeded app tags, kept so an app can be reset to them after its tags were changed
+CREATE TABLE IF NOT EXISTS default_app_tag (
+  app_id TEXT NOT NULL,
+  tag_id TEXT NOT NULL,
+  weight REAL NOT NULL,
+  FOREIGN KEY (app_id) REFERENCES app(id),
+  FOREIGN KEY (tag_id) REFERENCES tag(id),
+  PRIMARY KEY (app_id, tag_id)
+);
+
+INSERT INTO default_app_tag (app_id, tag_id, weight)
+SELECT app_id, tag_id, weight FROM app_tag WHERE is_default = TRUE;
@@ -0,0 +1,3 @@
+-- seconds of the activity state attributed to the tag: the time its app (or matched window) was
+-- focused times the app_tag or tag rule weight. NULL for tags not derived from an app, e.g. idle
+ALTER TABLE activity_state_tag ADD COLUMN weighted_duration REAL;
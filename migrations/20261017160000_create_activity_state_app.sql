@@ -0,0 +1,14 @@
+-- seconds each app (and window title) was focused during an activity state, derived from the
+-- window activities in the state and the window carried over from before it
+CREATE TABLE IF NOT EXISTS activity_state_app (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  activity_state_id INTEGER NOT NULL,
+  app_id TEXT NOT NULL,
+  window_title TEXT,
+  seconds REAL NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (activity_state_id) REFERENCES activity_state(id),
+  FOREIGN KEY (app_id) REFERENCES app(id)
+);
+
+CREATE INDEX idx_activity_state_app_activity_state_id ON activity_state_app(activity_state_id);
@@ -0,0 +1,2 @@
+-- seconds of input within an active state, only recorded for partially active states
+ALTER TABLE activity_state ADD COLUMN active_seconds INTEGER;
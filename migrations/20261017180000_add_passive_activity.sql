@@ -0,0 +1,52 @@
+-- activity_state is rebuilt to allow the PASSIVE state. foreign keys can't be turned off within the
+-- migration's transaction, so they are deferred: re-inserting the states resolves the tags referencing them
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE activity_state_backup AS SELECT * FROM activity_state;
+DROP TABLE activity_state;
+
+CREATE TABLE activity_state (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    state TEXT NOT NULL CHECK (state IN ('ACTIVE', 'INACTIVE', 'PASSIVE')) DEFAULT 'INACTIVE',
+    app_switches INTEGER NOT NULL DEFAULT 0,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    active_seconds INTEGER
+);
+
+INSERT INTO activity_state (id, state, app_switches, start_time, end_time, created_at, active_seconds)
+SELECT id, state, app_switches, start_time, end_time, created_at, active_seconds FROM activity_state_backup;
+
+DROP TABLE activity_state_backup;
+
+-- dropped with the table
+CREATE INDEX idx_activity_state_times ON activity_state(start_time, end_time);
+
+-- a focused window of a passive app (or an app with a passive tag) keeps a state without input from being idle
+ALTER TABLE app ADD COLUMN is_passive BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE tag ADD COLUMN is_passive BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- meeting and video apps
+UPDATE app SET is_passive = TRUE WHERE app_external_id IN (
+    'zoom.us',
+    'com.apple.FaceTime',
+    'FaceTime',
+    'teams.microsoft.com',
+    'skype.com',
+    'youtube.com',
+    'netflix.com',
+    'primevideo.com',
+    'disneyplus.com',
+    'hulu.com',
+    'max.com',
+    'peacocktv.com',
+    'paramountplus.com',
+    'crunchyroll.com',
+    'rakuten.tv',
+    'tv.apple.com',
+    'com.apple.TV',
+    'twitch.tv',
+    'vimeo.com',
+    'wetv.vip'
+);
@@ -0,0 +1,10 @@
+-- time the activity state loop didn't cover, e.g. because the machine was asleep or the service was stopped
+CREATE TABLE IF NOT EXISTS activity_gap (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL,
+    reason TEXT NOT NULL CHECK (reason IN ('SUSPEND', 'SERVICE_NOT_RUNNING', 'CLOCK_JUMP')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_activity_gap_times ON activity_gap(start_time, end_time);
@@ -0,0 +1,24 @@
+-- activity_state is rebuilt to allow the PAUSED state, recorded while recording is paused or a
+-- private app is focused. foreign keys are deferred as in the PASSIVE migration
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE activity_state_backup AS SELECT * FROM activity_state;
+DROP TABLE activity_state;
+
+CREATE TABLE activity_state (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    state TEXT NOT NULL CHECK (state IN ('ACTIVE', 'INACTIVE', 'PASSIVE', 'PAUSED')) DEFAULT 'INACTIVE',
+    app_switches INTEGER NOT NULL DEFAULT 0,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    active_seconds INTEGER
+);
+
+INSERT INTO activity_state (id, state, app_switches, start_time, end_time, created_at, active_seconds)
+SELECT id, state, app_switches, start_time, end_time, created_at, active_seconds FROM activity_state_backup;
+
+DROP TABLE activity_state_backup;
+
+-- dropped with the table
+CREATE INDEX idx_activity_state_times ON activity_state(start_time, end_time);
@@ -0,0 +1,12 @@
+-- per-minute counts of the raw activity rows removed by retention. A minute can have more than one
+-- row per type and app (e.g. the last window activity is rolled up later), so counts are summed
+CREATE TABLE activity_rollup (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    minute TIMESTAMP NOT NULL,
+    activity_type TEXT NOT NULL CHECK (activity_type IN ('WINDOW', 'MOUSE', 'KEYBOARD')),
+    app_id TEXT REFERENCES app(id),
+    event_count INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_activity_rollup_minute ON activity_rollup(minute);
@@ -0,0 +1,8 @@
+-- a random key generated once per install, used to hash window titles without them being guessable
+CREATE TABLE IF NOT EXISTS install_secret (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    secret BLOB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO install_secret (id, secret) VALUES (1, randomblob(32));
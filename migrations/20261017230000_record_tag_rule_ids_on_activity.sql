@@ -0,0 +1,4 @@
+-- window urls aren't stored, so the rules a window matched on its title and url are recorded when it
+-- is focused, as comma separated tag_rule ids
+ALTER TABLE activity DROP COLUMN url;
+ALTER TABLE activity ADD COLUMN tag_rule_ids TEXT;
@@ -0,0 +1,334 @@
+use std::time::Duration;
+
+use time::{format_description, Date, UtcOffset};
+
+pub const USAGE: &str = "Usage: os-monitor-service [--db <path>] <command>
+
+Commands:
+  run [--interval <secs>] [--http-port <port>] [--live-stream <socket>] [--record <file>]
+      [--private-app <app or domain>]...
+      Start monitoring (default when no command is given)
+  report [--from <YYYY-MM-DD>] [--to <YYYY-MM-DD>] [--offset <+HH:MM>] [--weekly] [--json]
+      Print usage summaries per day (or per week), today in UTC by default
+  apps [--json]
+      List known apps
+  tags [--json]
+      List tags
+  export [--from <YYYY-MM-DD>] [--to <YYYY-MM-DD>] [--offset <+HH:MM>] [--output <file>]
+      Export activity states as NDJSON, to stdout by default
+  db check
+      Run SQLite integrity and foreign key checks
+  db vacuum
+      Reclaim unused space in the database file
+  help
+      Print this message";
+
+#[derive(Debug, PartialEq)]
+pub struct RunOptions {
+    pub interval: Duration,
+    pub http_port: Option<u16>,
+    pub live_stream_path: Option<String>,
+    pub recording_path: Option<String>,
+    pub private_apps: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DateRange {
+    pub from: Option<Date>,
+    pub to: Option<Date>,
+    pub offset: UtcOffset,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(RunOptions),
+    Report {
+        range: DateRange,
+        weekly: bool,
+        json: bool,
+    },
+    Apps {
+        json: bool,
+    },
+    Tags {
+        json: bool,
+    },
+    Export {
+        range: DateRange,
+        output: Option<String>,
+    },
+    DbCheck,
+    DbVacuum,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub db_path: Option<String>,
+    pub command: Command,
+}
+
+struct Args {
+    args: std::vec::IntoIter<String>,
+}
+
+impl Args {
+    fn value(&mut self, flag: &str) -> Result<String, String> {
+        self.args
+            .next()
+            .ok_or_else(|| format!("{} requires a value", flag))
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.args.next()
+    }
+}
+
+/**
+ * Parses the command line arguments (without the program name).
+ */
+pub fn parse_args(args: Vec<String>) -> Result<Cli, String> {
+    let mut args = Args {
+        args: args.into_iter(),
+    };
+    let mut db_path = None;
+    let command = loop {
+        match args.next().as_deref() {
+            Some("--db") => db_path = Some(args.value("--db")?),
+            Some("run") | None => break parse_run(&mut args)?,
+            Some("report") => {
+                let (range, flags) = parse_date_range(&mut args, &["--weekly", "--json"])?;
+                break Command::Report {
+                    range,
+                    weekly: flags.contains(&"--weekly"),
+                    json: flags.contains(&"--json"),
+                };
+            }
+            Some("apps") => {
+                break Command::Apps {
+                    json: parse_json_flag(&mut args)?,
+                }
+            }
+            Some("tags") => {
+                break Command::Tags {
+                    json: parse_json_flag(&mut args)?,
+                }
+            }
+            Some("export") => break parse_export(&mut args)?,
+            Some("db") => match args.next().as_deref() {
+                Some("check") => break Command::DbCheck,
+                Some("vacuum") => break Command::DbVacuum,
+                _ => return Err("db requires `check` or `vacuum`".to_string()),
+            },
+            Some("help") | Some("--help") | Some("-h") => break Command::Help,
+            Some(other) => return Err(format!("unknown command `{}`", other)),
+        }
+    };
+    if let Some(extra) = args.next() {
+        return Err(format!("unexpected argument `{}`", extra));
+    }
+    Ok(Cli { db_path, command })
+}
+
+fn parse_run(args: &mut Args) -> Result<Command, String> {
+    let mut options = RunOptions {
+        interval: Duration::from_secs(60),
+        http_port: None,
+        live_stream_path: None,
+        recording_path: None,
+        private_apps: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interval" => {
+                let seconds = args.value("--interval")?;
+                // tokio intervals panic on a zero period
+                options.interval = seconds
+                    .parse()
+                    .ok()
+                    .filter(|seconds| *seconds > 0)
+                    .map(Duration::from_secs)
+                    .ok_or_else(|| format!("invalid interval `{}`", seconds))?;
+            }
+            "--http-port" => {
+                let port = args.value("--http-port")?;
+                options.http_port = Some(
+                    port.parse()
+                        .map_err(|_| format!("invalid port `{}`", port))?,
+                );
+            }
+            "--live-stream" => options.live_stream_path = Some(args.value("--live-stream")?),
+            "--record" => options.recording_path = Some(args.value("--record")?),
+            "--private-app" => options.private_apps.push(args.value("--private-app")?),
+            other => return Err(format!("unknown option `{}` for run", other)),
+        }
+    }
+    Ok(Command::Run(options))
+}
+
+fn parse_export(args: &mut Args) -> Result<Command, String> {
+    let mut output = None;
+    let mut range = DateRange {
+        from: None,
+        to: None,
+        offset: UtcOffset::UTC,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(args.value("--output")?),
+            flag => parse_date_range_flag(args, flag, &mut range)?,
+        }
+    }
+    Ok(Command::Export { range, output })
+}
+
+fn parse_json_flag(args: &mut Args) -> Result<bool, String> {
+    match args.next().as_deref() {
+        Some("--json") => Ok(true),
+        Some(other) => Err(format!("unknown option `{}`", other)),
+        None => Ok(false),
+    }
+}
+
+fn parse_date_range<'a>(
+    args: &mut Args,
+    allowed_flags: &[&'a str],
+) -> Result<(DateRange, Vec<&'a str>), String> {
+    let mut range = DateRange {
+        from: None,
+        to: None,
+        offset: UtcOffset::UTC,
+    };
+    let mut flags = Vec::new();
+    while let Some(arg) = args.next() {
+        match allowed_flags.iter().find(|flag| **flag == arg) {
+            Some(flag) => flags.push(*flag),
+            None => parse_date_range_flag(args, &arg, &mut range)?,
+        }
+    }
+    Ok((range, flags))
+}
+
+fn parse_date_range_flag(args: &mut Args, flag: &str, range: &mut DateRange) -> Result<(), String> {
+    match flag {
+        "--from" => range.from = Some(parse_date(&args.value(flag)?)?),
+        "--to" => range.to = Some(parse_date(&args.value(flag)?)?),
+        "--offset" => range.offset = parse_offset(&args.value(flag)?)?,
+        other => return Err(format!("unknown option `{}`", other)),
+    }
+    Ok(())
+}
+
+fn parse_date(value: &str) -> Result<Date, String> {
+    let format = format_description::parse("[year]-[month]-[day]").map_err(|e| e.to_string())?;
+    Date::parse(value, &format)
+        .map_err(|_| format!("invalid date `{}`, expected YYYY-MM-DD", value))
+}
+
+fn parse_offset(value: &str) -> Result<UtcOffset, String> {
+    let format = format_description::parse("[offset_hour sign:mandatory]:[offset_minute]")
+        .map_err(|e| e.to_string())?;
+    UtcOffset::parse(value, &format)
+        .map_err(|_| format!("invalid offset `{}`, expected +HH:MM", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, offset};
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn test_parse_args_defaults_to_run() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.db_path, None);
+        assert_eq!(
+            cli.command,
+            Command::Run(RunOptions {
+                interval: Duration::from_secs(60),
+                http_port: None,
+                live_stream_path: None,
+                recording_path: None,
+                private_apps: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli = parse(&[
+            "--db",
+            "/tmp/test.sqlite",
+            "run",
+            "--http-port",
+            "7070",
+            "--private-app",
+            "1Password",
+            "--private-app",
+            "mybank.com",
+        ])
+        .unwrap();
+        assert_eq!(cli.db_path, Some("/tmp/test.sqlite".to_string()));
+        assert!(matches!(
+            cli.command,
+            Command::Run(RunOptions {
+                http_port: Some(7070),
+                ref private_apps,
+                ..
+            }) if private_apps == &["1Password", "mybank.com"]
+        ));
+
+        let cli = parse(&[
+            "report",
+            "--from",
+            "2025-01-13",
+            "--offset",
+            "-05:00",
+            "--weekly",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Report {
+                range: DateRange {
+                    from: Some(date!(2025 - 01 - 13)),
+                    to: None,
+                    offset: offset!(-5),
+                },
+                weekly: true,
+                json: false,
+            }
+        );
+
+        assert_eq!(parse(&["db", "vacuum"]).unwrap().command, Command::DbVacuum);
+        assert_eq!(
+            parse(&["tags", "--json"]).unwrap().command,
+            Command::Tags { json: true }
+        );
+        assert!(parse(&["db"]).is_err());
+        assert!(parse(&["report", "--from", "yesterday"]).is_err());
+        assert!(parse(&["apps", "--verbose"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_run_interval() {
+        assert!(matches!(
+            parse(&["run", "--interval", "30"]).unwrap().command,
+            Command::Run(RunOptions { interval, .. }) if interval == Duration::from_secs(30)
+        ));
+        for interval in ["0", "-1", "soon"] {
+            assert_eq!(
+                parse(&["run", "--interval", interval]),
+                Err(format!("invalid interval `{}`", interval))
+            );
+        }
+    }
+}
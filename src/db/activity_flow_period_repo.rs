@@ -0,0 +1,119 @@
+use time::OffsetDateTime;
+
+use super::models::ActivityFlowPeriod;
+use crate::error::{Result, ResultExt};
+
+#[derive(Clone)]
+pub struct ActivityFlowPeriodRepo {
+    pool: sqlx::SqlitePool,
+}
+
+impl ActivityFlowPeriodRepo {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        ActivityFlowPeriodRepo { pool }
+    }
+
+    pub async fn save_activity_flow_period(
+        &self,
+        activity_flow_period: &ActivityFlowPeriod,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"INSERT INTO activity_flow_period (start_time, end_time, score, app_switches,
+                active_time, inactive_time, creating_time, consuming_time)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+            activity_flow_period.start_time,
+            activity_flow_period.end_time,
+            activity_flow_period.score,
+            activity_flow_period.app_switches,
+            activity_flow_period.active_time,
+            activity_flow_period.inactive_time,
+            activity_flow_period.creating_time,
+            activity_flow_period.consuming_time,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("save activity flow period")
+    }
+
+    pub async fn get_last_activity_flow_period(&self) -> Result<ActivityFlowPeriod> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            ActivityFlowPeriod,
+            r#"SELECT id, start_time as "start_time: _", end_time as "end_time: _", score,
+                app_switches, active_time, inactive_time, creating_time, consuming_time,
+                created_at as "created_at: _"
+            FROM activity_flow_period ORDER BY end_time DESC LIMIT 1"#
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .not_found("activity flow period", "")
+    }
+
+    pub async fn get_activity_flow_periods_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<ActivityFlowPeriod>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            ActivityFlowPeriod,
+            r#"SELECT id, start_time as "start_time: _", end_time as "end_time: _", score,
+                app_switches, active_time, inactive_time, creating_time, consuming_time,
+                created_at as "created_at: _"
+            FROM activity_flow_period
+            WHERE start_time >= ? AND start_time < ?
+            ORDER BY start_time"#,
+            start_time,
+            end_time,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get activity flow periods between")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::db::db_manager;
+
+    #[tokio::test]
+    async fn test_activity_flow_period_repo() {
+        let pool = db_manager::create_test_db().await;
+        let activity_flow_period_repo = ActivityFlowPeriodRepo::new(pool);
+        let start_time = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+        for i in 0..3 {
+            activity_flow_period_repo
+                .save_activity_flow_period(&ActivityFlowPeriod {
+                    id: None,
+                    start_time: start_time + Duration::from_secs(600 * i),
+                    end_time: start_time + Duration::from_secs(600 * (i + 1)),
+                    score: i as f64,
+                    app_switches: 0,
+                    active_time: 600,
+                    inactive_time: 0,
+                    creating_time: 600,
+                    consuming_time: 0,
+                    created_at: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let last = activity_flow_period_repo
+            .get_last_activity_flow_period()
+            .await
+            .unwrap();
+        assert_eq!(last.score, 2.0);
+        assert_eq!(last.end_time, start_time + Duration::from_secs(1800));
+
+        let periods = activity_flow_period_repo
+            .get_activity_flow_periods_between(start_time, start_time + Duration::from_secs(1200))
+            .await
+            .unwrap();
+        assert_eq!(periods.len(), 2);
+    }
+}
@@ -0,0 +1,100 @@
+use time::{OffsetDateTime, UtcOffset};
+
+use super::models::ActivityGap;
+use crate::error::{Result, ResultExt};
+
+#[derive(Clone)]
+pub struct ActivityGapRepo {
+    pool: sqlx::SqlitePool,
+}
+
+impl ActivityGapRepo {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        ActivityGapRepo { pool }
+    }
+
+    pub async fn save_activity_gap(
+        &self,
+        activity_gap: &ActivityGap,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            "INSERT INTO activity_gap (start_time, end_time, reason) VALUES (?, ?, ?)",
+            activity_gap.start_time,
+            activity_gap.end_time,
+            activity_gap.reason as _,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("save activity gap")
+    }
+
+    /// Gaps overlapping the range, in order.
+    pub async fn get_activity_gaps_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<ActivityGap>> {
+        // timestamps are stored and compared as UTC strings
+        let (start_time, end_time) = (
+            start_time.to_offset(UtcOffset::UTC),
+            end_time.to_offset(UtcOffset::UTC),
+        );
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            ActivityGap,
+            r#"SELECT id, start_time as "start_time: _", end_time as "end_time: _",
+            reason as "reason: _", created_at as "created_at: _"
+            FROM activity_gap WHERE start_time < ? AND end_time > ? ORDER BY start_time"#,
+            end_time,
+            start_time,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get activity gaps between")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::db::{db_manager, models::ActivityGapReason};
+
+    #[tokio::test]
+    async fn test_activity_gap_repo() {
+        let pool = db_manager::create_test_db().await;
+        let activity_gap_repo = ActivityGapRepo::new(pool);
+        let now = OffsetDateTime::now_utc();
+        activity_gap_repo
+            .save_activity_gap(&ActivityGap::new(
+                now - Duration::from_secs(3600),
+                now - Duration::from_secs(600),
+                ActivityGapReason::Suspend,
+            ))
+            .await
+            .unwrap();
+        activity_gap_repo
+            .save_activity_gap(&ActivityGap::new(
+                now - Duration::from_secs(300),
+                now - Duration::from_secs(120),
+                ActivityGapReason::ServiceNotRunning,
+            ))
+            .await
+            .unwrap();
+
+        let activity_gaps = activity_gap_repo
+            .get_activity_gaps_between(now - Duration::from_secs(900), now)
+            .await
+            .unwrap();
+        assert_eq!(activity_gaps.len(), 2);
+        assert_eq!(activity_gaps[0].reason, ActivityGapReason::Suspend);
+        assert!(activity_gaps[1].created_at.is_some());
+        assert!(activity_gap_repo
+            .get_activity_gaps_between(now - Duration::from_secs(60), now)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
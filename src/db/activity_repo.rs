@@ -1,6 +1,9 @@
 // activity repo is responsible for all the database operations related to activities. Makes use of the db manager to get the pool and execute queries.
 
+use time::{OffsetDateTime, UtcOffset};
+
 use super::models::{Activity, ActivityType};
+use crate::error::{Result, ResultExt};
 #[derive(Clone)]
 pub struct ActivityRepo {
     pool: sqlx::SqlitePool,
@@ -14,7 +17,7 @@ impl ActivityRepo {
     pub async fn save_activity(
         &self,
         activity: &Activity,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let app_id = if activity.app_id.is_none() {
             // if no app_id, try to get the last known app_id, defaulting to None if no previous activity exists
             match self.get_last_activity_by_type(ActivityType::Window).await {
@@ -27,52 +30,118 @@ impl ActivityRepo {
 
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
-            r#"INSERT INTO activity (activity_type, app_id, app_window_title, timestamp, platform) 
-            VALUES (?, ?, ?, ?, ?)"#,
+            r#"INSERT INTO activity (activity_type, app_id, app_window_title, tag_rule_ids, timestamp, platform) 
+            VALUES (?, ?, ?, ?, ?, ?)"#,
             activity.activity_type as _,
             app_id,
             activity.app_window_title,
+            activity.tag_rule_ids,
             activity.timestamp,
             activity.platform as _,
         )
         .execute(&mut *conn)
         .await
+        .context("save activity")
+    }
+
+    /**
+     * Saves a batch of activities in a single transaction.
+     * Activities without an app_id use the app_id of the closest preceding window activity in the batch.
+     * The last stored window activity is only looked up if an activity needs an app_id before any window activity in the batch provides one.
+     */
+    pub async fn save_activities(&self, activities: &[Activity]) -> Result<()> {
+        if activities.is_empty() {
+            return Ok(());
+        }
+
+        let needs_last_window = activities
+            .iter()
+            .take_while(|a| !(a.activity_type == ActivityType::Window && a.app_id.is_some()))
+            .any(|a| a.app_id.is_none());
+        let mut last_app_id = if needs_last_window {
+            match self.get_last_activity_by_type(ActivityType::Window).await {
+                Ok(last_activity) => last_activity.app_id,
+                Err(_) => None,
+            }
+        } else {
+            None
+        };
+
+        let mut tx = self.pool.begin().await?;
+        for activity in activities {
+            let app_id = activity.app_id.clone().or_else(|| last_app_id.clone());
+            if activity.activity_type == ActivityType::Window {
+                last_app_id = app_id.clone();
+            }
+            sqlx::query!(
+                r#"INSERT INTO activity (activity_type, app_id, app_window_title, tag_rule_ids, timestamp, platform)
+                VALUES (?, ?, ?, ?, ?, ?)"#,
+                activity.activity_type as _,
+                app_id,
+                activity.app_window_title,
+                activity.tag_rule_ids,
+                activity.timestamp,
+                activity.platform as _,
+            )
+            .execute(&mut *tx)
+            .await
+            .context("save activities")?;
+        }
+        tx.commit().await.context("save activities")
     }
 
-    pub async fn get_activity(&self, id: i32) -> Result<Activity, sqlx::Error> {
+    pub async fn get_activity(&self, id: i32) -> Result<Activity> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             Activity,
             r#"SELECT id, created_at, timestamp, activity_type as "activity_type: _", 
-            app_id, app_window_title, platform as "platform: _" 
+            app_id, app_window_title, tag_rule_ids, platform as "platform: _" 
             FROM activity WHERE id = ?"#,
             id
         )
         .fetch_one(&mut *conn)
         .await
+        .not_found("activity", id)
     }
 
-    pub async fn get_last_activity_by_type(
-        &self,
-        activity_type: ActivityType,
-    ) -> Result<Activity, sqlx::Error> {
+    pub async fn get_last_activity_by_type(&self, activity_type: ActivityType) -> Result<Activity> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             Activity,
             r#"SELECT id, created_at, timestamp, 
                    activity_type as "activity_type: _",
-                   app_id, app_window_title, platform as "platform: _" 
+                   app_id, app_window_title, tag_rule_ids, platform as "platform: _" 
                    FROM activity WHERE activity_type = ? ORDER BY timestamp DESC LIMIT 1"#,
             activity_type as _
         )
         .fetch_one(&mut *conn)
         .await
+        .not_found("activity", "")
     }
 
-    // get the activities since the last activity state. If none, return an empty vector.
-    pub(crate) async fn get_activities_since_last_activity_state(
+    /// The window that was focused at `timestamp`, i.e. the last window activity before it.
+    pub async fn get_last_window_activity_before(
         &self,
-    ) -> Result<Vec<Activity>, sqlx::Error> {
+        timestamp: OffsetDateTime,
+    ) -> Result<Option<Activity>> {
+        let mut conn = self.pool.acquire().await?;
+        let timestamp = timestamp.to_offset(UtcOffset::UTC);
+        sqlx::query_as!(
+            Activity,
+            r#"SELECT id, created_at, timestamp,
+                   activity_type as "activity_type: _",
+                   app_id, app_window_title, tag_rule_ids, platform as "platform: _"
+                   FROM activity WHERE activity_type = 'WINDOW' AND timestamp < ?
+                   ORDER BY timestamp DESC LIMIT 1"#,
+            timestamp
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .context("get last window activity before")
+    }
+
+    // get the activities since the last activity state. If none, return an empty vector.
+    pub(crate) async fn get_activities_since_last_activity_state(&self) -> Result<Vec<Activity>> {
         let mut conn = self.pool.acquire().await?;
 
         sqlx::query_as!(
@@ -80,7 +149,7 @@ impl ActivityRepo {
             r#"
             SELECT id, created_at, timestamp, 
                 activity_type as "activity_type: _",
-                app_id, app_window_title, 
+                app_id, app_window_title, tag_rule_ids,
                 platform as "platform: _"
             FROM activity a
                 WHERE a.timestamp > (
@@ -93,13 +162,29 @@ impl ActivityRepo {
         )
         .fetch_all(&mut *conn)
         .await
+        .context("get activities since last activity state")
+    }
+
+    /// Clears the window titles of the window activities before `before`. Returns the number cleared.
+    pub async fn clear_window_titles_before(&self, before: OffsetDateTime) -> Result<u64> {
+        let before = before.to_offset(UtcOffset::UTC);
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"UPDATE activity SET app_window_title = NULL
+            WHERE app_window_title IS NOT NULL AND timestamp < ?"#,
+            before,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("clear activity window titles")
+        .map(|result| result.rows_affected())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::db_manager;
+    use crate::db::{app_repo::AppRepo, db_manager, models::App};
     #[tokio::test]
     async fn test_activity_repo() {
         let pool = db_manager::create_test_db().await;
@@ -107,4 +192,30 @@ mod tests {
         let activity = Activity::__create_test_window(None, None);
         activity_repo.save_activity(&activity).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_save_activities_fills_app_id_from_window() {
+        let pool = db_manager::create_test_db().await;
+        let activity_repo = ActivityRepo::new(pool.clone());
+        let app = App::__create_test_app();
+        AppRepo::new(pool).save_app(&app).await.unwrap();
+        let app_id = app.id.unwrap();
+        let activities = vec![
+            Activity::__create_test_window(None, Some(app_id.clone())),
+            Activity::create_keyboard_activity(
+                &os_monitor::KeyboardEvent {},
+                time::OffsetDateTime::now_utc(),
+            ),
+            Activity::create_mouse_activity(
+                &os_monitor::MouseEvent {},
+                time::OffsetDateTime::now_utc(),
+            ),
+        ];
+        activity_repo.save_activities(&activities).await.unwrap();
+
+        let keyboard = activity_repo.get_activity(2).await.unwrap();
+        let mouse = activity_repo.get_activity(3).await.unwrap();
+        assert_eq!(keyboard.app_id, Some(app_id.clone()));
+        assert_eq!(mouse.app_id, Some(app_id));
+    }
 }
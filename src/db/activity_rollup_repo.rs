@@ -0,0 +1,86 @@
+use time::{OffsetDateTime, UtcOffset};
+
+use super::models::ActivityRollup;
+use crate::error::{Result, ResultExt};
+
+#[derive(Clone)]
+pub struct ActivityRollupRepo {
+    pool: sqlx::SqlitePool,
+}
+
+impl ActivityRollupRepo {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        ActivityRollupRepo { pool }
+    }
+
+    /**
+     * Counts the activities before `before` per minute, type and app into `activity_rollup` and
+     * deletes them, in one transaction. The last window activity is kept since it tells which window
+     * is still focused, but its title is cleared once it is before `before` too. Returns the number of
+     * activities deleted.
+     */
+    pub async fn roll_up_activities_before(&self, before: OffsetDateTime) -> Result<u64> {
+        // timestamps are stored and compared as UTC strings
+        let before = before.to_offset(UtcOffset::UTC);
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"INSERT INTO activity_rollup (minute, activity_type, app_id, event_count)
+            SELECT strftime('%Y-%m-%dT%H:%M:00Z', timestamp), activity_type, app_id, COUNT(*)
+            FROM activity
+            WHERE timestamp < ? AND id NOT IN (
+                SELECT id FROM activity WHERE activity_type = 'WINDOW'
+                ORDER BY timestamp DESC LIMIT 1
+            )
+            GROUP BY 1, 2, 3"#,
+            before,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("roll up activities")?;
+        let deleted = sqlx::query!(
+            r#"DELETE FROM activity
+            WHERE timestamp < ? AND id NOT IN (
+                SELECT id FROM activity WHERE activity_type = 'WINDOW'
+                ORDER BY timestamp DESC LIMIT 1
+            )"#,
+            before,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete rolled up activities")?
+        .rows_affected();
+        sqlx::query!(
+            r#"UPDATE activity SET app_window_title = NULL
+            WHERE timestamp < ? AND app_window_title IS NOT NULL"#,
+            before,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("clear rolled up window title")?;
+        tx.commit().await.context("roll up activities")?;
+        Ok(deleted)
+    }
+
+    /// Rollups of the minutes in the range, in order.
+    pub async fn get_activity_rollups_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<ActivityRollup>> {
+        let (start_time, end_time) = (
+            start_time.to_offset(UtcOffset::UTC),
+            end_time.to_offset(UtcOffset::UTC),
+        );
+        sqlx::query_as!(
+            ActivityRollup,
+            r#"SELECT id, minute as "minute: _", activity_type as "activity_type: _", app_id,
+            event_count, created_at as "created_at: _"
+            FROM activity_rollup WHERE minute >= ? AND minute < ? ORDER BY minute, id"#,
+            start_time,
+            end_time,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("get activity rollups between")
+    }
+}
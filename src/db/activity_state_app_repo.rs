@@ -0,0 +1,100 @@
+use time::{OffsetDateTime, UtcOffset};
+
+use super::models::{ActivityStateApp, ActivityStateAppDetail};
+use crate::error::{Result, ResultExt};
+
+#[derive(Clone)]
+pub struct ActivityStateAppRepo {
+    pool: sqlx::SqlitePool,
+}
+
+impl ActivityStateAppRepo {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        ActivityStateAppRepo { pool }
+    }
+
+    pub async fn save_activity_state_apps(
+        &self,
+        activity_state_apps: &[ActivityStateApp],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for activity_state_app in activity_state_apps {
+            sqlx::query!(
+                r#"INSERT INTO activity_state_app (activity_state_id, app_id, window_title, seconds)
+                VALUES (?, ?, ?, ?)"#,
+                activity_state_app.activity_state_id,
+                activity_state_app.app_id,
+                activity_state_app.window_title,
+                activity_state_app.seconds,
+            )
+            .execute(&mut *tx)
+            .await
+            .context("save activity state apps")?;
+        }
+        tx.commit().await.context("save activity state apps")
+    }
+
+    pub async fn get_activity_state_apps(
+        &self,
+        activity_state_id: i64,
+    ) -> Result<Vec<ActivityStateApp>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            ActivityStateApp,
+            r#"SELECT activity_state_id, app_id, window_title, seconds
+            FROM activity_state_app WHERE activity_state_id = ?
+            ORDER BY seconds DESC"#,
+            activity_state_id
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get activity state apps")
+    }
+
+    /**
+     * Returns the focused seconds per app for each activity state starting within the given range.
+     */
+    pub async fn get_activity_state_app_details_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<ActivityStateAppDetail>> {
+        let mut conn = self.pool.acquire().await?;
+        let (start_time, end_time) = (
+            start_time.to_offset(UtcOffset::UTC),
+            end_time.to_offset(UtcOffset::UTC),
+        );
+        sqlx::query_as!(
+            ActivityStateAppDetail,
+            r#"SELECT activity_state_app.activity_state_id, app.id as "app_id!", app.name as app_name,
+                app.app_external_id, SUM(activity_state_app.seconds) as "seconds!: f64"
+            FROM activity_state_app
+            JOIN activity_state ON activity_state.id = activity_state_app.activity_state_id
+            JOIN app ON app.id = activity_state_app.app_id
+            WHERE activity_state.start_time >= ? AND activity_state.start_time < ?
+            GROUP BY activity_state_app.activity_state_id, app.id"#,
+            start_time,
+            end_time,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get activity state app details between")
+    }
+
+    /// Clears the window titles of the activity states that ended before `before`.
+    pub async fn clear_window_titles_before(&self, before: OffsetDateTime) -> Result<u64> {
+        let before = before.to_offset(UtcOffset::UTC);
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"UPDATE activity_state_app SET window_title = NULL
+            WHERE window_title IS NOT NULL AND activity_state_id IN (
+                SELECT id FROM activity_state WHERE end_time < ?
+            )"#,
+            before,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("clear activity state app window titles")
+        .map(|result| result.rows_affected())
+    }
+}
@@ -1,7 +1,8 @@
-use time::OffsetDateTime;
+use time::{OffsetDateTime, UtcOffset};
 
 use crate::{
     db::models::{ActivityState, ActivityStateType},
+    error::{Result, ResultExt},
     services::activity_state_service::ActivityPeriod,
 };
 
@@ -18,24 +19,26 @@ impl ActivityStateRepo {
     pub async fn save_activity_state(
         &self,
         activity_state: &ActivityState,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
-            r#"INSERT INTO activity_state (state, app_switches, start_time, end_time) 
-        VALUES (?, ?, ?, ?)"#,
+            r#"INSERT INTO activity_state (state, app_switches, active_seconds, start_time, end_time) 
+        VALUES (?, ?, ?, ?, ?)"#,
             activity_state.state as _, // Cast enum to database type
             activity_state.app_switches,
+            activity_state.active_seconds,
             activity_state.start_time,
             activity_state.end_time,
         )
         .execute(&mut *conn)
         .await
+        .context("save activity state")
     }
 
     pub async fn get_activity_states_for_activity_period(
         &self,
         activity_period: &ActivityPeriod,
-    ) -> Result<Vec<ActivityState>, sqlx::Error> {
+    ) -> Result<Vec<ActivityState>> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             ActivityState,
@@ -45,9 +48,10 @@ impl ActivityStateRepo {
         )
         .fetch_all(&mut *conn)
         .await
+        .context("get activity states for activity period")
     }
 
-    pub async fn get_activity_state(&self, id: i32) -> Result<ActivityState, sqlx::Error> {
+    pub async fn get_activity_state(&self, id: i32) -> Result<ActivityState> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             ActivityState,
@@ -56,9 +60,10 @@ impl ActivityStateRepo {
         )
         .fetch_one(&mut *conn)
         .await
+        .not_found("activity state", id)
     }
 
-    pub async fn get_last_activity_state(&self) -> Result<ActivityState, sqlx::Error> {
+    pub async fn get_last_activity_state(&self) -> Result<ActivityState> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             ActivityState,
@@ -66,13 +71,14 @@ impl ActivityStateRepo {
         )
         .fetch_one(&mut *conn)
         .await
+        .not_found("activity state", "")
     }
 
     pub async fn get_activity_states_starting_between(
         &self,
         start_time: OffsetDateTime,
         end_time: OffsetDateTime,
-    ) -> Result<Vec<ActivityState>, sqlx::Error> {
+    ) -> Result<Vec<ActivityState>> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             ActivityState,
@@ -82,14 +88,15 @@ impl ActivityStateRepo {
         )
         .fetch_all(&mut *conn)
         .await
+        .context("get activity states starting between")
     }
 
     pub(crate) async fn create_idle_activity_state(
         &self,
         activity_period: &ActivityPeriod,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let (start_time, end_time) = (activity_period.start_time, activity_period.end_time);
-        let mut conn = self.pool.acquire().await.unwrap();
+        let mut conn = self.pool.acquire().await?;
 
         sqlx::query!(
             r#"INSERT INTO activity_state (state, app_switches, start_time, end_time)
@@ -101,25 +108,113 @@ impl ActivityStateRepo {
         )
         .execute(&mut *conn)
         .await
+        .context("create idle activity state")
     }
 
+    /// `active_seconds` is only set for partially active states.
     pub(crate) async fn create_active_activity_state(
         &self,
         app_switches: i64,
+        active_seconds: Option<i64>,
         activity_period: &ActivityPeriod,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let (start_time, end_time) = (activity_period.start_time, activity_period.end_time);
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
-            r#"INSERT INTO activity_state (state, app_switches, start_time, end_time) 
-        VALUES (?, ?, ?, ?)"#,
+            r#"INSERT INTO activity_state (state, app_switches, active_seconds, start_time, end_time) 
+        VALUES (?, ?, ?, ?, ?)"#,
             ActivityStateType::Active as _,
             app_switches,
+            active_seconds,
+            start_time,
+            end_time,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("create active activity state")
+    }
+
+    pub(crate) async fn create_passive_activity_state(
+        &self,
+        app_switches: i64,
+        activity_period: &ActivityPeriod,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let (start_time, end_time) = (activity_period.start_time, activity_period.end_time);
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"INSERT INTO activity_state (state, app_switches, start_time, end_time)
+        VALUES (?, ?, ?, ?)"#,
+            ActivityStateType::Passive as _,
+            app_switches,
             start_time,
             end_time,
         )
         .execute(&mut *conn)
         .await
+        .context("create passive activity state")
+    }
+
+    pub(crate) async fn create_paused_activity_state(
+        &self,
+        activity_period: &ActivityPeriod,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let (start_time, end_time) = (activity_period.start_time, activity_period.end_time);
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"INSERT INTO activity_state (state, app_switches, start_time, end_time)
+        VALUES (?, ?, ?, ?)"#,
+            ActivityStateType::Paused as _,
+            0,
+            start_time,
+            end_time,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("create paused activity state")
+    }
+
+    /**
+     * Deletes the activity states that ended before `before`, with their apps and tags. The last
+     * activity state is kept, since the next one starts where it ended. Returns the number of
+     * activity states deleted.
+     */
+    pub async fn delete_activity_states_ending_before(
+        &self,
+        before: OffsetDateTime,
+    ) -> Result<u64> {
+        let before = before.to_offset(UtcOffset::UTC);
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"DELETE FROM activity_state_tag WHERE activity_state_id IN (
+                SELECT id FROM activity_state
+                WHERE end_time < ? AND id < (SELECT MAX(id) FROM activity_state)
+            )"#,
+            before,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete activity state tags")?;
+        sqlx::query!(
+            r#"DELETE FROM activity_state_app WHERE activity_state_id IN (
+                SELECT id FROM activity_state
+                WHERE end_time < ? AND id < (SELECT MAX(id) FROM activity_state)
+            )"#,
+            before,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete activity state apps")?;
+        let deleted = sqlx::query!(
+            r#"DELETE FROM activity_state
+            WHERE end_time < ? AND id < (SELECT MAX(id) FROM activity_state)"#,
+            before,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete activity states")?
+        .rows_affected();
+        tx.commit().await.context("delete activity states")?;
+        Ok(deleted)
     }
 }
 
@@ -173,6 +268,7 @@ mod tests {
         activity_state_repo
             .create_active_activity_state(
                 5,
+                None,
                 &ActivityPeriod {
                     start_time: OffsetDateTime::now_utc(),
                     end_time: OffsetDateTime::now_utc() + Duration::from_secs(120),
@@ -1,4 +1,5 @@
 use super::models::{App, AppTag};
+use crate::error::{Result, ResultExt};
 #[derive(Clone)]
 pub struct AppRepo {
     pool: sqlx::SqlitePool,
@@ -9,10 +10,7 @@ impl AppRepo {
         AppRepo { pool }
     }
 
-    pub async fn save_app(
-        &self,
-        app: &App,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    pub async fn save_app(&self, app: &App) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
             r#"INSERT INTO app (id, name, app_external_id, platform, is_browser, is_default) 
@@ -26,37 +24,80 @@ impl AppRepo {
         )
         .execute(&mut *conn)
         .await
+        .context("save app")
     }
 
-    pub async fn get_app_by_external_id(&self, external_id: &str) -> Result<App, sqlx::Error> {
+    pub async fn get_app_by_external_id(&self, external_id: &str) -> Result<App> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             App,
-            r#"SELECT id, name, app_external_id, platform, is_browser, is_default, is_blocked, created_at, updated_at
+            r#"SELECT id, name, app_external_id, platform, is_browser, is_default, is_blocked, is_passive, created_at, updated_at
             FROM app WHERE app_external_id = ?"#,
             external_id
         )
         .fetch_one(&mut *conn)
         .await
+        .not_found("app", external_id)
     }
 
-    pub async fn get_app_by_name_or_url(&self, name: &str, url: &str) -> Result<App, sqlx::Error> {
+    pub async fn get_app_by_name_or_url(&self, name: &str, url: &str) -> Result<App> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             App,
-            r#"SELECT id, name, app_external_id, platform, is_browser, is_default, is_blocked, created_at, updated_at
+            r#"SELECT id, name, app_external_id, platform, is_browser, is_default, is_blocked, is_passive, created_at, updated_at
             FROM app WHERE name = ? OR name = ?"#,
             name,
             url
         )
         .fetch_one(&mut *conn)
         .await
+        .not_found("app", name)
     }
 
-    pub async fn get_app_tag_by_app_ids(
+    pub async fn get_all_apps(&self) -> Result<Vec<App>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            App,
+            r#"SELECT id, name, app_external_id, platform, is_browser, is_default, is_blocked, is_passive, created_at, updated_at
+            FROM app ORDER BY app_external_id"#
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get all apps")
+    }
+
+    pub async fn set_app_passive(
         &self,
-        ids: &Vec<String>,
-    ) -> Result<Vec<AppTag>, sqlx::Error> {
+        id: &str,
+        is_passive: bool,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            "UPDATE app SET is_passive = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            is_passive,
+            id
+        )
+        .execute(&mut *conn)
+        .await
+        .context("set app passive")
+    }
+
+    /// Ids of the apps that are passive themselves or have a passive tag.
+    pub async fn get_passive_app_ids(&self) -> Result<Vec<String>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_scalar!(
+            r#"SELECT id as "id!" FROM app WHERE is_passive = TRUE
+            UNION
+            SELECT app_tag.app_id FROM app_tag
+            JOIN tag ON tag.id = app_tag.tag_id
+            WHERE tag.is_passive = TRUE"#
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get passive app ids")
+    }
+
+    pub async fn get_app_tag_by_app_ids(&self, ids: &Vec<String>) -> Result<Vec<AppTag>> {
         if ids.is_empty() {
             return Ok(Vec::new());
         }
@@ -64,13 +105,12 @@ impl AppRepo {
         let mut conn = self.pool.acquire().await?;
 
         // Create the parameterized query with the correct number of placeholders
-        let placeholders = std::iter::repeat("?")
-            .take(ids.len())
+        let placeholders = std::iter::repeat_n("?", ids.len())
             .collect::<Vec<_>>()
             .join(",");
 
         let query = format!(
-            r#"SELECT id, app_id, tag_id, weight, created_at, updated_at
+            r#"SELECT id, app_id, tag_id, weight, is_default, created_at, updated_at
             FROM app_tag WHERE app_id IN ({})"#,
             placeholders
         );
@@ -83,10 +123,13 @@ impl AppRepo {
             query = query.bind(id);
         }
 
-        query.fetch_all(&mut *conn).await
+        query
+            .fetch_all(&mut *conn)
+            .await
+            .context("get app tag by app ids")
     }
 
-    pub async fn get_apps_by_ids(&self, ids: &Vec<String>) -> Result<Vec<App>, sqlx::Error> {
+    pub async fn get_apps_by_ids(&self, ids: &Vec<String>) -> Result<Vec<App>> {
         if ids.is_empty() {
             return Ok(Vec::new());
         }
@@ -94,13 +137,12 @@ impl AppRepo {
         let mut conn = self.pool.acquire().await?;
 
         // Create the parameterized query with the correct number of placeholders
-        let placeholders = std::iter::repeat("?")
-            .take(ids.len())
+        let placeholders = std::iter::repeat_n("?", ids.len())
             .collect::<Vec<_>>()
             .join(",");
 
         let query = format!(
-            r#"SELECT id, name, app_external_id, platform, is_browser, is_default, is_blocked, created_at, updated_at
+            r#"SELECT id, name, app_external_id, platform, is_browser, is_default, is_blocked, is_passive, created_at, updated_at
             FROM app WHERE id IN ({})"#,
             placeholders
         );
@@ -113,19 +155,25 @@ impl AppRepo {
             query = query.bind(id);
         }
 
-        query.fetch_all(&mut *conn).await
+        query.fetch_all(&mut *conn).await.context("get apps by ids")
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::db_manager;
+    use crate::{db::db_manager, error::Error};
     #[tokio::test]
     async fn test_app_repo() {
         let pool = db_manager::create_test_db().await;
         let app_repo = AppRepo::new(pool);
         let app = App::__create_test_app();
         app_repo.save_app(&app).await.unwrap();
+
+        let missing = app_repo.get_app_by_external_id("missing.app").await;
+        assert!(matches!(
+            missing,
+            Err(Error::NotFound { entity: "app", ref id }) if id == "missing.app"
+        ));
     }
 }
@@ -1,4 +1,7 @@
+use time::{OffsetDateTime, UtcOffset};
+
 use super::models::BlockedActivity;
+use crate::error::{Result, ResultExt};
 
 #[derive(Clone)]
 pub struct BlockedActivityRepo {
@@ -13,7 +16,7 @@ impl BlockedActivityRepo {
     pub async fn save_blocked_activity(
         &self,
         blocked_activity: &BlockedActivity,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
             r#"INSERT INTO blocked_activity (id, external_app_id, created_at, updated_at) 
@@ -25,9 +28,10 @@ impl BlockedActivityRepo {
         )
         .execute(&mut *conn)
         .await
+        .context("save blocked activity")
     }
 
-    pub async fn get_all_blocked_activities(&self) -> Result<Vec<BlockedActivity>, sqlx::Error> {
+    pub async fn get_all_blocked_activities(&self) -> Result<Vec<BlockedActivity>> {
         sqlx::query_as!(
             BlockedActivity,
             r#"SELECT id, external_app_id, created_at, updated_at 
@@ -36,6 +40,17 @@ impl BlockedActivityRepo {
         )
         .fetch_all(&self.pool)
         .await
+        .context("get all blocked activities")
+    }
+
+    pub async fn delete_blocked_activities_before(&self, before: OffsetDateTime) -> Result<u64> {
+        let before = before.to_offset(UtcOffset::UTC);
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!("DELETE FROM blocked_activity WHERE created_at < ?", before)
+            .execute(&mut *conn)
+            .await
+            .context("delete blocked activities")
+            .map(|result| result.rows_affected())
     }
 }
 
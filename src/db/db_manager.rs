@@ -1,4 +1,6 @@
-use sqlx::{sqlite::SqlitePool, Pool, Sqlite};
+use sqlx::{sqlite::SqlitePool, Pool, Row, Sqlite};
+
+use crate::error::{Error, Result, ResultExt};
 
 pub struct DbManager {
     pub pool: Pool<Sqlite>,
@@ -42,20 +44,22 @@ pub async fn create_test_db() -> SqlitePool {
     pool
 }
 
-async fn set_wal_mode(pool: &sqlx::SqlitePool) -> Result<(), sqlx::Error> {
+async fn set_wal_mode(pool: &sqlx::SqlitePool) -> Result<()> {
     sqlx::query("PRAGMA journal_mode=WAL;")
         .execute(pool)
-        .await?;
+        .await
+        .context("enable WAL mode")?;
     Ok(())
 }
 
 impl DbManager {
-    pub async fn new(db_path: &str) -> Result<Self, sqlx::Error> {
+    pub async fn new(db_path: &str) -> Result<Self> {
         let database_url = format!("sqlite:{db_path}");
 
         let path = std::path::Path::new(db_path);
         if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).map_err(|e| sqlx::Error::Configuration(Box::new(e)))?;
+            std::fs::create_dir_all(parent)
+                .map_err(|e| Error::Config(format!("cannot create {}: {}", parent.display(), e)))?;
         }
         log::trace!("database_url: {}", database_url);
 
@@ -64,6 +68,7 @@ impl DbManager {
 
         match std::fs::OpenOptions::new()
             .create(true)
+            .truncate(false)
             .write(true)
             .open(db_path)
         {
@@ -71,13 +76,54 @@ impl DbManager {
             Err(e) => log::error!("Error creating/opening database file: {}", e),
         }
 
-        let pool = SqlitePool::connect(&database_url).await?;
+        let pool = SqlitePool::connect(&database_url)
+            .await
+            .context("open database")?;
 
         set_wal_mode(&pool).await?;
         sqlx::migrate!().run(&pool).await?;
 
         Ok(Self { pool })
     }
+
+    /**
+     * Runs SQLite's integrity and foreign key checks. Returns the problems found, or an empty
+     * vector if the database is healthy.
+     */
+    pub async fn check(&self) -> Result<Vec<String>> {
+        let mut problems = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+            .fetch_all(&self.pool)
+            .await
+            .context("check database integrity")?
+            .into_iter()
+            .filter(|result| result != "ok")
+            .collect::<Vec<_>>();
+
+        let foreign_key_violations = sqlx::query("PRAGMA foreign_key_check")
+            .fetch_all(&self.pool)
+            .await
+            .context("check foreign keys")?;
+        for violation in foreign_key_violations {
+            let table: String = violation.try_get(0)?;
+            let rowid: Option<i64> = violation.try_get(1)?;
+            let parent: String = violation.try_get(2)?;
+            problems.push(format!(
+                "foreign key violation in {} (rowid {}) referencing {}",
+                table,
+                rowid.map_or("none".to_string(), |rowid| rowid.to_string()),
+                parent
+            ));
+        }
+        Ok(problems)
+    }
+
+    pub async fn vacuum(&self) -> Result<()> {
+        sqlx::query("VACUUM")
+            .execute(&self.pool)
+            .await
+            .context("vacuum database")?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -94,6 +140,15 @@ mod tests {
         assert_eq!(result.unwrap(), 1);
     }
 
+    #[tokio::test]
+    async fn test_check() {
+        let db_manager = DbManager {
+            pool: create_test_db().await,
+        };
+        assert!(db_manager.check().await.unwrap().is_empty());
+        db_manager.vacuum().await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_migrations() {
         let _ = create_test_db().await;
@@ -0,0 +1,90 @@
+use time::OffsetDateTime;
+
+use super::models::IngestLag;
+use crate::error::{Result, ResultExt};
+
+#[derive(Clone)]
+pub struct IngestLagRepo {
+    pool: sqlx::SqlitePool,
+}
+
+impl IngestLagRepo {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        IngestLagRepo { pool }
+    }
+
+    pub async fn save_ingest_lag(
+        &self,
+        dropped_events: i64,
+        timestamp: OffsetDateTime,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            "INSERT INTO ingest_lag (dropped_events, timestamp) VALUES (?, ?)",
+            dropped_events,
+            timestamp,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("save ingest lag")
+    }
+
+    pub async fn get_ingest_lags_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<IngestLag>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            IngestLag,
+            r#"SELECT id, dropped_events, timestamp as "timestamp: _", created_at as "created_at: _"
+            FROM ingest_lag WHERE timestamp >= ? AND timestamp <= ? ORDER BY timestamp"#,
+            start_time,
+            end_time,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get ingest lags between")
+    }
+
+    pub async fn get_total_dropped_events(&self) -> Result<i64> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_scalar!(
+            r#"SELECT COALESCE(SUM(dropped_events), 0) as "total!: i64" FROM ingest_lag"#
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .context("get total dropped events")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::db::db_manager;
+
+    #[tokio::test]
+    async fn test_ingest_lag_repo() {
+        let pool = db_manager::create_test_db().await;
+        let ingest_lag_repo = IngestLagRepo::new(pool);
+        let now = OffsetDateTime::now_utc();
+        ingest_lag_repo
+            .save_ingest_lag(12, now - Duration::from_secs(60))
+            .await
+            .unwrap();
+        ingest_lag_repo.save_ingest_lag(3, now).await.unwrap();
+
+        let lags = ingest_lag_repo
+            .get_ingest_lags_between(now - Duration::from_secs(30), now)
+            .await
+            .unwrap();
+        assert_eq!(lags.len(), 1);
+        assert_eq!(lags[0].dropped_events, 3);
+        assert_eq!(
+            ingest_lag_repo.get_total_dropped_events().await.unwrap(),
+            15
+        );
+    }
+}
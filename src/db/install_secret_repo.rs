@@ -0,0 +1,21 @@
+use crate::error::{Result, ResultExt};
+
+#[derive(Clone)]
+pub struct InstallSecretRepo {
+    pool: sqlx::SqlitePool,
+}
+
+impl InstallSecretRepo {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        InstallSecretRepo { pool }
+    }
+
+    /// The random key generated for this install when the database was created.
+    pub async fn get_install_secret(&self) -> Result<Vec<u8>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_scalar!("SELECT secret FROM install_secret WHERE id = 1")
+            .fetch_one(&mut *conn)
+            .await
+            .not_found("install_secret", "1")
+    }
+}
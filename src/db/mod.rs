@@ -1,8 +1,15 @@
+pub mod activity_flow_period_repo;
+pub mod activity_gap_repo;
 pub mod activity_repo;
+pub mod activity_rollup_repo;
+pub mod activity_state_app_repo;
 pub mod activity_state_repo;
 pub mod app_repo;
 pub mod blocked_activity_repo;
 pub mod db_manager;
+pub mod ingest_lag_repo;
+pub mod install_secret_repo;
 pub mod models;
 pub mod tag_repo;
+pub mod tag_rule_repo;
 pub mod types;
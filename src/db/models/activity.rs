@@ -32,6 +32,8 @@ pub struct Activity {
     pub app_window_title: Option<String>,
     pub platform: Platform,
     pub app_id: Option<String>,
+    /// Comma separated ids of the tag rules a window matched when it was focused.
+    pub tag_rule_ids: Option<String>,
 }
 
 impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Activity {
@@ -44,6 +46,7 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Activity {
             app_window_title: row.try_get("app_window_title")?,
             platform: row.try_get("platform")?,
             app_id: row.try_get("app_id")?,
+            tag_rule_ids: row.try_get("tag_rule_ids")?,
         })
     }
 }
@@ -58,44 +61,37 @@ impl Activity {
     ) -> Self {
         Activity {
             id: None,
-            created_at: Some(OffsetDateTime::now_utc()),
+            created_at: Some(timestamp),
             timestamp: Some(timestamp),
             activity_type,
             app_window_title,
             platform,
             app_id,
+            tag_rule_ids: None,
         }
     }
 
-    pub fn create_window_activity(event: &WindowEvent, app_id: Option<String>) -> Self {
+    pub fn create_window_activity(
+        event: &WindowEvent,
+        app_id: Option<String>,
+        timestamp: OffsetDateTime,
+    ) -> Self {
         log::trace!("create_window_activity: {:?}", event);
         Self::new(
             ActivityType::Window,
             Some(event.window_title.clone()),
-            OffsetDateTime::now_utc(),
+            timestamp,
             event.platform.into(),
             app_id,
         )
     }
 
-    pub fn create_mouse_activity(_: &MouseEvent) -> Self {
-        Self::new(
-            ActivityType::Mouse,
-            None,
-            OffsetDateTime::now_utc(),
-            Platform::Mac,
-            None,
-        )
+    pub fn create_mouse_activity(_: &MouseEvent, timestamp: OffsetDateTime) -> Self {
+        Self::new(ActivityType::Mouse, None, timestamp, Platform::Mac, None)
     }
 
-    pub fn create_keyboard_activity(_: &KeyboardEvent) -> Self {
-        Self::new(
-            ActivityType::Keyboard,
-            None,
-            OffsetDateTime::now_utc(),
-            Platform::Mac,
-            None,
-        )
+    pub fn create_keyboard_activity(_: &KeyboardEvent, timestamp: OffsetDateTime) -> Self {
+        Self::new(ActivityType::Keyboard, None, timestamp, Platform::Mac, None)
     }
 
     #[cfg(test)]
@@ -111,6 +107,7 @@ impl Activity {
                 url: Some("https://cursor.com".to_string()),
             },
             app_id,
+            OffsetDateTime::now_utc(),
         )
     }
 }
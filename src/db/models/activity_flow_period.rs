@@ -0,0 +1,19 @@
+use time::OffsetDateTime;
+
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq, serde::Serialize)]
+pub struct ActivityFlowPeriod {
+    pub id: Option<i64>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub start_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub end_time: OffsetDateTime,
+    pub score: f64,
+    pub app_switches: i64,
+    // seconds
+    pub active_time: i64,
+    pub inactive_time: i64,
+    pub creating_time: i64,
+    pub consuming_time: i64,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+}
@@ -0,0 +1,55 @@
+use time::OffsetDateTime;
+
+#[derive(Clone, Copy, Debug, sqlx::Type, PartialEq, Eq, Hash, serde::Serialize)]
+#[sqlx(type_name = "TEXT", rename_all = "SCREAMING_SNAKE_CASE")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ActivityGapReason {
+    /// The loop kept running but wall-clock time jumped ahead of it, e.g. the laptop was closed.
+    Suspend,
+    /// The service was stopped, or its loop stalled.
+    ServiceNotRunning,
+    /// The wall clock was set back. The gap spans the time that is recorded twice.
+    ClockJump,
+}
+
+impl From<String> for ActivityGapReason {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "SUSPEND" => ActivityGapReason::Suspend,
+            "SERVICE_NOT_RUNNING" => ActivityGapReason::ServiceNotRunning,
+            "CLOCK_JUMP" => ActivityGapReason::ClockJump,
+            _ => panic!("Unknown activity gap reason: {}", s),
+        }
+    }
+}
+
+/**
+ * Time not covered by activity states, recorded when the activity state loop detects a discontinuity.
+ */
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq, serde::Serialize)]
+pub struct ActivityGap {
+    pub id: Option<i64>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub start_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub end_time: OffsetDateTime,
+    pub reason: ActivityGapReason,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+}
+
+impl ActivityGap {
+    pub fn new(
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+        reason: ActivityGapReason,
+    ) -> Self {
+        ActivityGap {
+            id: None,
+            start_time,
+            end_time,
+            reason,
+            created_at: None,
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use time::OffsetDateTime;
+
+use super::ActivityType;
+
+/**
+ * The number of raw activities of a type (and app, for window activities) in a minute, kept after
+ * retention deleted the activities themselves.
+ */
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct ActivityRollup {
+    pub id: Option<i64>,
+    pub minute: OffsetDateTime,
+    pub activity_type: ActivityType,
+    pub app_id: Option<String>,
+    pub event_count: i64,
+    pub created_at: Option<OffsetDateTime>,
+}
@@ -2,11 +2,16 @@ use std::time::Duration;
 
 use time::OffsetDateTime;
 
-#[derive(Debug, sqlx::Type, PartialEq)]
+#[derive(Debug, sqlx::Type, PartialEq, serde::Serialize)]
 #[sqlx(type_name = "TEXT", rename_all = "UPPERCASE")]
+#[serde(rename_all = "UPPERCASE")]
 pub enum ActivityStateType {
     Active,
     Inactive,
+    /// No input, but a passive app (e.g. a video call or a video) was focused.
+    Passive,
+    /// Recording was paused, manually or because a private app was focused.
+    Paused,
 }
 
 impl From<String> for ActivityStateType {
@@ -14,18 +19,25 @@ impl From<String> for ActivityStateType {
         match s.as_str() {
             "ACTIVE" => ActivityStateType::Active,
             "INACTIVE" => ActivityStateType::Inactive,
+            "PASSIVE" => ActivityStateType::Passive,
+            "PAUSED" => ActivityStateType::Paused,
             _ => panic!("Unknown activity state: {}", s), // Or handle invalid types differently
         }
     }
 }
 
-#[derive(Debug, sqlx::FromRow, PartialEq)]
+#[derive(Debug, sqlx::FromRow, PartialEq, serde::Serialize)]
 pub struct ActivityState {
     pub id: Option<i64>,
     pub state: ActivityStateType,
     pub app_switches: i64,
+    /// Set when only part of an active state had input (see `IdleThresholds::with_partial_active_states`).
+    pub active_seconds: Option<i64>,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub start_time: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub end_time: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub created_at: Option<OffsetDateTime>,
 }
 
@@ -36,9 +48,40 @@ impl ActivityState {
             id: None,
             state: ActivityStateType::Inactive,
             app_switches: 0,
+            active_seconds: None,
             start_time: Some(now - Duration::from_secs(120)),
             end_time: Some(now),
             created_at: Some(now),
         }
     }
 }
+
+impl ActivityState {
+    /// Length of the state in seconds.
+    pub fn duration_seconds(&self) -> i64 {
+        match (self.start_time, self.end_time) {
+            (Some(start_time), Some(end_time)) => (end_time - start_time).whole_seconds().max(0),
+            _ => 0,
+        }
+    }
+
+    /// Seconds of the state counted as active: all of an active state unless it was partially active.
+    pub fn active_duration_seconds(&self) -> i64 {
+        match self.state {
+            ActivityStateType::Active => self
+                .active_seconds
+                .map_or(self.duration_seconds(), |active_seconds| {
+                    active_seconds.clamp(0, self.duration_seconds())
+                }),
+            ActivityStateType::Inactive
+            | ActivityStateType::Passive
+            | ActivityStateType::Paused => 0,
+        }
+    }
+}
+
+impl Default for ActivityState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,22 @@
+/**
+ * Seconds an app was focused during an activity state, split by window title.
+ */
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq, serde::Serialize)]
+pub struct ActivityStateApp {
+    pub activity_state_id: i64,
+    pub app_id: String,
+    pub window_title: Option<String>,
+    pub seconds: f64,
+}
+
+/**
+ * Seconds an app was focused during an activity state across all its windows, with the app's details.
+ */
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct ActivityStateAppDetail {
+    pub activity_state_id: i64,
+    pub app_id: String,
+    pub app_name: Option<String>,
+    pub app_external_id: String,
+    pub seconds: f64,
+}
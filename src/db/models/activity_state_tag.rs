@@ -0,0 +1,17 @@
+/**
+ * A tag linked to an activity state, along with the app and weight of the app_tag or tag rule it came from.
+ * Tags that were not derived from an app (e.g. idle) have no app or weight.
+ */
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct ActivityStateTagDetail {
+    pub activity_state_id: i64,
+    pub tag_id: String,
+    pub tag_name: String,
+    pub tag_type: String,
+    pub app_id: Option<String>,
+    pub app_name: Option<String>,
+    pub app_external_id: Option<String>,
+    pub weight: Option<f64>,
+    /// Seconds attributed to the tag, see `AppService::create_tags_from_activities`.
+    pub weighted_duration: Option<f64>,
+}
@@ -6,7 +6,7 @@ use uuid;
 
 use crate::db::types::Platform;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize)]
 pub struct App {
     pub id: Option<String>,
     pub name: Option<String>,
@@ -15,7 +15,10 @@ pub struct App {
     pub is_browser: bool,
     pub is_default: bool,
     pub is_blocked: bool,
+    pub is_passive: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub created_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub updated_at: Option<OffsetDateTime>,
 }
 
@@ -29,6 +32,7 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for App {
             is_browser: row.try_get("is_browser")?,
             is_default: row.try_get("is_default")?,
             is_blocked: row.try_get("is_blocked")?,
+            is_passive: row.try_get("is_passive")?,
             created_at: row.try_get("created_at")?,
             updated_at: row.try_get("updated_at")?,
         })
@@ -40,10 +44,7 @@ impl App {
         // if the event has a url, we use the url as the app_external_id
         // if the event has a bundle_id, we use the bundle_id as the app_external_id
         // if the event has neither, we use the app_name as the app_external_id
-        let url = match &event.url {
-            Some(url) => Some(Self::get_domain_from_url(url)),
-            None => None,
-        };
+        let url = event.url.as_ref().map(|url| Self::get_domain_from_url(url));
 
         let app_external_id = if let Some(url) = url {
             url.clone()
@@ -58,14 +59,31 @@ impl App {
             created_at: None,
             updated_at: None,
             name: Some(event.app_name.clone()),
-            app_external_id: app_external_id,
-            platform: event.platform.clone().into(),
+            app_external_id,
+            platform: event.platform.into(),
             is_browser: event.url.is_some(),
             is_default: false,
             is_blocked: false,
+            is_passive: false,
         }
     }
 
+    /**
+     * Whether `name` (an app name, bundle id or domain) refers to the app of a window. Domains include
+     * their subdomains, and a browser's name or bundle id matches all of its tabs.
+     */
+    pub fn window_is_named(event: &WindowEvent, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let app_external_id = Self::new(event).app_external_id.to_lowercase();
+        app_external_id == name
+            || app_external_id.ends_with(&format!(".{}", name))
+            || event.app_name.to_lowercase() == name
+            || event
+                .bundle_id
+                .as_ref()
+                .is_some_and(|bundle_id| bundle_id.to_lowercase() == name)
+    }
+
     pub fn get_domain_from_url(url: &str) -> String {
         if let Ok(parsed) = url::Url::parse(url) {
             if let Some(domain) = parsed.host_str() {
@@ -99,11 +117,12 @@ impl App {
             is_browser: false,
             is_default: false,
             is_blocked: false,
+            is_passive: false,
         }
     }
 
     #[cfg(test)]
-    pub fn __create_test_apps(names: &Vec<String>) -> Vec<App> {
+    pub fn __create_test_apps(names: &[String]) -> Vec<App> {
         names
             .iter()
             .map(|name| App {
@@ -116,6 +135,7 @@ impl App {
                 is_browser: false,
                 is_default: false,
                 is_blocked: false,
+                is_passive: false,
             })
             .collect()
     }
@@ -1,13 +1,16 @@
 use sqlx::Row;
 use time::OffsetDateTime;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize)]
 pub struct AppTag {
     pub id: Option<String>,
     pub app_id: String,
     pub tag_id: String,
     pub weight: f32,
+    pub is_default: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub created_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub updated_at: Option<OffsetDateTime>,
 }
 
@@ -19,6 +22,7 @@ impl AppTag {
             app_id,
             tag_id,
             weight: 0.0,
+            is_default: false,
             created_at: None,
             updated_at: None,
         }
@@ -32,6 +36,7 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for AppTag {
             app_id: row.try_get("app_id")?,
             tag_id: row.try_get("tag_id")?,
             weight: row.try_get("weight")?,
+            is_default: row.try_get("is_default")?,
             created_at: row.try_get("created_at")?,
             updated_at: row.try_get("updated_at")?,
         })
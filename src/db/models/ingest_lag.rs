@@ -0,0 +1,9 @@
+use time::OffsetDateTime;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct IngestLag {
+    pub id: Option<i64>,
+    pub dropped_events: i64,
+    pub timestamp: OffsetDateTime,
+    pub created_at: Option<OffsetDateTime>,
+}
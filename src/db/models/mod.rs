@@ -1,13 +1,27 @@
 mod activity;
+mod activity_flow_period;
+mod activity_gap;
+mod activity_rollup;
 mod activity_state;
+mod activity_state_app;
+mod activity_state_tag;
 mod app;
 mod app_tag;
 mod blocked_activity;
+mod ingest_lag;
 mod tag;
+mod tag_rule;
 
-pub(crate) use activity::*;
-pub(crate) use activity_state::*;
-pub(crate) use app::*;
-pub(crate) use app_tag::*;
-pub(crate) use blocked_activity::*;
-pub(crate) use tag::*;
+pub use activity::*;
+pub use activity_flow_period::*;
+pub use activity_gap::*;
+pub use activity_rollup::*;
+pub use activity_state::*;
+pub use activity_state_app::*;
+pub use activity_state_tag::*;
+pub use app::*;
+pub use app_tag::*;
+pub use blocked_activity::*;
+pub use ingest_lag::*;
+pub use tag::*;
+pub use tag_rule::*;
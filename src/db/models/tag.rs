@@ -1,16 +1,19 @@
 use sqlx::Row;
 use time::OffsetDateTime;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize)]
 pub struct Tag {
     pub id: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub created_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub updated_at: Option<OffsetDateTime>,
     pub name: String,
     pub tag_type: Option<String>,
     pub parent_tag_id: Option<String>,
     pub is_default: bool,
     pub is_blocked: bool,
+    pub is_passive: bool,
 }
 
 impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Tag {
@@ -24,6 +27,7 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Tag {
             updated_at: row.try_get("updated_at")?,
             is_default: row.try_get("is_default")?,
             is_blocked: row.try_get("is_blocked")?,
+            is_passive: row.try_get("is_passive")?,
         })
     }
 }
@@ -0,0 +1,46 @@
+use time::OffsetDateTime;
+
+/**
+ * Tags an app's activity by matching its window title and/or url path against regular expressions.
+ * A `None` app_external_id matches every app and a `None` pattern matches any value.
+ * When several rules match the same window, only the ones with the highest priority apply.
+ */
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq, serde::Serialize)]
+pub struct TagRule {
+    pub id: String,
+    pub app_external_id: Option<String>,
+    pub title_pattern: Option<String>,
+    pub url_pattern: Option<String>,
+    pub tag_id: String,
+    pub weight: f64,
+    pub priority: i64,
+    pub is_enabled: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+impl TagRule {
+    pub fn new(
+        app_external_id: Option<String>,
+        title_pattern: Option<String>,
+        url_pattern: Option<String>,
+        tag_id: String,
+        weight: f64,
+        priority: i64,
+    ) -> Self {
+        TagRule {
+            id: uuid::Uuid::new_v4().to_string(),
+            app_external_id,
+            title_pattern,
+            url_pattern,
+            tag_id,
+            weight,
+            priority,
+            is_enabled: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
@@ -1,8 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use sqlx::SqlitePool;
+use time::OffsetDateTime;
 
-use super::models::{AppTag, Tag};
+use super::models::{ActivityStateTagDetail, AppTag, Tag, TagRule};
+use crate::error::{Error, Result, ResultExt};
+
+/// Upper bound on hierarchy depth when walking up from a tag, in case the data already has a cycle.
+const MAX_TAG_DEPTH: i64 = 32;
 
 #[derive(Clone)]
 pub struct TagRepo {
@@ -14,25 +19,147 @@ impl TagRepo {
         Self { pool }
     }
 
-    pub async fn get_tag_by_name(&self, name: &str) -> Result<Tag, sqlx::Error> {
+    pub async fn get_tag_by_name(&self, name: &str) -> Result<Tag> {
         let mut conn = self.pool.acquire().await?;
 
         sqlx::query_as!(Tag, "SELECT * FROM tag WHERE name = ?", name)
             .fetch_one(&mut *conn)
             .await
+            .not_found("tag", name)
+    }
+
+    pub async fn get_tag_by_id(&self, id: &str) -> Result<Tag> {
+        let mut conn = self.pool.acquire().await?;
+
+        sqlx::query_as!(Tag, "SELECT * FROM tag WHERE id = ?", id)
+            .fetch_one(&mut *conn)
+            .await
+            .not_found("tag", id)
     }
 
-    pub async fn get_default_tags_by_app_tags(
+    pub async fn get_all_tags(&self) -> Result<Vec<Tag>> {
+        let mut conn = self.pool.acquire().await?;
+
+        sqlx::query_as!(Tag, "SELECT * FROM tag ORDER BY tag_type, name")
+            .fetch_all(&mut *conn)
+            .await
+            .context("get all tags")
+    }
+
+    pub async fn create_child_tag(
         &self,
-        app_tags: &Vec<AppTag>,
-    ) -> Result<Vec<Tag>, sqlx::Error> {
+        name: &str,
+        tag_type: &str,
+        parent_tag_id: &str,
+    ) -> Result<Tag> {
+        self.create_tag(name, tag_type, Some(parent_tag_id)).await
+    }
+
+    /**
+     * Moves a tag under `parent_tag_id`, or to the top level with `None`. Fails with `Validation`
+     * when the new parent is the tag itself or one of its descendants.
+     */
+    pub async fn set_parent_tag(&self, tag_id: &str, parent_tag_id: Option<&str>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(parent_tag_id) = parent_tag_id {
+            let creates_cycle = sqlx::query_scalar!(
+                r#"WITH RECURSIVE descendant(id) AS (
+                    SELECT ?
+                    UNION
+                    SELECT tag.id FROM tag JOIN descendant ON tag.parent_tag_id = descendant.id
+                )
+                SELECT EXISTS(SELECT 1 FROM descendant WHERE id = ?) as "creates_cycle!: bool""#,
+                tag_id,
+                parent_tag_id
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .context("set parent tag")?;
+            if creates_cycle {
+                return Err(Error::Validation(format!(
+                    "tag {} cannot be the parent of {}: it is the tag itself or one of its descendants",
+                    parent_tag_id, tag_id
+                )));
+            }
+        }
+        let result = sqlx::query!(
+            "UPDATE tag SET parent_tag_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            parent_tag_id,
+            tag_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("set parent tag")?;
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound {
+                entity: "tag",
+                id: tag_id.to_string(),
+            });
+        }
+        tx.commit().await.context("set parent tag")?;
+        Ok(())
+    }
+
+    /// Returns the tag's parent, grandparent and so on, nearest first.
+    pub async fn get_tag_ancestors(&self, tag_id: &str) -> Result<Vec<Tag>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as::<_, Tag>(
+            r#"WITH RECURSIVE ancestor(id, depth) AS (
+                SELECT parent_tag_id, 1 FROM tag WHERE id = ? AND parent_tag_id IS NOT NULL
+                UNION
+                SELECT tag.parent_tag_id, ancestor.depth + 1
+                FROM tag JOIN ancestor ON tag.id = ancestor.id
+                WHERE tag.parent_tag_id IS NOT NULL AND ancestor.depth < ?
+            )
+            SELECT tag.* FROM tag
+            JOIN (SELECT id, MIN(depth) AS depth FROM ancestor GROUP BY id) AS ancestor
+                ON tag.id = ancestor.id
+            ORDER BY ancestor.depth"#,
+        )
+        .bind(tag_id)
+        .bind(MAX_TAG_DEPTH)
+        .fetch_all(&mut *conn)
+        .await
+        .context("get tag ancestors")
+    }
+
+    /// Returns every tag below the given tag, at any depth.
+    pub async fn get_tag_descendants(&self, tag_id: &str) -> Result<Vec<Tag>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as::<_, Tag>(
+            r#"WITH RECURSIVE descendant(id) AS (
+                SELECT id FROM tag WHERE parent_tag_id = ?
+                UNION
+                SELECT tag.id FROM tag JOIN descendant ON tag.parent_tag_id = descendant.id
+            )
+            SELECT tag.* FROM tag JOIN descendant ON tag.id = descendant.id
+            ORDER BY tag.name"#,
+        )
+        .bind(tag_id)
+        .fetch_all(&mut *conn)
+        .await
+        .context("get tag descendants")
+    }
+
+    pub async fn get_tags_by_app_id(&self, app_id: &str) -> Result<Vec<Tag>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            Tag,
+            "SELECT tag.* FROM tag JOIN app_tag ON tag.id = app_tag.tag_id WHERE app_tag.app_id = ?",
+            app_id
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get tags by app id")
+    }
+
+    pub async fn get_default_tags_by_app_tags(&self, app_tags: &[AppTag]) -> Result<Vec<Tag>> {
         let mut conn = self.pool.acquire().await?;
         let app_ids = app_tags
             .iter()
-            .filter_map(|app_tag| Some(app_tag.app_id.clone()))
+            .map(|app_tag| app_tag.app_id.clone())
             .collect::<Vec<String>>();
-        let placeholders = std::iter::repeat("?")
-            .take(app_ids.len())
+        let placeholders = std::iter::repeat_n("?", app_ids.len())
             .collect::<Vec<_>>()
             .join(",");
 
@@ -51,34 +178,215 @@ impl TagRepo {
             query = query.bind(app_id);
         }
 
-        query.fetch_all(&mut *conn).await
+        query
+            .fetch_all(&mut *conn)
+            .await
+            .context("get default tags by app tags")
+    }
+
+    /**
+     * Returns (activity_state_id, tag name) pairs for the default tags (creating, consuming, neutral, idle)
+     * linked to activity states starting within the given range.
+     */
+    pub async fn get_default_tag_names_for_activity_states_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<(i64, String)>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = sqlx::query!(
+            r#"SELECT DISTINCT activity_state.id as "activity_state_id!", tag.name
+            FROM activity_state
+            JOIN activity_state_tag ON activity_state_tag.activity_state_id = activity_state.id
+            JOIN tag ON tag.id = activity_state_tag.tag_id
+            WHERE activity_state.start_time >= ? AND activity_state.start_time < ?
+                AND tag.tag_type = 'default'"#,
+            start_time,
+            end_time,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get default tag names for activity states between")?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.activity_state_id, row.name))
+            .collect())
+    }
+
+    /**
+     * Returns every tag linked to an activity state starting within the given range, with the app
+     * and weight of the app_tag or tag rule that produced it.
+     */
+    pub async fn get_activity_state_tag_details_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<ActivityStateTagDetail>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            ActivityStateTagDetail,
+            r#"SELECT activity_state.id as "activity_state_id!",
+                tag.id as "tag_id!", tag.name as tag_name, tag.tag_type,
+                app.id as "app_id?", app.name as "app_name?",
+                app.app_external_id as "app_external_id?",
+                COALESCE(app_tag.weight, tag_rule.weight) as "weight?: f64",
+                activity_state_tag.weighted_duration as "weighted_duration?: f64"
+            FROM activity_state_tag
+            JOIN activity_state ON activity_state.id = activity_state_tag.activity_state_id
+            JOIN tag ON tag.id = activity_state_tag.tag_id
+            LEFT JOIN app_tag ON app_tag.id = activity_state_tag.app_tag_id
+            LEFT JOIN tag_rule ON tag_rule.id = activity_state_tag.tag_rule_id
+            LEFT JOIN app ON app.id = COALESCE(app_tag.app_id, activity_state_tag.app_id)
+            WHERE activity_state.start_time >= ? AND activity_state.start_time < ?"#,
+            start_time,
+            end_time,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get activity state tag details between")
     }
 
-    pub async fn create_tag(&self, name: &str, tag_type: &str) -> Result<(), sqlx::Error> {
+    pub async fn create_tag(
+        &self,
+        name: &str,
+        tag_type: &str,
+        parent_tag_id: Option<&str>,
+    ) -> Result<Tag> {
         let mut conn = self.pool.acquire().await?;
+        let id = uuid::Uuid::new_v4().to_string();
         sqlx::query!(
-            "INSERT INTO tag (name, tag_type) VALUES (?, ?)",
+            "INSERT INTO tag (id, name, tag_type, parent_tag_id) VALUES (?, ?, ?, ?)",
+            id,
+            name,
+            tag_type,
+            parent_tag_id
+        )
+        .execute(&mut *conn)
+        .await
+        .context("create tag")?;
+        sqlx::query_as!(Tag, "SELECT * FROM tag WHERE id = ?", id)
+            .fetch_one(&mut *conn)
+            .await
+            .context("create tag")
+    }
+
+    pub async fn get_tag_by_name_and_type(
+        &self,
+        name: &str,
+        tag_type: &str,
+    ) -> Result<Option<Tag>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            Tag,
+            "SELECT * FROM tag WHERE name = ? AND tag_type = ?",
             name,
             tag_type
         )
+        .fetch_optional(&mut *conn)
+        .await
+        .context("get tag by name and type")
+    }
+
+    pub async fn rename_tag(
+        &self,
+        id: &str,
+        name: &str,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            "UPDATE tag SET name = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            name,
+            id
+        )
         .execute(&mut *conn)
-        .await?;
-        Ok(())
+        .await
+        .context("rename tag")
+    }
+
+    pub async fn set_tag_blocked(
+        &self,
+        id: &str,
+        is_blocked: bool,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            "UPDATE tag SET is_blocked = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            is_blocked,
+            id
+        )
+        .execute(&mut *conn)
+        .await
+        .context("set tag blocked")
+    }
+
+    pub async fn set_tag_passive(
+        &self,
+        id: &str,
+        is_passive: bool,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            "UPDATE tag SET is_passive = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            is_passive,
+            id
+        )
+        .execute(&mut *conn)
+        .await
+        .context("set tag passive")
+    }
+
+    /**
+     * Deletes a tag along with its app tags, tag rules and activity state tags. Child tags move up
+     * to the deleted tag's parent.
+     */
+    pub async fn delete_tag(&self, id: &str) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"UPDATE tag SET parent_tag_id = (SELECT parent_tag_id FROM tag WHERE id = ?),
+                updated_at = CURRENT_TIMESTAMP
+            WHERE parent_tag_id = ?"#,
+            id,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete tag")?;
+        sqlx::query!("DELETE FROM activity_state_tag WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await
+            .context("delete tag")?;
+        sqlx::query!("DELETE FROM app_tag WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await
+            .context("delete tag")?;
+        sqlx::query!("DELETE FROM default_app_tag WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await
+            .context("delete tag")?;
+        sqlx::query!("DELETE FROM tag_rule WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await
+            .context("delete tag")?;
+        let result = sqlx::query!("DELETE FROM tag WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await
+            .context("delete tag")?;
+        tx.commit().await.context("delete tag")?;
+        Ok(result)
     }
 
     pub async fn create_activity_state_tags(
         &self,
         activity_state_id: i64,
-        tags: &Vec<Tag>,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+        tags: &[Tag],
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let mut conn = self.pool.acquire().await?;
         let unique_tags = tags
             .iter()
             .filter_map(|tag| tag.id.clone())
             .collect::<HashSet<String>>();
 
-        let placeholders = std::iter::repeat("(?, ?)")
-            .take(unique_tags.len())
+        let placeholders = std::iter::repeat_n("(?, ?)", unique_tags.len())
             .collect::<Vec<_>>()
             .join(",");
 
@@ -94,46 +402,39 @@ impl TagRepo {
             query = query.bind(activity_state_id).bind(tag);
         }
 
-        query.execute(&mut *conn).await
+        query
+            .execute(&mut *conn)
+            .await
+            .context("create activity state tags")
     }
 
+    /// Links app tags to an activity state along with their weighted duration in seconds.
     pub async fn create_activity_state_tags_with_app_tags(
         &self,
         activity_state_id: i64,
-        app_tags: &Vec<AppTag>,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+        app_tags: &[(AppTag, f64)],
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let mut conn = self.pool.acquire().await?;
+        insert_activity_state_tags_with_app_tags(&mut conn, activity_state_id, app_tags).await
+    }
 
-        // Create unique pairs of (app_tag.id, app_tag.tag_id)
-        let unique_tags = app_tags
-            .iter()
-            .map(|app_tag| (app_tag.id.clone(), app_tag.tag_id.clone()))
-            .collect::<HashSet<(Option<String>, String)>>();
-
-        let placeholders = std::iter::repeat("(?, ?, ?)")
-            .take(unique_tags.len())
-            .collect::<Vec<_>>()
-            .join(",");
-
-        if unique_tags.is_empty() {
-            println!("DEBUG: No app_tags to insert, returning early");
-            // Return a successful result but with 0 rows affected by executing a no-op query
-            return sqlx::query("SELECT 1 WHERE 0").execute(&mut *conn).await;
-        }
-
-        let query = format!(
-            r#"
-            INSERT INTO activity_state_tag (activity_state_id, app_tag_id, tag_id)
-            VALUES {}"#,
-            placeholders
-        );
-
-        let mut query = sqlx::query(&query);
-        for (app_tag_id, tag_id) in unique_tags {
-            query = query.bind(activity_state_id).bind(app_tag_id).bind(tag_id);
-        }
-
-        query.execute(&mut *conn).await
+    /**
+     * Links the tags of matched tag rules to an activity state, keeping the app the rule matched on,
+     * the rule itself and the weighted duration in seconds, along with the app tags of the time no
+     * rule matched. Runs in one transaction, so it can be retried after a failure.
+     */
+    pub async fn create_activity_state_tags_with_tag_rules(
+        &self,
+        activity_state_id: i64,
+        matches: &[(String, &TagRule, f64)],
+        app_tags: &[(AppTag, f64)],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_activity_state_tags_with_tag_rules(&mut tx, activity_state_id, matches).await?;
+        insert_activity_state_tags_with_app_tags(&mut tx, activity_state_id, app_tags).await?;
+        tx.commit()
+            .await
+            .context("create activity state tags with tag rules")
     }
 
     pub async fn create_app_tag(
@@ -141,7 +442,7 @@ impl TagRepo {
         app_id: String,
         tag_id: String,
         weight: f32,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         let mut conn = self.pool.acquire().await?;
         let id = uuid::Uuid::new_v4().to_string();
 
@@ -154,10 +455,121 @@ impl TagRepo {
         )
         .execute(&mut *conn)
         .await
+        .context("create app tag")
+    }
+
+    /// Links a tag to an app, or updates the weight when the app already has the tag. Either way the
+    /// app tag is no longer a seeded default.
+    pub async fn upsert_app_tag(&self, app_id: &str, tag_id: &str, weight: f32) -> Result<AppTag> {
+        let mut conn = self.pool.acquire().await?;
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query!(
+            r#"INSERT INTO app_tag (id, app_id, tag_id, weight) VALUES (?, ?, ?, ?)
+            ON CONFLICT (app_id, tag_id)
+            DO UPDATE SET weight = excluded.weight, is_default = FALSE,
+                updated_at = CURRENT_TIMESTAMP"#,
+            id,
+            app_id,
+            tag_id,
+            weight
+        )
+        .execute(&mut *conn)
+        .await
+        .context("upsert app tag")?;
+        sqlx::query_as::<_, AppTag>(
+            r#"SELECT id, app_id, tag_id, weight, is_default, created_at, updated_at
+            FROM app_tag WHERE app_id = ? AND tag_id = ?"#,
+        )
+        .bind(app_id)
+        .bind(tag_id)
+        .fetch_one(&mut *conn)
+        .await
+        .context("upsert app tag")
+    }
+
+    // activity state tags created from the app tag keep their app and stop referencing it
+    pub async fn delete_app_tag(
+        &self,
+        app_id: &str,
+        tag_id: &str,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"UPDATE activity_state_tag SET app_id = ?, app_tag_id = NULL
+            WHERE app_tag_id IN (SELECT id FROM app_tag WHERE app_id = ? AND tag_id = ?)"#,
+            app_id,
+            app_id,
+            tag_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete app tag")?;
+        let result = sqlx::query!(
+            "DELETE FROM app_tag WHERE app_id = ? AND tag_id = ?",
+            app_id,
+            tag_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete app tag")?;
+        tx.commit().await.context("delete app tag")?;
+        Ok(result)
+    }
+
+    /**
+     * Replaces an app's tags with the seeded defaults, or with `fallback_tag_id` (weight 1.0) when
+     * the app wasn't seeded.
+     */
+    pub async fn reset_app_tags(&self, app_id: &str, fallback_tag_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"UPDATE activity_state_tag SET app_id = ?, app_tag_id = NULL
+            WHERE app_tag_id IN (SELECT id FROM app_tag WHERE app_id = ?)"#,
+            app_id,
+            app_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("reset app tags")?;
+        sqlx::query!("DELETE FROM app_tag WHERE app_id = ?", app_id)
+            .execute(&mut *tx)
+            .await
+            .context("reset app tags")?;
+        let defaults = sqlx::query!(
+            "SELECT tag_id, weight FROM default_app_tag WHERE app_id = ?",
+            app_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("reset app tags")?;
+        let is_seeded = !defaults.is_empty();
+        let defaults = if is_seeded {
+            defaults
+                .into_iter()
+                .map(|row| (row.tag_id, row.weight))
+                .collect::<Vec<_>>()
+        } else {
+            vec![(fallback_tag_id.to_string(), 1.0)]
+        };
+        for (tag_id, weight) in defaults {
+            let id = uuid::Uuid::new_v4().to_string();
+            sqlx::query!(
+                "INSERT INTO app_tag (id, app_id, tag_id, weight, is_default) VALUES (?, ?, ?, ?, ?)",
+                id,
+                app_id,
+                tag_id,
+                weight,
+                is_seeded
+            )
+            .execute(&mut *tx)
+            .await
+            .context("reset app tags")?;
+        }
+        tx.commit().await.context("reset app tags")
     }
 
     #[cfg(test)]
-    pub async fn get_app_tag_by_app_id(&self, app_id: &str) -> Result<Tag, sqlx::Error> {
+    pub async fn get_app_tag_by_app_id(&self, app_id: &str) -> Result<Tag> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             Tag,
@@ -166,13 +578,11 @@ impl TagRepo {
         )
         .fetch_one(&mut *conn)
         .await
+        .not_found("app tag", app_id)
     }
 
     #[cfg(test)]
-    pub async fn get_tags_for_activity_state(
-        &self,
-        activity_state_id: i64,
-    ) -> Result<Vec<Tag>, sqlx::Error> {
+    pub async fn get_tags_for_activity_state(&self, activity_state_id: i64) -> Result<Vec<Tag>> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query_as!(
             Tag,
@@ -183,12 +593,110 @@ impl TagRepo {
         )
         .fetch_all(&mut *conn)
         .await
+        .context("get tags for activity state")
+    }
+}
+
+async fn insert_activity_state_tags_with_app_tags(
+    conn: &mut sqlx::SqliteConnection,
+    activity_state_id: i64,
+    app_tags: &[(AppTag, f64)],
+) -> Result<sqlx::sqlite::SqliteQueryResult> {
+    // Sum the durations of unique pairs of (app_tag.id, app_tag.tag_id)
+    let mut unique_tags: HashMap<(Option<String>, String), f64> = HashMap::new();
+    for (app_tag, weighted_duration) in app_tags {
+        *unique_tags
+            .entry((app_tag.id.clone(), app_tag.tag_id.clone()))
+            .or_default() += weighted_duration;
+    }
+
+    let placeholders = std::iter::repeat_n("(?, ?, ?, ?)", unique_tags.len())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    if unique_tags.is_empty() {
+        log::trace!(
+            "no app tags to insert for activity state {}",
+            activity_state_id
+        );
+        // Return a successful result but with 0 rows affected by executing a no-op query
+        return sqlx::query("SELECT 1 WHERE 0")
+            .execute(&mut *conn)
+            .await
+            .map_err(Error::from);
+    }
+
+    let query = format!(
+        r#"
+            INSERT INTO activity_state_tag (activity_state_id, app_tag_id, tag_id, weighted_duration)
+            VALUES {}"#,
+        placeholders
+    );
+
+    let mut query = sqlx::query(&query);
+    for ((app_tag_id, tag_id), weighted_duration) in unique_tags {
+        query = query
+            .bind(activity_state_id)
+            .bind(app_tag_id)
+            .bind(tag_id)
+            .bind(weighted_duration);
+    }
+
+    query
+        .execute(&mut *conn)
+        .await
+        .context("create activity state tags with app tags")
+}
+
+async fn insert_activity_state_tags_with_tag_rules(
+    conn: &mut sqlx::SqliteConnection,
+    activity_state_id: i64,
+    matches: &[(String, &TagRule, f64)],
+) -> Result<sqlx::sqlite::SqliteQueryResult> {
+    let mut unique_matches: HashMap<(String, String, String), f64> = HashMap::new();
+    for (app_id, tag_rule, weighted_duration) in matches {
+        *unique_matches
+            .entry((app_id.clone(), tag_rule.id.clone(), tag_rule.tag_id.clone()))
+            .or_default() += weighted_duration;
+    }
+
+    if unique_matches.is_empty() {
+        return sqlx::query("SELECT 1 WHERE 0")
+            .execute(&mut *conn)
+            .await
+            .map_err(Error::from);
+    }
+
+    let placeholders = std::iter::repeat_n("(?, ?, ?, ?, ?)", unique_matches.len())
+        .collect::<Vec<_>>()
+        .join(",");
+    let query = format!(
+        r#"
+            INSERT INTO activity_state_tag (activity_state_id, app_id, tag_rule_id, tag_id, weighted_duration)
+            VALUES {}"#,
+        placeholders
+    );
+
+    let mut query = sqlx::query(&query);
+    for ((app_id, tag_rule_id, tag_id), weighted_duration) in unique_matches {
+        query = query
+            .bind(activity_state_id)
+            .bind(app_id)
+            .bind(tag_rule_id)
+            .bind(tag_id)
+            .bind(weighted_duration);
     }
+
+    query
+        .execute(&mut *conn)
+        .await
+        .context("create activity state tags with tag rules")
 }
 
 #[cfg(test)]
 mod tests {
-    // use crate::db::{db_manager, models::AppTag, tag_repo::TagRepo};
+    use super::*;
+    use crate::db::db_manager;
 
     // #[tokio::test]
     // async fn test_create_activity_state_tags() {
@@ -209,4 +717,60 @@ mod tests {
     //     // let activity = activity_service.get_activity(1).await.unwrap();
     //     assert_eq!(true, true);
     // }
+
+    #[tokio::test]
+    async fn test_tag_hierarchy() {
+        let pool = db_manager::create_test_db().await;
+        let tag_repo = TagRepo::new(pool);
+        let coding_id = tag_repo
+            .get_tag_by_name("coding")
+            .await
+            .unwrap()
+            .id
+            .unwrap();
+
+        let frontend = tag_repo
+            .create_child_tag("frontend", "category", &coding_id)
+            .await
+            .unwrap();
+        let frontend_id = frontend.id.clone().unwrap();
+        assert_eq!(frontend.parent_tag_id.as_deref(), Some(coding_id.as_str()));
+        let react = tag_repo
+            .create_child_tag("react", "category", &frontend_id)
+            .await
+            .unwrap();
+        let react_id = react.id.unwrap();
+
+        let ancestor_names = tag_repo
+            .get_tag_ancestors(&react_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect::<Vec<_>>();
+        assert_eq!(ancestor_names, vec!["frontend", "coding"]);
+        let descendant_names = tag_repo
+            .get_tag_descendants(&coding_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect::<Vec<_>>();
+        assert_eq!(descendant_names, vec!["frontend", "react"]);
+
+        assert!(matches!(
+            tag_repo.set_parent_tag(&coding_id, Some(&react_id)).await,
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            tag_repo.set_parent_tag(&coding_id, Some(&coding_id)).await,
+            Err(Error::Validation(_))
+        ));
+        tag_repo.set_parent_tag(&react_id, None).await.unwrap();
+        assert!(tag_repo
+            .get_tag_ancestors(&react_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -0,0 +1,149 @@
+use super::models::TagRule;
+use crate::error::{Result, ResultExt};
+
+#[derive(Clone)]
+pub struct TagRuleRepo {
+    pool: sqlx::SqlitePool,
+}
+
+impl TagRuleRepo {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        TagRuleRepo { pool }
+    }
+
+    pub async fn save_tag_rule(
+        &self,
+        tag_rule: &TagRule,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"INSERT INTO tag_rule (id, app_external_id, title_pattern, url_pattern, tag_id, weight, priority, is_enabled)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+            tag_rule.id,
+            tag_rule.app_external_id,
+            tag_rule.title_pattern,
+            tag_rule.url_pattern,
+            tag_rule.tag_id,
+            tag_rule.weight,
+            tag_rule.priority,
+            tag_rule.is_enabled,
+        )
+        .execute(&mut *conn)
+        .await
+        .context("save tag rule")
+    }
+
+    pub async fn get_tag_rule(&self, id: &str) -> Result<TagRule> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            TagRule,
+            r#"SELECT id, app_external_id, title_pattern, url_pattern, tag_id, weight, priority,
+                is_enabled, created_at as "created_at: _", updated_at as "updated_at: _"
+            FROM tag_rule WHERE id = ?"#,
+            id
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .not_found("tag rule", id)
+    }
+
+    pub async fn get_all_tag_rules(&self) -> Result<Vec<TagRule>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            TagRule,
+            r#"SELECT id, app_external_id, title_pattern, url_pattern, tag_id, weight, priority,
+                is_enabled, created_at as "created_at: _", updated_at as "updated_at: _"
+            FROM tag_rule ORDER BY priority DESC, created_at"#
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get all tag rules")
+    }
+
+    pub async fn get_enabled_tag_rules(&self) -> Result<Vec<TagRule>> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query_as!(
+            TagRule,
+            r#"SELECT id, app_external_id, title_pattern, url_pattern, tag_id, weight, priority,
+                is_enabled, created_at as "created_at: _", updated_at as "updated_at: _"
+            FROM tag_rule WHERE is_enabled = TRUE ORDER BY priority DESC"#
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("get enabled tag rules")
+    }
+
+    pub async fn set_tag_rule_enabled(
+        &self,
+        id: &str,
+        is_enabled: bool,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            "UPDATE tag_rule SET is_enabled = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            is_enabled,
+            id
+        )
+        .execute(&mut *conn)
+        .await
+        .context("set tag rule enabled")
+    }
+
+    // activity state tags created by the rule are kept and just stop referencing it
+    pub async fn delete_tag_rule(&self, id: &str) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "UPDATE activity_state_tag SET tag_rule_id = NULL WHERE tag_rule_id = ?",
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("delete tag rule")?;
+        let result = sqlx::query!("DELETE FROM tag_rule WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await
+            .context("delete tag rule")?;
+        tx.commit().await.context("delete tag rule")?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{db_manager, tag_repo::TagRepo};
+
+    #[tokio::test]
+    async fn test_tag_rule_repo() {
+        let pool = db_manager::create_test_db().await;
+        let tag_rule_repo = TagRuleRepo::new(pool.clone());
+        let coding_tag = TagRepo::new(pool).get_tag_by_name("coding").await.unwrap();
+        let tag_rule = TagRule::new(
+            Some("github.com".to_string()),
+            None,
+            Some(r"^/[^/]+/[^/]+/pull/\d+".to_string()),
+            coding_tag.id.unwrap(),
+            1.0,
+            10,
+        );
+        tag_rule_repo.save_tag_rule(&tag_rule).await.unwrap();
+
+        let saved = tag_rule_repo.get_tag_rule(&tag_rule.id).await.unwrap();
+        assert_eq!(saved.url_pattern, tag_rule.url_pattern);
+        assert!(saved.is_enabled);
+
+        tag_rule_repo
+            .set_tag_rule_enabled(&tag_rule.id, false)
+            .await
+            .unwrap();
+        assert!(tag_rule_repo
+            .get_enabled_tag_rules()
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(tag_rule_repo.get_all_tag_rules().await.unwrap().len(), 1);
+
+        tag_rule_repo.delete_tag_rule(&tag_rule.id).await.unwrap();
+        assert!(tag_rule_repo.get_all_tag_rules().await.unwrap().is_empty());
+    }
+}
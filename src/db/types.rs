@@ -1,5 +1,6 @@
-#[derive(Debug, sqlx::Type, PartialEq, Clone)]
+#[derive(Debug, sqlx::Type, PartialEq, Clone, serde::Serialize)]
 #[sqlx(type_name = "TEXT", rename_all = "UPPERCASE")]
+#[serde(rename_all = "UPPERCASE")]
 pub enum Platform {
     Mac,
     Windows,
@@ -0,0 +1,132 @@
+/**
+ * Errors returned by the repos and services. Database errors carry the operation that failed and
+ * missing rows are reported as `NotFound`, so callers can match on the variant instead of the
+ * underlying `sqlx::Error`.
+ */
+#[derive(Debug)]
+pub enum Error {
+    Database {
+        context: String,
+        source: sqlx::Error,
+    },
+    NotFound {
+        entity: &'static str,
+        id: String,
+    },
+    Validation(String),
+    /// The change conflicts with existing data, e.g. a tag with the same name already exists.
+    Conflict(String),
+    Config(String),
+    /// The event source stopped sending events.
+    SourceClosed,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::NotFound { .. })
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Database { context, source } => write!(f, "failed to {}: {}", context, source),
+            Error::NotFound { entity, id } if id.is_empty() => write!(f, "{} not found", entity),
+            Error::NotFound { entity, id } => write!(f, "{} {} not found", entity, id),
+            Error::Validation(reason) => write!(f, "invalid input: {}", reason),
+            Error::Conflict(reason) => write!(f, "conflict: {}", reason),
+            Error::Config(reason) => write!(f, "invalid configuration: {}", reason),
+            Error::SourceClosed => write!(f, "event source closed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => Error::NotFound {
+                entity: "row",
+                id: String::new(),
+            },
+            source => Error::Database {
+                context: "query the database".to_string(),
+                source,
+            },
+        }
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for Error {
+    fn from(err: sqlx::migrate::MigrateError) -> Self {
+        Error::Database {
+            context: "run migrations".to_string(),
+            source: err.into(),
+        }
+    }
+}
+
+pub(crate) trait ResultExt<T> {
+    /// Wraps a database error with the operation that failed, e.g. `save app`.
+    fn context(self, context: &str) -> Result<T>;
+
+    /// Like `context`, but reports a missing row as `NotFound` for the given entity and id.
+    fn not_found(self, entity: &'static str, id: impl ToString) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for std::result::Result<T, sqlx::Error> {
+    fn context(self, context: &str) -> Result<T> {
+        self.map_err(|source| match source {
+            sqlx::Error::RowNotFound => Error::NotFound {
+                entity: "row",
+                id: String::new(),
+            },
+            source => Error::Database {
+                context: context.to_string(),
+                source,
+            },
+        })
+    }
+
+    fn not_found(self, entity: &'static str, id: impl ToString) -> Result<T> {
+        self.map_err(|source| match source {
+            sqlx::Error::RowNotFound => Error::NotFound {
+                entity,
+                id: id.to_string(),
+            },
+            source => Error::Database {
+                context: format!("get {}", entity),
+                source,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_result_ext() {
+        let not_found: std::result::Result<(), sqlx::Error> = Err(sqlx::Error::RowNotFound);
+        let err = not_found.not_found("app", "zoom.us").unwrap_err();
+        assert!(err.is_not_found());
+        assert_eq!(err.to_string(), "app zoom.us not found");
+
+        let closed: std::result::Result<(), sqlx::Error> = Err(sqlx::Error::PoolClosed);
+        let err = closed.context("save app").unwrap_err();
+        assert!(matches!(err, Error::Database { .. }));
+        assert!(err.to_string().starts_with("failed to save app: "));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
@@ -1,8 +1,28 @@
 pub mod db;
+pub mod error;
 pub mod monitor_callback;
+pub mod server;
 pub mod services;
+pub mod sources;
 
 mod utils;
 
 pub use db::db_manager::{get_default_db_path, DbManager};
-pub use monitor_callback::MonitoringConfig;
+pub use error::{Error, Result};
+pub use monitor_callback::{MonitoringConfig, MonitoringHandle};
+pub use server::{
+    live_stream::{LiveStreamEvent, LiveStreamPublisher, LIVE_STREAM_VERSION},
+    QueryServer,
+};
+pub use services::activities_service::{
+    ActivityStateLoopSnapshot, ActivityStateLoopStatus, IngestStats,
+};
+pub use services::activity_flow_period_service::ActivityFlowPeriodService;
+pub use services::activity_state_service::IdleThresholds;
+pub use services::report_service::{AppUsage, GapUsage, ReportService, TagUsage, UsageSummary};
+pub use services::retention_service::{RetentionPolicy, RetentionReport, RetentionService};
+pub use services::tag_rule_service::TagRuleService;
+pub use services::tag_service::TagService;
+pub use services::title_policy_service::{TitlePolicy, TitleRedaction};
+pub use sources::EventSource;
+pub use utils::clock::{Clock, ManualClock, SystemClock};
@@ -1,21 +1,57 @@
 extern crate dotenv;
-use std::{sync::Arc, time::Duration};
+use std::{io::Write, sync::Arc};
 
 use dotenv::dotenv;
 
 use os_monitor::{
-    detect_changes, get_application_icon_data, has_accessibility_permissions,
-    request_accessibility_permissions, Monitor,
+    detect_changes, has_accessibility_permissions, request_accessibility_permissions, Monitor,
 };
-use os_monitor_service::{db::db_manager, MonitoringConfig};
+use os_monitor_service::{
+    db::{
+        activity_state_repo::ActivityStateRepo, app_repo::AppRepo, db_manager, tag_repo::TagRepo,
+    },
+    DbManager, MonitoringConfig, ReportService, UsageSummary,
+};
+use time::{Duration, OffsetDateTime, UtcOffset};
 use tokio::{self};
 
+mod cli;
+
+use cli::{Command, DateRange, RunOptions};
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
 #[tokio::main]
 async fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    dotenv().ok();
+
+    let cli = match cli::parse_args(std::env::args().skip(1).collect()) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let db_path = cli.db_path.unwrap_or_else(db_manager::get_default_db_path);
+
+    let result = match cli.command {
+        Command::Run(options) => run(db_path, options).await,
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        command => run_command(&db_path, command).await,
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run(db_path: String, options: RunOptions) -> CliResult {
     println!("Starting os-monitor");
     log::trace!("Starting os-monitor");
-    dotenv().ok();
 
     let has_permissions = has_accessibility_permissions();
     println!("has_permissions: {}", has_permissions);
@@ -26,23 +62,182 @@ async fn main() {
 
     let monitor = Monitor::new();
 
-    let db_path = db_manager::get_default_db_path();
-
-    let icon_data = get_application_icon_data("com.apple.finder");
-    println!("icon_data: {}", icon_data.unwrap());
-
-    tokio::spawn(async move {
-        MonitoringConfig::new(Arc::new(monitor), db_path)
-            .with_interval(Duration::from_secs(60))
-            .initialize()
-            .await;
-    });
+    let mut config = MonitoringConfig::new(Arc::new(monitor), db_path)
+        .with_interval(options.interval)
+        .with_private_apps(options.private_apps);
+    if let Some(port) = options.http_port {
+        config = config.with_http_server(port);
+    }
+    #[cfg(unix)]
+    if let Some(live_stream_path) = options.live_stream_path {
+        config = config.with_live_stream(live_stream_path);
+    }
+    if let Some(recording_path) = options.recording_path {
+        config = config.with_event_recording(recording_path);
+    }
+    let monitoring = config.initialize().await?;
 
     std::thread::spawn(move || loop {
         detect_changes().expect("Failed to detect changes");
         std::thread::sleep(std::time::Duration::from_secs(1));
     });
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+    tokio::signal::ctrl_c().await?;
+    println!("Stopping os-monitor");
+    monitoring.shutdown().await?;
+    Ok(())
+}
+
+async fn run_command(db_path: &str, command: Command) -> CliResult {
+    let db_manager = DbManager::new(db_path).await?;
+    let pool = db_manager.pool.clone();
+    match command {
+        Command::Report {
+            range,
+            weekly,
+            json,
+        } => {
+            let (from, to) = resolve_dates(&range);
+            let report_service = ReportService::new(pool);
+            let summaries = if weekly {
+                report_service
+                    .get_weekly_summaries(from, to, |date| {
+                        date.midnight().assume_offset(range.offset)
+                    })
+                    .await?
+            } else {
+                report_service
+                    .get_daily_summaries(from, to, |date| {
+                        date.midnight().assume_offset(range.offset)
+                    })
+                    .await?
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            } else {
+                for summary in &summaries {
+                    print_summary(summary, range.offset);
+                }
+            }
+        }
+        Command::Apps { json } => {
+            let apps = AppRepo::new(pool).get_all_apps().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&apps)?);
+            } else {
+                for app in apps {
+                    println!(
+                        "{}\t{}\t{}{}",
+                        app.id.unwrap_or_default(),
+                        app.app_external_id,
+                        app.name.unwrap_or_default(),
+                        if app.is_blocked { "\t(blocked)" } else { "" }
+                    );
+                }
+            }
+        }
+        Command::Tags { json } => {
+            let tags = TagRepo::new(pool).get_all_tags().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&tags)?);
+            } else {
+                for tag in tags {
+                    println!(
+                        "{}\t{}\t{}{}",
+                        tag.id.unwrap_or_default(),
+                        tag.tag_type.unwrap_or_default(),
+                        tag.name,
+                        if tag.is_blocked { "\t(blocked)" } else { "" }
+                    );
+                }
+            }
+        }
+        Command::Export { range, output } => {
+            let (from, to) = resolve_dates(&range);
+            let activity_states = ActivityStateRepo::new(pool)
+                .get_activity_states_starting_between(
+                    from.midnight()
+                        .assume_offset(range.offset)
+                        .to_offset(UtcOffset::UTC),
+                    (to + Duration::days(1))
+                        .midnight()
+                        .assume_offset(range.offset)
+                        .to_offset(UtcOffset::UTC),
+                )
+                .await?;
+            let mut writer: Box<dyn Write> = match output {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            for activity_state in &activity_states {
+                writeln!(writer, "{}", serde_json::to_string(activity_state)?)?;
+            }
+            writer.flush()?;
+        }
+        Command::DbCheck => {
+            let problems = db_manager.check().await?;
+            if problems.is_empty() {
+                println!("ok");
+            } else {
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+                return Err(format!("{} problems found", problems.len()).into());
+            }
+        }
+        Command::DbVacuum => {
+            db_manager.vacuum().await?;
+            println!("vacuumed {}", db_path);
+        }
+        Command::Run(_) | Command::Help => unreachable!("handled in main"),
+    }
+    db_manager.pool.close().await;
+    Ok(())
+}
+
+fn resolve_dates(range: &DateRange) -> (time::Date, time::Date) {
+    let today = OffsetDateTime::now_utc().to_offset(range.offset).date();
+    let to = range.to.unwrap_or(today);
+    (range.from.unwrap_or(to), to)
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).round() as i64;
+    format!("{}h{:02}m", minutes / 60, minutes % 60)
+}
+
+fn print_summary(summary: &UsageSummary, offset: time::UtcOffset) {
+    println!(
+        "{} to {}",
+        summary.start_time.to_offset(offset).date(),
+        (summary.end_time.to_offset(offset) - Duration::seconds(1)).date()
+    );
+    println!(
+        "  active {}  passive {}  paused {}  idle {}  app switches {}",
+        format_seconds(summary.active_seconds as f64),
+        format_seconds(summary.passive_seconds as f64),
+        format_seconds(summary.paused_seconds as f64),
+        format_seconds(summary.inactive_seconds as f64),
+        summary.app_switches
+    );
+    for app in summary.apps.iter().take(10) {
+        println!(
+            "  app  {:>8}  {}",
+            format_seconds(app.seconds),
+            app.app_name
+                .as_deref()
+                .filter(|name| !name.is_empty())
+                .or(app.app_external_id.as_deref())
+                .unwrap_or(&app.app_id)
+        );
+    }
+    for tag in summary.tags.iter().take(10) {
+        println!("  tag  {:>8}  {}", format_seconds(tag.seconds), tag.name);
+    }
+    for gap in &summary.gaps {
+        println!(
+            "  gap  {:>8}  {:?}",
+            format_seconds(gap.seconds as f64),
+            gap.reason
+        );
     }
 }
@@ -1,21 +1,69 @@
-use os_monitor::{start_monitoring, Monitor};
+use os_monitor::Monitor;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::services;
+use sqlx::SqlitePool;
+use tokio::task::JoinHandle;
+
+use crate::db::{db_manager::DbManager, install_secret_repo::InstallSecretRepo};
+use crate::error::{Error, Result};
+use crate::server::{live_stream::LiveStreamPublisher, QueryServer};
+use crate::services::{
+    activities_service::{ActivityService, ActivityStateLoopStatus, IngestStats},
+    activity_buffer_service,
+    activity_flow_period_service::ActivityFlowPeriodService,
+    activity_state_service::IdleThresholds,
+    retention_service::{RetentionPolicy, RetentionService},
+    title_policy_service::{TitlePolicy, TitleRedactor},
+};
+use crate::sources::{EventRecorder, EventSource, OsMonitorSource};
+use crate::utils::clock::{Clock, SystemClock};
 
 pub struct MonitoringConfig {
-    monitor: Arc<Monitor>,
+    source: Arc<dyn EventSource>,
     db_path: String,
     activity_state_interval: Duration,
+    idle_thresholds: IdleThresholds,
+    flow_period_interval: Duration,
+    retention_policy: Option<RetentionPolicy>,
+    write_buffer_size: usize,
+    write_buffer_max_age: Duration,
+    ingest_stats: IngestStats,
+    activity_state_loop_status: ActivityStateLoopStatus,
+    private_apps: Vec<String>,
+    title_policies: Vec<TitlePolicy>,
+    recording_path: Option<String>,
+    http_server_port: Option<u16>,
+    #[cfg(unix)]
+    live_stream_path: Option<String>,
+    clock: Arc<dyn Clock>,
 }
 
 impl MonitoringConfig {
     pub fn new(monitor: Arc<Monitor>, db_path: String) -> Self {
+        Self::from_source(Arc::new(OsMonitorSource::new(monitor)), db_path)
+    }
+
+    /// Monitors events from any `EventSource` instead of the os_monitor crate.
+    pub fn from_source(source: Arc<dyn EventSource>, db_path: String) -> Self {
         Self {
-            monitor,
+            source,
             db_path,
             activity_state_interval: Duration::from_secs(60),
+            idle_thresholds: IdleThresholds::default(),
+            flow_period_interval: Duration::from_secs(10 * 60),
+            retention_policy: None,
+            write_buffer_size: activity_buffer_service::DEFAULT_MAX_SIZE,
+            write_buffer_max_age: activity_buffer_service::DEFAULT_MAX_AGE,
+            ingest_stats: IngestStats::default(),
+            activity_state_loop_status: ActivityStateLoopStatus::default(),
+            private_apps: Vec::new(),
+            title_policies: Vec::new(),
+            recording_path: None,
+            http_server_port: None,
+            #[cfg(unix)]
+            live_stream_path: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
@@ -24,16 +72,411 @@ impl MonitoringConfig {
         self
     }
 
-    pub async fn initialize(self) {
-        let activity_service =
-            Arc::new(services::activities_service::start_activities_monitoring(self.db_path).await);
-        activity_service
-            .register_receiver(self.monitor.subscribe())
-            .await;
-        activity_service.start_activity_state_loop(self.activity_state_interval);
+    /// Minimum input events, minimum active seconds and the idle timeout applied when an interval is
+    /// classified as active or idle (see `IdleThresholds`). By default any activity makes it active.
+    pub fn with_idle_thresholds(mut self, idle_thresholds: IdleThresholds) -> Self {
+        self.idle_thresholds = idle_thresholds;
+        self
+    }
+
+    /// How often activity states are aggregated into scored activity flow periods.
+    pub fn with_flow_period_interval(mut self, interval: Duration) -> Self {
+        self.flow_period_interval = interval;
+        self
+    }
+
+    /**
+     * Applies `retention_policy` at startup and then every hour: raw activities are rolled up into
+     * per-minute counts and old activity states, blocked activities and window titles are deleted
+     * (see `RetentionPolicy`). Off by default, so nothing is deleted.
+     */
+    pub fn with_retention(mut self, retention_policy: RetentionPolicy) -> Self {
+        self.retention_policy = Some(retention_policy);
+        self
+    }
+
+    /// Raw activities are buffered in memory and written in a single transaction once
+    /// `max_events` are pending or the oldest one is older than `max_age`.
+    pub fn with_write_buffer(mut self, max_events: usize, max_age: Duration) -> Self {
+        self.write_buffer_size = max_events;
+        self.write_buffer_max_age = max_age;
+        self
+    }
+
+    /// Apps and domains (e.g. a password manager or a banking site) that pause recording while focused.
+    pub fn with_private_apps(mut self, private_apps: Vec<String>) -> Self {
+        self.private_apps = private_apps;
+        self
+    }
+
+    /// Privacy policies applied to window titles before they are stored, e.g. dropping chat titles
+    /// or keeping only the project name of an editor (see `TitlePolicy`).
+    pub fn with_title_policies(mut self, title_policies: Vec<TitlePolicy>) -> Self {
+        self.title_policies = title_policies;
+        self
+    }
+
+    /// Appends every received event to an NDJSON recording at `path` (see `ReplaySource`).
+    pub fn with_event_recording(mut self, path: String) -> Self {
+        self.recording_path = Some(path);
+        self
+    }
 
-        std::thread::spawn(move || {
-            start_monitoring(self.monitor);
+    /// Serves the read-only JSON API (see `QueryServer`) on 127.0.0.1:`port`. Off by default.
+    pub fn with_http_server(mut self, port: u16) -> Self {
+        self.http_server_port = Some(port);
+        self
+    }
+
+    /// Streams window focus changes, closed activity states and blocked apps as NDJSON to every
+    /// process connected to the Unix domain socket at `path` (see `LiveStreamPublisher`).
+    #[cfg(unix)]
+    pub fn with_live_stream(mut self, path: String) -> Self {
+        self.live_stream_path = Some(path);
+        self
+    }
+
+    /// Replaces the wall clock used to timestamp activities and activity states.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Ingestion counters (e.g. events dropped because the receiver lagged). The returned
+    /// handle keeps reporting after `initialize` has consumed the config.
+    pub fn ingest_stats(&self) -> IngestStats {
+        self.ingest_stats.clone()
+    }
+
+    /// Failures of the activity state loop, which keeps running when a tick fails.
+    pub fn activity_state_loop_status(&self) -> ActivityStateLoopStatus {
+        self.activity_state_loop_status.clone()
+    }
+
+    /**
+     * Starts monitoring and returns the handle to stop it. Fails if an interval or the write buffer
+     * max age is zero, a title policy pattern is invalid or the database can't be opened or migrated;
+     * monitoring doesn't start in that case.
+     */
+    pub async fn initialize(self) -> Result<MonitoringHandle> {
+        for (name, duration) in [
+            ("activity state interval", self.activity_state_interval),
+            ("flow period interval", self.flow_period_interval),
+            ("write buffer max age", self.write_buffer_max_age),
+        ] {
+            if duration.is_zero() {
+                return Err(Error::Config(format!("{} must be greater than zero", name)));
+            }
+        }
+        let title_redactor = TitleRedactor::new(self.title_policies)
+            .map_err(|err| Error::Config(format!("invalid title policy pattern: {}", err)))?;
+        let db_manager = DbManager::new(&self.db_path).await?;
+        let title_redactor = title_redactor.with_hash_key(
+            InstallSecretRepo::new(db_manager.pool.clone())
+                .get_install_secret()
+                .await?,
+        );
+        let mut activity_service = ActivityService::new(db_manager.pool.clone())
+            .with_write_buffer(self.write_buffer_size, self.write_buffer_max_age)
+            .with_ingest_stats(self.ingest_stats)
+            .with_loop_status(self.activity_state_loop_status.clone())
+            .with_idle_thresholds(self.idle_thresholds)
+            .with_private_apps(self.private_apps)
+            .with_title_redactor(title_redactor)
+            .with_clock(self.clock.clone());
+        if let Some(recording_path) = self.recording_path {
+            match EventRecorder::open(&recording_path) {
+                Ok(event_recorder) => {
+                    activity_service =
+                        activity_service.with_event_recorder(Arc::new(event_recorder))
+                }
+                Err(err) => {
+                    log::error!("Failed to open event recording {}: {}", recording_path, err)
+                }
+            }
+        }
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut live_stream_task = None;
+        #[cfg(unix)]
+        if let Some(live_stream_path) = self.live_stream_path {
+            let live_stream = LiveStreamPublisher::new(256);
+            match live_stream.start(&live_stream_path, activity_service.subscribe_shutdown()) {
+                Ok(task) => {
+                    live_stream_task = Some(task);
+                    activity_service = activity_service.with_live_stream(Arc::new(live_stream))
+                }
+                Err(err) => log::error!(
+                    "Failed to start live stream on {}: {}",
+                    live_stream_path,
+                    err
+                ),
+            }
+        }
+        let activity_service = Arc::new(activity_service);
+        // subscribed first so events sent while backfilling aren't missed
+        let receiver_task = activity_service
+            .register_receiver(self.source.subscribe())
+            .await;
+        if let Err(err) = activity_service
+            .backfill_activity_states(self.activity_state_interval)
+            .await
+        {
+            log::error!("Failed to backfill activity states: {}", err);
+        }
+        let activity_state_task =
+            activity_service.start_activity_state_loop(self.activity_state_interval);
+        let flow_period_task = ActivityFlowPeriodService::new(db_manager.pool.clone())
+            .with_clock(self.clock.clone())
+            .start_flow_period_loop(
+                self.flow_period_interval,
+                activity_service.subscribe_shutdown(),
+            );
+        let retention_task = self.retention_policy.map(|retention_policy| {
+            RetentionService::new(db_manager.pool.clone())
+                .with_clock(self.clock)
+                .start_retention_loop(
+                    retention_policy,
+                    Duration::from_secs(60 * 60),
+                    activity_service.subscribe_shutdown(),
+                )
         });
+        let mut query_server_task = None;
+        if let Some(port) = self.http_server_port {
+            match QueryServer::new(db_manager.pool.clone())
+                .with_activity_state_loop_status(self.activity_state_loop_status)
+                .start(port, activity_service.subscribe_shutdown())
+                .await
+            {
+                Ok((_, task)) => query_server_task = Some(task),
+                Err(err) => log::error!("Failed to start query server on port {}: {}", port, err),
+            }
+        }
+
+        self.source.start();
+        Ok(MonitoringHandle {
+            activity_service,
+            receiver_task,
+            activity_state_task,
+            flow_period_task,
+            retention_task,
+            query_server_task,
+            live_stream_task,
+            pool: db_manager.pool,
+        })
+    }
+}
+
+/**
+ * Running monitoring started by `MonitoringConfig::initialize`. Dropping the handle leaves monitoring
+ * running; call `shutdown` to stop it.
+ */
+pub struct MonitoringHandle {
+    activity_service: Arc<ActivityService>,
+    receiver_task: JoinHandle<Result<()>>,
+    activity_state_task: JoinHandle<()>,
+    flow_period_task: JoinHandle<()>,
+    retention_task: Option<JoinHandle<()>>,
+    query_server_task: Option<JoinHandle<()>>,
+    live_stream_task: Option<JoinHandle<()>>,
+    pool: SqlitePool,
+}
+
+impl MonitoringHandle {
+    /**
+     * Pauses recording until `resume` is called. While paused, window titles and input aren't stored
+     * and activity states are recorded as `PAUSED`, so reports show the paused time.
+     */
+    pub fn pause(&self) {
+        self.activity_service.pause(None);
+    }
+
+    /// Pauses recording for `duration`, after which it resumes on its own.
+    pub fn pause_for(&self, duration: Duration) {
+        self.activity_service.pause(Some(duration));
+    }
+
+    pub async fn resume(&self) {
+        self.activity_service.resume().await;
+    }
+
+    /// Whether recording is paused, manually or because a private app is focused.
+    pub fn is_paused(&self) -> bool {
+        self.activity_service.is_paused()
+    }
+
+    /**
+     * Stops receiving events and every background loop (letting work in progress finish), closes the
+     * interval in progress as an activity state ending now, flushes pending writes and closes the
+     * database pool. The event source itself (e.g. the os_monitor thread) isn't stopped, its events
+     * are no longer received.
+     */
+    pub async fn shutdown(self) -> Result<()> {
+        self.activity_service.stop();
+        match self.receiver_task.await {
+            Ok(Ok(())) | Ok(Err(Error::SourceClosed)) => {}
+            Ok(Err(err)) => log::error!("Failed to stop the event receiver: {}", err),
+            Err(err) => log::error!("Event receiver task failed: {}", err),
+        }
+        let loop_tasks = [
+            ("Activity state loop", Some(self.activity_state_task)),
+            ("Flow period loop", Some(self.flow_period_task)),
+            ("Retention loop", self.retention_task),
+            ("Query server", self.query_server_task),
+            ("Live stream", self.live_stream_task),
+        ];
+        for (name, task) in loop_tasks {
+            let Some(task) = task else { continue };
+            if let Err(err) = task.await {
+                log::error!("{} task failed: {}", name, err);
+            }
+        }
+        let result = self.activity_service.close_activity_state().await;
+        self.pool.close().await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use os_monitor::{AppEvent, Platform, WindowEvent};
+
+    use super::*;
+    use crate::{
+        db::{
+            activity_state_repo::ActivityStateRepo, app_repo::AppRepo, models::ActivityStateType,
+        },
+        services::activity_state_service::ActivityPeriod,
+        sources::ChannelSource,
+        DbManager, ManualClock,
+    };
+
+    #[tokio::test]
+    async fn test_initialize_from_channel_source() {
+        let db_path = std::env::temp_dir()
+            .join(format!(
+                "os-monitor-service-{}.sqlite",
+                uuid::Uuid::new_v4()
+            ))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let source = ChannelSource::default();
+        MonitoringConfig::from_source(Arc::new(source.clone()), db_path.clone())
+            .initialize()
+            .await
+            .unwrap();
+
+        source.send(AppEvent::Window(WindowEvent {
+            app_name: "Replayed App".to_string(),
+            window_title: "main.rs".to_string(),
+            bundle_id: Some("com.replayed.app".to_string()),
+            url: None,
+            platform: Platform::Linux,
+        }));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let db_manager = DbManager::new(&db_path).await.unwrap();
+        let app = AppRepo::new(db_manager.pool)
+            .get_app_by_external_id("com.replayed.app")
+            .await
+            .unwrap();
+        assert_eq!(app.name, Some("Replayed App".to_string()));
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_rejects_zero_durations() {
+        let db_path = std::env::temp_dir()
+            .join(format!(
+                "os-monitor-service-{}.sqlite",
+                uuid::Uuid::new_v4()
+            ))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let monitoring_config =
+            || MonitoringConfig::from_source(Arc::new(ChannelSource::default()), db_path.clone());
+        for monitoring_config in [
+            monitoring_config().with_interval(Duration::ZERO),
+            monitoring_config().with_flow_period_interval(Duration::ZERO),
+            monitoring_config().with_write_buffer(16, Duration::ZERO),
+        ] {
+            assert!(matches!(
+                monitoring_config.initialize().await,
+                Err(Error::Config(_))
+            ));
+        }
+        // rejected before the database is opened
+        assert!(!std::path::Path::new(&db_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_activity_state() {
+        let db_path = std::env::temp_dir()
+            .join(format!(
+                "os-monitor-service-{}.sqlite",
+                uuid::Uuid::new_v4()
+            ))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let start = time::OffsetDateTime::now_utc()
+            .replace_nanosecond(0)
+            .unwrap();
+        let db_manager = DbManager::new(&db_path).await.unwrap();
+        ActivityStateRepo::new(db_manager.pool.clone())
+            .create_idle_activity_state(&ActivityPeriod {
+                start_time: start - Duration::from_secs(60),
+                end_time: start,
+            })
+            .await
+            .unwrap();
+        db_manager.pool.close().await;
+
+        let clock = Arc::new(ManualClock::new(start));
+        let source = ChannelSource::default();
+        let live_stream_path = format!("{}.sock", db_path);
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut monitoring_config =
+            MonitoringConfig::from_source(Arc::new(source.clone()), db_path.clone())
+                .with_clock(clock.clone())
+                .with_retention(RetentionPolicy::default())
+                .with_http_server(0);
+        #[cfg(unix)]
+        {
+            monitoring_config = monitoring_config.with_live_stream(live_stream_path.clone());
+        }
+        let monitoring = monitoring_config.initialize().await.unwrap();
+        clock.advance(Duration::from_secs(1));
+        source.send(AppEvent::Window(WindowEvent {
+            app_name: "Replayed App".to_string(),
+            window_title: "main.rs".to_string(),
+            bundle_id: Some("com.replayed.app".to_string()),
+            url: None,
+            platform: Platform::Linux,
+        }));
+        source.send(AppEvent::Keyboard(true));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        clock.advance(Duration::from_secs(29));
+        monitoring.shutdown().await.unwrap();
+
+        // the interval in progress is closed at shutdown instead of being lost
+        let db_manager = DbManager::new(&db_path).await.unwrap();
+        let activity_state = ActivityStateRepo::new(db_manager.pool.clone())
+            .get_last_activity_state()
+            .await
+            .unwrap();
+        assert_eq!(activity_state.state, ActivityStateType::Active);
+        assert_eq!(activity_state.start_time, Some(start));
+        assert_eq!(
+            activity_state.end_time,
+            Some(start + Duration::from_secs(30))
+        );
+        db_manager.pool.close().await;
+        // the live stream socket is removed along with the other loops
+        assert!(!std::path::Path::new(&live_stream_path).exists());
+
+        let _ = std::fs::remove_file(&db_path);
     }
 }
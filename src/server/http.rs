@@ -0,0 +1,161 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const MAX_REQUEST_HEAD_SIZE: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/**
+ * The parts of an HTTP/1.1 request the query server needs. Request bodies are ignored since
+ * every endpoint is a GET.
+ */
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub host: Option<String>,
+}
+
+impl Request {
+    /// A `+` in the query is kept as is rather than decoded to a space, so `offset=+05:00` works unescaped.
+    pub fn parse(head: &str) -> Option<Request> {
+        let mut lines = head.lines();
+        let request_line = lines.next()?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?.replace('+', "%2B");
+        let url = url::Url::parse(&format!("http://localhost{}", target)).ok()?;
+        let host = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+            .map(|(_, value)| value.trim().to_string());
+        Some(Request {
+            method,
+            path: url.path().trim_end_matches('/').to_string(),
+            query: url.query_pairs().into_owned().collect(),
+            host,
+        })
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(|value| value.as_str())
+    }
+
+    /**
+     * Whether the request was addressed to this machine by name (`localhost` or `127.0.0.1`, with
+     * any port), so pages on other sites can't reach the server through DNS rebinding.
+     */
+    pub fn is_local_host(&self) -> bool {
+        self.host.as_deref().is_some_and(|host| {
+            let host_name = host
+                .rsplit_once(':')
+                .map_or(host, |(host_name, _)| host_name);
+            host_name.eq_ignore_ascii_case("localhost") || host_name == "127.0.0.1"
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json<T: serde::Serialize>(value: &T) -> Response {
+        match serde_json::to_string(value) {
+            Ok(body) => Response { status: 200, body },
+            Err(err) => Response::error(500, &err.to_string()),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Fails with `TimedOut` if the request head isn't received within `REQUEST_TIMEOUT`.
+pub async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<Request>> {
+    let mut head = Vec::new();
+    let read_head = async {
+        let mut buf = [0u8; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&buf[..read]);
+            if head.len() > MAX_REQUEST_HEAD_SIZE {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    };
+    match tokio::time::timeout(REQUEST_TIMEOUT, read_head).await {
+        Ok(Ok(true)) => Ok(Request::parse(&String::from_utf8_lossy(&head))),
+        Ok(Ok(false)) => Ok(None),
+        Ok(Err(err)) => Err(err),
+        Err(_) => Err(std::io::ErrorKind::TimedOut.into()),
+    }
+}
+
+pub async fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = Request::parse(
+            "GET /summary/?start=2025-01-14T00%3A00%3A00Z&end=now HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/summary");
+        assert_eq!(request.query_param("start"), Some("2025-01-14T00:00:00Z"));
+        assert_eq!(request.query_param("end"), Some("now"));
+        assert!(request.is_local_host());
+        assert_eq!(Request::parse(""), None);
+
+        let request = Request::parse(
+            "GET /summary/daily?offset=+05:30 HTTP/1.1\r\nhost: 127.0.0.1:7070\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.query_param("offset"), Some("+05:30"));
+        assert!(request.is_local_host());
+        for head in [
+            "GET /apps HTTP/1.1\r\nHost: attacker.example:7070\r\n\r\n",
+            "GET /apps HTTP/1.1\r\n\r\n",
+        ] {
+            assert!(!Request::parse(head).unwrap().is_local_host());
+        }
+    }
+}
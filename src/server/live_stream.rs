@@ -0,0 +1,222 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+use crate::db::models::{ActivityState, Tag};
+
+/**
+ * Version of the live stream message schema. Bumped whenever a field is removed or changes meaning;
+ * new fields and message types may be added without a bump, so clients should ignore what they don't know.
+ */
+pub const LIVE_STREAM_VERSION: u32 = 1;
+
+/**
+ * Something the service observed, published to live stream subscribers as it happens.
+ */
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveStreamEvent {
+    WindowFocus {
+        app_id: String,
+        app_name: String,
+        app_external_id: String,
+        window_title: Option<String>,
+        tags: Vec<Tag>,
+    },
+    ActivityStateClosed(ActivityState),
+    AppBlocked {
+        apps: Vec<os_monitor::BlockedApp>,
+    },
+    /// Sent to a subscriber that fell behind; `dropped_messages` were skipped.
+    Lagged {
+        dropped_messages: u64,
+    },
+}
+
+#[derive(Serialize)]
+struct LiveStreamMessage<'a> {
+    version: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    timestamp: OffsetDateTime,
+    #[serde(flatten)]
+    event: &'a LiveStreamEvent,
+}
+
+/**
+ * Fans out `LiveStreamEvent`s as newline-delimited JSON to every connected subscriber, e.g.
+ * `{"version":1,"timestamp":"...","type":"window_focus","app_id":"...",...}`.
+ * Publishing never blocks the service: messages are dropped when nobody is listening, and slow
+ * subscribers are sent a `lagged` message instead of the messages they missed.
+ */
+pub struct LiveStreamPublisher {
+    sender: Sender<String>,
+}
+
+impl LiveStreamPublisher {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        LiveStreamPublisher { sender }
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub fn publish(&self, event: &LiveStreamEvent, timestamp: OffsetDateTime) {
+        if !self.has_subscribers() {
+            return;
+        }
+        match to_line(event, timestamp) {
+            Ok(line) => {
+                let _ = self.sender.send(line);
+            }
+            Err(err) => log::error!("Failed to serialize live stream event: {}", err),
+        }
+    }
+
+    /// Receives every published message as a JSON line (including the trailing newline).
+    pub fn subscribe(&self) -> Receiver<String> {
+        self.sender.subscribe()
+    }
+
+    /**
+     * Listens on a Unix domain socket at `path`, replacing a stale socket file left by a previous run,
+     * and streams every published message to each connected client. Fails rather than removing
+     * anything at `path` that isn't a socket. The socket is only accessible to the current user.
+     * Stops accepting clients and removes the socket file once `true` is sent on `shutdown`; connected
+     * clients are streamed the remaining messages until the publisher is dropped.
+     */
+    #[cfg(unix)]
+    pub fn start(
+        &self,
+        path: &str,
+        mut shutdown: tokio::sync::watch::Receiver<bool>,
+    ) -> std::io::Result<tokio::task::JoinHandle<()>> {
+        use crate::utils::shutdown::stopped;
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path),
+                ))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let listener = tokio::net::UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        let sender = self.sender.clone();
+        let path = path.to_string();
+        Ok(tokio::spawn(async move {
+            loop {
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = stopped(&mut shutdown) => break,
+                };
+                match accepted {
+                    Ok((stream, _)) => {
+                        tokio::spawn(stream_to_subscriber(stream, sender.subscribe()));
+                    }
+                    Err(err) => log::error!("Failed to accept live stream subscriber: {}", err),
+                }
+            }
+            drop(listener);
+            if let Err(err) = std::fs::remove_file(&path) {
+                log::warn!("Failed to remove live stream socket {}: {}", path, err);
+            }
+        }))
+    }
+}
+
+fn to_line(event: &LiveStreamEvent, timestamp: OffsetDateTime) -> serde_json::Result<String> {
+    let mut line = serde_json::to_string(&LiveStreamMessage {
+        version: LIVE_STREAM_VERSION,
+        timestamp,
+        event,
+    })?;
+    line.push('\n');
+    Ok(line)
+}
+
+#[cfg(unix)]
+async fn stream_to_subscriber(mut stream: tokio::net::UnixStream, mut receiver: Receiver<String>) {
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        let line = match receiver.recv().await {
+            Ok(line) => line,
+            Err(RecvError::Lagged(dropped_messages)) => {
+                match to_line(
+                    &LiveStreamEvent::Lagged { dropped_messages },
+                    OffsetDateTime::now_utc(),
+                ) {
+                    Ok(line) => line,
+                    Err(_) => continue,
+                }
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if stream.write_all(line.as_bytes()).await.is_err() {
+            log::trace!("live stream subscriber disconnected");
+            break;
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        sync::watch,
+    };
+
+    use super::*;
+    use crate::db::models::ActivityStateType;
+
+    #[tokio::test]
+    async fn test_live_stream_over_unix_socket() {
+        let path = std::env::temp_dir()
+            .join(format!("os-monitor-service-{}.sock", uuid::Uuid::new_v4()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let publisher = LiveStreamPublisher::new(16);
+        let (shutdown, shutdown_receiver) = watch::channel(false);
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(publisher.start(&path, shutdown_receiver.clone()).is_err());
+        std::fs::remove_file(&path).unwrap();
+        let live_stream_task = publisher.start(&path, shutdown_receiver).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        while !publisher.has_subscribers() {
+            tokio::task::yield_now().await;
+        }
+
+        let mut activity_state = ActivityState::new();
+        activity_state.state = ActivityStateType::Active;
+        publisher.publish(
+            &LiveStreamEvent::ActivityStateClosed(activity_state),
+            OffsetDateTime::now_utc(),
+        );
+
+        let line = lines.next_line().await.unwrap().unwrap();
+        let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(message["version"], LIVE_STREAM_VERSION);
+        assert_eq!(message["type"], "activity_state_closed");
+        assert_eq!(message["state"], "ACTIVE");
+
+        // the socket file is removed on shutdown
+        shutdown.send_replace(true);
+        live_stream_task.await.unwrap();
+        assert!(!std::path::Path::new(&path).exists());
+    }
+}
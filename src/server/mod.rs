@@ -0,0 +1,415 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use time::{
+    format_description::{self, well_known::Rfc3339},
+    Date, OffsetDateTime, UtcOffset,
+};
+use tokio::{
+    net::TcpListener,
+    sync::watch,
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{
+    db::{
+        activity_gap_repo::ActivityGapRepo, activity_state_repo::ActivityStateRepo,
+        app_repo::AppRepo, blocked_activity_repo::BlockedActivityRepo, tag_repo::TagRepo,
+    },
+    error::Error,
+    services::{
+        activities_service::ActivityStateLoopStatus,
+        activity_flow_period_service::ActivityFlowPeriodService, report_service::ReportService,
+    },
+    utils::shutdown::stopped,
+};
+
+mod http;
+pub mod live_stream;
+
+use http::{Request, Response};
+
+// keeps daily and weekly summary requests from running one report per day for centuries
+const MAX_SUMMARY_DAYS: i64 = 366;
+
+/**
+ * Read-only HTTP/JSON server over the service database, bound to 127.0.0.1 so local dashboards and
+ * scripts don't need to open the SQLite file themselves. Requests must have a `localhost` or
+ * `127.0.0.1` Host header.
+ *
+ * Endpoints (all GET):
+ * - `/health`, which reports `degraded` while the activity state loop is failing
+ * - `/activity-states?start=<rfc3339>&end=<rfc3339>`
+ * - `/activity-flow-periods?start=<rfc3339>&end=<rfc3339>`
+ * - `/activity-gaps?start=<rfc3339>&end=<rfc3339>`
+ * - `/apps`, `/tags`, `/blocked-activities`
+ * - `/summary?start=<rfc3339>&end=<rfc3339>`
+ * - `/summary/daily` and `/summary/weekly` with `start_date=<YYYY-MM-DD>&end_date=<YYYY-MM-DD>&offset=<+HH:MM>`,
+ *   spanning at most a year
+ */
+#[derive(Clone)]
+pub struct QueryServer {
+    activity_state_repo: ActivityStateRepo,
+    activity_gap_repo: ActivityGapRepo,
+    app_repo: AppRepo,
+    tag_repo: TagRepo,
+    blocked_activity_repo: BlockedActivityRepo,
+    activity_flow_period_service: ActivityFlowPeriodService,
+    report_service: ReportService,
+    activity_state_loop_status: Option<ActivityStateLoopStatus>,
+}
+
+impl QueryServer {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        QueryServer {
+            activity_state_repo: ActivityStateRepo::new(pool.clone()),
+            activity_gap_repo: ActivityGapRepo::new(pool.clone()),
+            app_repo: AppRepo::new(pool.clone()),
+            tag_repo: TagRepo::new(pool.clone()),
+            blocked_activity_repo: BlockedActivityRepo::new(pool.clone()),
+            activity_flow_period_service: ActivityFlowPeriodService::new(pool.clone()),
+            report_service: ReportService::new(pool),
+            activity_state_loop_status: None,
+        }
+    }
+
+    pub fn with_activity_state_loop_status(
+        mut self,
+        activity_state_loop_status: ActivityStateLoopStatus,
+    ) -> Self {
+        self.activity_state_loop_status = Some(activity_state_loop_status);
+        self
+    }
+
+    /**
+     * Binds to 127.0.0.1 on the given port (0 picks a free port) and serves requests on a
+     * background task until `true` is sent on `shutdown`. Returns the bound address and the task,
+     * which ends once the requests in progress are answered.
+     */
+    pub async fn start(
+        self,
+        port: u16,
+        shutdown: watch::Receiver<bool>,
+    ) -> std::io::Result<(SocketAddr, JoinHandle<()>)> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let local_addr = listener.local_addr()?;
+        log::trace!("query server listening on {}", local_addr);
+        let server_task = tokio::spawn(async move { self.serve(listener, shutdown).await });
+        Ok((local_addr, server_task))
+    }
+
+    async fn serve(self, listener: TcpListener, mut shutdown: watch::Receiver<bool>) {
+        let mut connections = JoinSet::new();
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                Some(_) = connections.join_next() => continue,
+                _ = stopped(&mut shutdown) => break,
+            };
+            let (mut stream, _) = match accepted {
+                Ok(connection) => connection,
+                Err(err) => {
+                    log::error!("Failed to accept query server connection: {}", err);
+                    continue;
+                }
+            };
+            let server = self.clone();
+            connections.spawn(async move {
+                let response = match http::read_request(&mut stream).await {
+                    Ok(Some(request)) => server.handle_request(&request).await,
+                    Ok(None) => Response::error(400, "malformed request"),
+                    Err(err) if err.kind() == std::io::ErrorKind::TimedOut => {
+                        Response::error(408, "request timeout")
+                    }
+                    Err(err) => {
+                        log::error!("Failed to read query server request: {}", err);
+                        return;
+                    }
+                };
+                if let Err(err) = http::write_response(&mut stream, &response).await {
+                    log::error!("Failed to write query server response: {}", err);
+                }
+            });
+        }
+        while connections.join_next().await.is_some() {}
+    }
+
+    async fn handle_request(&self, request: &Request) -> Response {
+        if !request.is_local_host() {
+            return Response::error(403, "invalid host");
+        }
+        if request.method != "GET" {
+            return Response::error(405, "only GET is supported");
+        }
+        self.route(request)
+            .await
+            .unwrap_or_else(|error_response| error_response)
+    }
+
+    async fn route(&self, request: &Request) -> Result<Response, Response> {
+        let response = match request.path.as_str() {
+            "/health" => match &self.activity_state_loop_status {
+                Some(loop_status) => Response::json(&serde_json::json!({
+                    "status": if loop_status.is_healthy() { "ok" } else { "degraded" },
+                    "activity_state_loop": loop_status.snapshot(),
+                })),
+                None => Response::json(&serde_json::json!({ "status": "ok" })),
+            },
+            "/activity-states" => {
+                let (start_time, end_time) = time_range(request)?;
+                Response::json(
+                    &self
+                        .activity_state_repo
+                        .get_activity_states_starting_between(start_time, end_time)
+                        .await
+                        .map_err(database_error)?,
+                )
+            }
+            "/activity-flow-periods" => {
+                let (start_time, end_time) = time_range(request)?;
+                Response::json(
+                    &self
+                        .activity_flow_period_service
+                        .get_activity_flow_periods_between(start_time, end_time)
+                        .await
+                        .map_err(database_error)?,
+                )
+            }
+            "/activity-gaps" => {
+                let (start_time, end_time) = time_range(request)?;
+                Response::json(
+                    &self
+                        .activity_gap_repo
+                        .get_activity_gaps_between(start_time, end_time)
+                        .await
+                        .map_err(database_error)?,
+                )
+            }
+            "/apps" => Response::json(&self.app_repo.get_all_apps().await.map_err(database_error)?),
+            "/tags" => Response::json(&self.tag_repo.get_all_tags().await.map_err(database_error)?),
+            "/blocked-activities" => Response::json(
+                &self
+                    .blocked_activity_repo
+                    .get_all_blocked_activities()
+                    .await
+                    .map_err(database_error)?,
+            ),
+            "/summary" => {
+                let (start_time, end_time) = time_range(request)?;
+                Response::json(
+                    &self
+                        .report_service
+                        .get_usage_summary(start_time, end_time)
+                        .await
+                        .map_err(database_error)?,
+                )
+            }
+            "/summary/daily" => {
+                let (start_date, end_date, offset) = date_range(request)?;
+                Response::json(
+                    &self
+                        .report_service
+                        .get_daily_summaries(start_date, end_date, |date| {
+                            date.midnight().assume_offset(offset)
+                        })
+                        .await
+                        .map_err(database_error)?,
+                )
+            }
+            "/summary/weekly" => {
+                let (start_date, end_date, offset) = date_range(request)?;
+                Response::json(
+                    &self
+                        .report_service
+                        .get_weekly_summaries(start_date, end_date, |date| {
+                            date.midnight().assume_offset(offset)
+                        })
+                        .await
+                        .map_err(database_error)?,
+                )
+            }
+            _ => Response::error(404, "not found"),
+        };
+        Ok(response)
+    }
+}
+
+fn database_error(err: Error) -> Response {
+    if let Error::Validation(reason) = &err {
+        return Response::error(400, reason);
+    }
+    log::error!("Query server database error: {}", err);
+    Response::error(500, "database error")
+}
+
+fn required_param<'a>(request: &'a Request, name: &str) -> Result<&'a str, Response> {
+    request
+        .query_param(name)
+        .ok_or_else(|| Response::error(400, &format!("missing query parameter `{}`", name)))
+}
+
+fn time_range(request: &Request) -> Result<(OffsetDateTime, OffsetDateTime), Response> {
+    let parse = |name: &str| {
+        let value = required_param(request, name)?;
+        OffsetDateTime::parse(value, &Rfc3339)
+            .map(|time| time.to_offset(UtcOffset::UTC))
+            .map_err(|_| Response::error(400, &format!("`{}` must be an RFC 3339 time", name)))
+    };
+    Ok((parse("start")?, parse("end")?))
+}
+
+fn date_range(request: &Request) -> Result<(Date, Date, UtcOffset), Response> {
+    let date_format = format_description::parse("[year]-[month]-[day]")
+        .map_err(|err| Response::error(500, &err.to_string()))?;
+    let parse_date = |name: &str| {
+        let value = required_param(request, name)?;
+        Date::parse(value, &date_format)
+            .map_err(|_| Response::error(400, &format!("`{}` must be a YYYY-MM-DD date", name)))
+    };
+    let offset = match request.query_param("offset") {
+        Some(value) => parse_offset(value)
+            .ok_or_else(|| Response::error(400, "`offset` must be formatted as +HH:MM"))?,
+        None => UtcOffset::UTC,
+    };
+    let (start_date, end_date) = (parse_date("start_date")?, parse_date("end_date")?);
+    if (end_date - start_date).whole_days() >= MAX_SUMMARY_DAYS {
+        return Err(Response::error(
+            400,
+            &format!("the date range must span at most {} days", MAX_SUMMARY_DAYS),
+        ));
+    }
+    Ok((start_date, end_date, offset))
+}
+
+fn parse_offset(value: &str) -> Option<UtcOffset> {
+    let offset_format =
+        format_description::parse("[offset_hour sign:mandatory]:[offset_minute]").ok()?;
+    UtcOffset::parse(value, &offset_format).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::{
+        db::{db_manager, models::ActivityState},
+        services::activity_state_service::ActivityPeriod,
+    };
+
+    async fn get(addr: SocketAddr, target: &str) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(
+            parse_offset("-05:00"),
+            Some(UtcOffset::from_hms(-5, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_offset("+05:30"),
+            Some(UtcOffset::from_hms(5, 30, 0).unwrap())
+        );
+        assert_eq!(parse_offset("05:30"), None);
+        assert_eq!(parse_offset("five"), None);
+    }
+
+    #[tokio::test]
+    async fn test_query_server() {
+        let pool = db_manager::create_test_db().await;
+        let activity_state_repo = ActivityStateRepo::new(pool.clone());
+        let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+        activity_state_repo
+            .create_active_activity_state(
+                2,
+                None,
+                &ActivityPeriod {
+                    start_time: now - Duration::from_secs(60),
+                    end_time: now,
+                },
+            )
+            .await
+            .unwrap();
+        activity_state_repo
+            .save_activity_state(&ActivityState::new())
+            .await
+            .unwrap();
+        let (shutdown, shutdown_receiver) = watch::channel(false);
+        let (addr, server_task) = QueryServer::new(pool)
+            .start(0, shutdown_receiver)
+            .await
+            .unwrap();
+        assert!(addr.ip().is_loopback());
+
+        let (status, body) = get(addr, "/health").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "ok");
+
+        let start = (now - Duration::from_secs(90)).format(&Rfc3339).unwrap();
+        let end = (now + Duration::from_secs(1)).format(&Rfc3339).unwrap();
+        let (status, body) = get(
+            addr,
+            &format!("/activity-states?start={}&end={}", start, end),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["state"], "ACTIVE");
+        assert_eq!(body[0]["app_switches"], 2);
+
+        let (status, body) = get(addr, &format!("/summary?start={}&end={}", start, end)).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["active_seconds"], 60);
+
+        let (status, body) = get(addr, "/tags").await;
+        assert_eq!(status, 200);
+        assert!(body
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|tag| tag["name"] == "creating"));
+
+        let (status, body) = get(
+            addr,
+            "/summary/daily?start_date=2025-01-14&end_date=2025-01-15&offset=-05:00",
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+
+        let (status, _) = get(
+            addr,
+            "/summary/weekly?start_date=2025-01-14&end_date=9999-12-31&offset=+05:00",
+        )
+        .await;
+        assert_eq!(status, 400);
+        let (status, _) = get(
+            addr,
+            "/summary/daily?start_date=9999-12-31&end_date=9999-12-31&offset=-05:00",
+        )
+        .await;
+        assert_eq!(status, 400);
+
+        let (status, _) = get(addr, "/activity-states?start=yesterday").await;
+        assert_eq!(status, 400);
+        let (status, _) = get(addr, "/unknown").await;
+        assert_eq!(status, 404);
+
+        // stops accepting connections on shutdown
+        shutdown.send_replace(true);
+        server_task.await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
@@ -1,33 +1,131 @@
-use std::time::Duration;
-use tokio::sync::broadcast::Receiver;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        broadcast::{error::RecvError, Receiver},
+        watch,
+    },
+    task::JoinHandle,
+};
 
-use once_cell::sync::Lazy;
 use os_monitor::{AppEvent, BlockedAppEvent, KeyboardEvent, MouseEvent, WindowEvent};
 use parking_lot::Mutex;
 
 use crate::db::{
+    activity_gap_repo::ActivityGapRepo,
     activity_repo::ActivityRepo,
     activity_state_repo::ActivityStateRepo,
     blocked_activity_repo::BlockedActivityRepo,
-    db_manager,
-    models::{Activity, BlockedActivity},
+    ingest_lag_repo::IngestLagRepo,
+    models::{Activity, ActivityType, App, BlockedActivity},
+};
+use crate::{
+    error::{Error, Result},
+    server::live_stream::{LiveStreamEvent, LiveStreamPublisher},
+    sources::EventRecorder,
+    utils::{
+        clock::{Clock, ManualClock, SystemClock},
+        retry::retry_transient,
+        shutdown::stopped,
+    },
 };
 
 use self::activity_state_service::ActivityPeriod;
 
 use super::{
-    activity_state_service::{self, ActivityStateService},
+    activity_buffer_service::{self, ActivityBuffer},
+    activity_state_service::{self, ActivityStateService, IdleThresholds},
     app_service::AppService,
     app_switch_service::AppSwitchState,
+    pause_service::PauseState,
+    title_policy_service::TitleRedactor,
 };
 
 #[cfg(test)]
 use crate::db::models::ActivityState;
-#[cfg(test)]
 use time::OffsetDateTime;
 
-static APP_SWITCH_STATE: Lazy<Mutex<AppSwitchState>> =
-    Lazy::new(|| Mutex::new(AppSwitchState::new(Duration::from_secs(2))));
+// window switches closer together than this are counted once
+const MIN_TIME_BETWEEN_APP_SWITCHES: Duration = Duration::from_secs(2);
+
+/**
+ * Counters describing the health of event ingestion.
+ * Cloning shares the underlying counters, so a clone can be handed out before monitoring starts.
+ */
+#[derive(Clone, Default, Debug)]
+pub struct IngestStats {
+    dropped_events: Arc<AtomicU64>,
+    lag_occurrences: Arc<AtomicU64>,
+}
+
+impl IngestStats {
+    /// Total number of events dropped because the receiver lagged behind the event channel.
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the receiver lagged behind the event channel.
+    pub fn lag_occurrences(&self) -> u64 {
+        self.lag_occurrences.load(Ordering::Relaxed)
+    }
+
+    fn record_lag(&self, dropped_events: u64) {
+        self.dropped_events
+            .fetch_add(dropped_events, Ordering::Relaxed);
+        self.lag_occurrences.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+pub struct ActivityStateLoopSnapshot {
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_success_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_failure_at: Option<OffsetDateTime>,
+    pub last_error: Option<String>,
+    /// Failed ticks since the last successful one.
+    pub consecutive_failures: u64,
+    pub total_failures: u64,
+}
+
+/**
+ * Health of the activity state loop. Failed ticks are recorded here instead of stopping the loop.
+ * Cloning shares the status, so a clone can be handed out before monitoring starts.
+ */
+#[derive(Clone, Default, Debug)]
+pub struct ActivityStateLoopStatus {
+    snapshot: Arc<Mutex<ActivityStateLoopSnapshot>>,
+}
+
+impl ActivityStateLoopStatus {
+    pub fn snapshot(&self) -> ActivityStateLoopSnapshot {
+        self.snapshot.lock().clone()
+    }
+
+    /// Whether the last tick succeeded (or none has run yet).
+    pub fn is_healthy(&self) -> bool {
+        self.snapshot.lock().consecutive_failures == 0
+    }
+
+    fn record_success(&self, now: OffsetDateTime) {
+        let mut snapshot = self.snapshot.lock();
+        snapshot.last_success_at = Some(now);
+        snapshot.consecutive_failures = 0;
+    }
+
+    fn record_failure(&self, err: String, now: OffsetDateTime) {
+        let mut snapshot = self.snapshot.lock();
+        snapshot.last_failure_at = Some(now);
+        snapshot.last_error = Some(err);
+        snapshot.consecutive_failures += 1;
+        snapshot.total_failures += 1;
+    }
+}
 
 #[derive(Clone)]
 pub struct ActivityService {
@@ -36,6 +134,22 @@ pub struct ActivityService {
     app_service: AppService,
     activity_state_service: ActivityStateService,
     blocked_activity_repo: BlockedActivityRepo,
+    ingest_lag_repo: IngestLagRepo,
+    activity_buffer: Arc<tokio::sync::Mutex<ActivityBuffer>>,
+    ingest_stats: IngestStats,
+    event_recorder: Option<Arc<EventRecorder>>,
+    live_stream: Option<Arc<LiveStreamPublisher>>,
+    app_switch_state: Arc<Mutex<AppSwitchState>>,
+    idle_thresholds: IdleThresholds,
+    activity_gap_repo: ActivityGapRepo,
+    last_tick: Arc<Mutex<Option<Instant>>>,
+    loop_status: ActivityStateLoopStatus,
+    shutdown: Arc<watch::Sender<bool>>,
+    pause_state: Arc<Mutex<PauseState>>,
+    // the last window focused while paused, recorded once recording resumes
+    paused_window: Arc<Mutex<Option<WindowEvent>>>,
+    title_redactor: Arc<TitleRedactor>,
+    clock: Arc<dyn Clock>,
 }
 
 impl ActivityService {
@@ -45,56 +159,218 @@ impl ActivityService {
         let activity_state_service = ActivityStateService::new(pool.clone());
         let app_service = AppService::new(pool.clone());
         let blocked_activity_repo = BlockedActivityRepo::new(pool.clone());
-        let service = ActivityService {
+        let ingest_lag_repo = IngestLagRepo::new(pool.clone());
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        ActivityService {
             activities_repo,
             activity_state_repo,
             app_service,
             activity_state_service,
             blocked_activity_repo,
+            ingest_lag_repo,
+            ingest_stats: IngestStats::default(),
+            event_recorder: None,
+            live_stream: None,
+            app_switch_state: Arc::new(Mutex::new(AppSwitchState::new(
+                MIN_TIME_BETWEEN_APP_SWITCHES,
+                clock.clone(),
+            ))),
+            idle_thresholds: IdleThresholds::default(),
+            activity_gap_repo: ActivityGapRepo::new(pool.clone()),
+            last_tick: Arc::new(Mutex::new(None)),
+            loop_status: ActivityStateLoopStatus::default(),
+            shutdown: Arc::new(watch::channel(false).0),
+            pause_state: Arc::new(Mutex::new(PauseState::new(Vec::new()))),
+            paused_window: Arc::new(Mutex::new(None)),
+            title_redactor: Arc::new(TitleRedactor::default()),
+            clock,
+            activity_buffer: Arc::new(tokio::sync::Mutex::new(ActivityBuffer::new(
+                activity_buffer_service::DEFAULT_MAX_SIZE,
+                activity_buffer_service::DEFAULT_MAX_AGE,
+            ))),
+        }
+    }
+
+    /**
+     * Configures when buffered activities are written: as soon as `max_events` are pending
+     * or the oldest pending activity is older than `max_age`.
+     */
+    pub fn with_write_buffer(mut self, max_events: usize, max_age: Duration) -> Self {
+        self.activity_buffer = Arc::new(tokio::sync::Mutex::new(ActivityBuffer::new(
+            max_events, max_age,
+        )));
+        self
+    }
+
+    pub fn with_ingest_stats(mut self, ingest_stats: IngestStats) -> Self {
+        self.ingest_stats = ingest_stats;
+        self
+    }
+
+    /// Tees every received event to a recording that can later be replayed with `ReplaySource`.
+    pub fn with_event_recorder(mut self, event_recorder: Arc<EventRecorder>) -> Self {
+        self.event_recorder = Some(event_recorder);
+        self
+    }
+
+    /// Publishes window focus changes, closed activity states and blocked apps as they happen.
+    pub fn with_live_stream(mut self, live_stream: Arc<LiveStreamPublisher>) -> Self {
+        self.live_stream = Some(live_stream);
+        self
+    }
+
+    pub fn with_loop_status(mut self, loop_status: ActivityStateLoopStatus) -> Self {
+        self.loop_status = loop_status;
+        self
+    }
+
+    /// Thresholds deciding whether the activities of an interval make an active state.
+    pub fn with_idle_thresholds(mut self, idle_thresholds: IdleThresholds) -> Self {
+        self.idle_thresholds = idle_thresholds;
+        self
+    }
+
+    /// Uses `clock` for every timestamp the service creates, including the activity state periods.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.activity_state_service = self.activity_state_service.with_clock(clock.clone());
+        self.app_switch_state = Arc::new(Mutex::new(AppSwitchState::new(
+            MIN_TIME_BETWEEN_APP_SWITCHES,
+            clock.clone(),
+        )));
+        self.clock = clock;
+        self
+    }
+
+    /**
+     * Apps and domains (e.g. a password manager or a banking site) during whose focus recording is
+     * paused. Matched against the app name, bundle id and the domain of the url, including subdomains.
+     */
+    pub fn with_private_apps(mut self, private_apps: Vec<String>) -> Self {
+        self.pause_state = Arc::new(Mutex::new(PauseState::new(private_apps)));
+        self
+    }
+
+    /// Redacts window titles (see `TitlePolicy`) before they are recorded, stored or published.
+    pub(crate) fn with_title_redactor(mut self, title_redactor: TitleRedactor) -> Self {
+        self.title_redactor = Arc::new(title_redactor);
+        self
+    }
+
+    /**
+     * Pauses recording until `resume` is called, or for `duration`. While paused, window titles and
+     * input aren't stored and the activity states are recorded as `PAUSED`.
+     */
+    pub fn pause(&self, duration: Option<Duration>) {
+        let now = self.clock.now();
+        self.pause_state
+            .lock()
+            .pause(now, duration.map(|duration| now + duration));
+    }
+
+    /// Resumes recording, starting with the window focused while paused.
+    pub async fn resume(&self) {
+        self.pause_state.lock().resume(self.clock.now());
+        self.record_paused_window().await;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause_state.lock().is_paused(self.clock.now())
+    }
+
+    // once recording resumes (including when a timed pause ran out), the window focused meanwhile is stored
+    async fn record_paused_window(&self) {
+        if self.is_paused() {
+            return;
+        }
+        let paused_window = self.paused_window.lock().take();
+        if let Some(paused_window) = paused_window {
+            if let Err(err) = self.handle_window_activity(paused_window).await {
+                log::error!("Failed to handle window activity after pause: {}", err);
+            }
+        }
+    }
+
+    /// Drops window titles and input while paused, keeping track of private app focus.
+    async fn receive_event(&self, event: AppEvent) {
+        let is_private = match &event {
+            AppEvent::Window(window) => self.pause_state.lock().is_private(window),
+            _ => false,
         };
-        service
+        if let AppEvent::Window(_) = event {
+            self.pause_state
+                .lock()
+                .set_private_app_focused(self.clock.now(), is_private);
+            if is_private {
+                *self.paused_window.lock() = None;
+            }
+        }
+        self.record_paused_window().await;
+        if self.is_paused() {
+            match event {
+                AppEvent::Window(window) if !is_private => {
+                    *self.paused_window.lock() = Some(window)
+                }
+                AppEvent::AppBlocked(event) => self.handle_app_blocked_activity(event).await,
+                _ => {}
+            }
+            return;
+        }
+        self.record_event(&event);
+        self.handle_event(event).await
     }
 
     async fn handle_keyboard_activity(&self, event: KeyboardEvent) {
         log::trace!("{}: {:?}", "handle_keyboard_activity", event);
-        let activity = Activity::create_keyboard_activity(&event);
-        if let Err(err) = self.save_activity(&activity).await {
-            log::error!("Failed to save keyboard activity: {}", err);
-        }
+        let activity = Activity::create_keyboard_activity(&event, self.clock.now());
+        self.buffer_activity(activity).await;
     }
 
     async fn handle_mouse_activity(&self, event: MouseEvent) {
         log::trace!("{}: {:?}", "handle_mouse_activity", event);
-        let activity = Activity::create_mouse_activity(&event);
-        if let Err(err) = self.save_activity(&activity).await {
-            log::error!("Failed to save mouse activity: {}", err);
-        }
+        let activity = Activity::create_mouse_activity(&event, self.clock.now());
+        self.buffer_activity(activity).await;
     }
 
-    pub async fn handle_window_activity(&self, event: WindowEvent) {
+    /**
+     * Records a window focus change, with its title redacted by the title policies and the tag rules it
+     * matched before redaction. Fails if the app can't be looked up or created, the tag rules can't be
+     * loaded, or the window activity can't be saved.
+     */
+    pub async fn handle_window_activity(&self, event: WindowEvent) -> Result<()> {
         log::trace!("{}: {:?}", "handle_window_activity", event);
-        let app_id = self.app_service.handle_window_event(&event).await;
-        if let Ok(app_id) = app_id {
-            let activity = Activity::create_window_activity(&event, Some(app_id));
-            if let Err(err) = self.save_activity(&activity).await {
-                log::error!("Failed to save window activity: {}", err);
-            }
-            let mut app_switch_state = APP_SWITCH_STATE.lock();
-            app_switch_state.new_window_activity(activity);
-        } else {
-            log::error!("Failed to get or create app id");
-        }
+        let app_id = self.app_service.handle_window_event(&event).await?;
+        let mut activity =
+            Activity::create_window_activity(&event, Some(app_id.clone()), self.clock.now());
+        activity.app_window_title = self.title_redactor.redact(&event);
+        activity.tag_rule_ids = self.app_service.match_tag_rules(&event).await?;
+        self.publish_window_focus(&event, &activity, app_id).await;
+        {
+            let mut app_switch_state = self.app_switch_state.lock();
+            app_switch_state.new_window_activity(activity.clone());
+        } // lock is released here
+          // window changes are infrequent and other lookups depend on the last window, so write them right away
+        self.buffer_activity(activity).await;
+        self.flush_activities().await?;
+        Ok(())
     }
 
     async fn handle_app_blocked_activity(&self, event: BlockedAppEvent) {
         log::trace!("{}: {:?}", "handle_app_blocked_activity", event);
+        if let Some(live_stream) = &self.live_stream {
+            live_stream.publish(
+                &LiveStreamEvent::AppBlocked {
+                    apps: event.blocked_apps.clone(),
+                },
+                self.clock.now(),
+            );
+        }
 
         for blocked_app in event.blocked_apps {
             let blocked_activity = BlockedActivity {
                 id: uuid::Uuid::new_v4().to_string(),
                 external_app_id: blocked_app.app_external_id,
-                created_at: Some(time::OffsetDateTime::now_utc()),
-                updated_at: Some(time::OffsetDateTime::now_utc()),
+                created_at: Some(self.clock.now()),
+                updated_at: Some(self.clock.now()),
             };
 
             if let Err(err) = self
@@ -107,42 +383,212 @@ impl ActivityService {
         }
     }
 
-    pub async fn register_receiver(&self, mut event_receiver: Receiver<AppEvent>) {
+    async fn publish_window_focus(&self, event: &WindowEvent, activity: &Activity, app_id: String) {
+        let Some(live_stream) = &self.live_stream else {
+            return;
+        };
+        if !live_stream.has_subscribers() {
+            return;
+        }
+        let tags = match self.app_service.get_tags_for_app(&app_id).await {
+            Ok(tags) => tags,
+            Err(err) => {
+                log::error!("Failed to get tags for live stream: {}", err);
+                Vec::new()
+            }
+        };
+        live_stream.publish(
+            &LiveStreamEvent::WindowFocus {
+                app_id,
+                app_name: event.app_name.clone(),
+                app_external_id: App::new(event).app_external_id,
+                window_title: activity.app_window_title.clone(),
+                tags,
+            },
+            self.clock.now(),
+        );
+    }
+
+    async fn publish_activity_state_closed(&self) {
+        let Some(live_stream) = &self.live_stream else {
+            return;
+        };
+        match self.activity_state_service.get_last_activity_state().await {
+            Ok(activity_state) => live_stream.publish(
+                &LiveStreamEvent::ActivityStateClosed(activity_state),
+                self.clock.now(),
+            ),
+            Err(err) => log::error!("Failed to get activity state for live stream: {}", err),
+        }
+    }
+
+    /**
+     * Called when the receiver fell behind the broadcast channel and `dropped_events` were overwritten.
+     * The loss is counted and persisted so data quality issues are visible, and consumption continues.
+     */
+    async fn handle_lagged_events(&self, dropped_events: u64) {
+        log::warn!(
+            "Event receiver lagged, {} events were dropped",
+            dropped_events
+        );
+        self.ingest_stats.record_lag(dropped_events);
+        if let Err(err) = self
+            .ingest_lag_repo
+            .save_ingest_lag(dropped_events as i64, self.clock.now())
+            .await
+        {
+            log::error!("Failed to save ingest lag: {}", err);
+        }
+    }
+
+    fn record_event(&self, event: &AppEvent) {
+        if let Some(event_recorder) = &self.event_recorder {
+            let redacted_event;
+            let event = match event {
+                AppEvent::Window(window) if !self.title_redactor.is_empty() => {
+                    redacted_event = AppEvent::Window(WindowEvent {
+                        window_title: self.title_redactor.redact(window).unwrap_or_default(),
+                        ..window.clone()
+                    });
+                    &redacted_event
+                }
+                event => event,
+            };
+            if let Err(err) = event_recorder.record(event, self.clock.now()) {
+                log::error!("Failed to record event: {}", err);
+            }
+        }
+    }
+
+    async fn handle_event(&self, event: AppEvent) {
+        match event {
+            AppEvent::Mouse(event) => {
+                if event {
+                    self.handle_mouse_activity(MouseEvent {}).await
+                }
+            }
+            AppEvent::Keyboard(event) => {
+                if event {
+                    self.handle_keyboard_activity(KeyboardEvent {}).await
+                }
+            }
+            AppEvent::Window(event) => {
+                if let Err(err) = self.handle_window_activity(event).await {
+                    log::error!("Failed to handle window activity: {}", err);
+                }
+            }
+            AppEvent::AppBlocked(event) => self.handle_app_blocked_activity(event).await,
+        }
+    }
+
+    /**
+     * Consumes events on a background task until the source closes or `stop` is called, then
+     * flushes the buffered activities. The returned handle resolves to `Error::SourceClosed` when
+     * the source closed, or to the error of the final flush.
+     */
+    pub async fn register_receiver(
+        &self,
+        mut event_receiver: Receiver<AppEvent>,
+    ) -> JoinHandle<Result<()>> {
         let service_clone = self.clone();
+        let mut shutdown = self.shutdown.subscribe();
+        let flush_period = self.activity_buffer.lock().await.max_age();
         tokio::spawn(async move {
-            while let Ok(event) = event_receiver.recv().await {
-                match event {
-                    AppEvent::Mouse(event) => {
-                        if event {
-                            service_clone.handle_mouse_activity(MouseEvent {}).await
-                        }
-                    }
-                    AppEvent::Keyboard(event) => {
-                        if event {
-                            service_clone
-                                .handle_keyboard_activity(KeyboardEvent {})
-                                .await
+            let mut flush_interval = tokio::time::interval(flush_period);
+            let source_closed = loop {
+                tokio::select! {
+                    event = event_receiver.recv() => match event {
+                        Ok(event) => service_clone.receive_event(event).await,
+                        Err(RecvError::Lagged(dropped_events)) => {
+                            service_clone.handle_lagged_events(dropped_events).await
                         }
-                    }
-                    AppEvent::Window(event) => service_clone.handle_window_activity(event).await,
-                    AppEvent::AppBlocked(event) => {
-                        service_clone.handle_app_blocked_activity(event).await
-                    }
+                        Err(RecvError::Closed) => break true,
+                    },
+                    _ = flush_interval.tick() => service_clone.flush_activities_if_due().await,
+                    _ = stopped(&mut shutdown) => break false,
                 }
+            };
+            if source_closed {
+                log::warn!("Event receiver channel closed");
+            }
+            if let Err(err) = service_clone.flush_activities().await {
+                log::error!("Failed to flush activities: {}", err);
+                return Err(err);
+            }
+            if source_closed {
+                Err(Error::SourceClosed)
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Stops the event receiver and the activity state loop. Their tasks finish what they are doing first.
+    pub(crate) fn stop(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Lets other background loops stop along with this service's (see `stop`).
+    pub(crate) fn subscribe_shutdown(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    async fn buffer_activity(&self, activity: Activity) {
+        let mut activity_buffer = self.activity_buffer.lock().await;
+        if activity_buffer.push(activity, self.clock.now()) {
+            if let Err(err) = self.write_buffered_activities(&mut activity_buffer).await {
+                log::error!("Failed to save buffered activities: {}", err);
             }
-            log::warn!("Event receiver channel closed");
-        });
+        }
+    }
+
+    async fn flush_activities_if_due(&self) {
+        let mut activity_buffer = self.activity_buffer.lock().await;
+        if activity_buffer.should_flush(self.clock.now()) {
+            if let Err(err) = self.write_buffered_activities(&mut activity_buffer).await {
+                log::error!("Failed to save buffered activities: {}", err);
+            }
+        }
+    }
+
+    /**
+     * Writes all buffered activities to the database in a single transaction.
+     * Returns the number of activities written.
+     */
+    pub async fn flush_activities(&self) -> Result<usize> {
+        let mut activity_buffer = self.activity_buffer.lock().await;
+        self.write_buffered_activities(&mut activity_buffer).await
     }
 
+    // the buffer lock is held for the duration of the write so batches are stored in order
+    async fn write_buffered_activities(
+        &self,
+        activity_buffer: &mut ActivityBuffer,
+    ) -> Result<usize> {
+        let activities = activity_buffer.take();
+        if activities.is_empty() {
+            return Ok(0);
+        }
+        log::trace!("flushing {} activities", activities.len());
+        match self.activities_repo.save_activities(&activities).await {
+            Ok(()) => Ok(activities.len()),
+            Err(err) => {
+                activity_buffer.restore(activities);
+                Err(err)
+            }
+        }
+    }
+
+    #[cfg(test)]
     pub async fn save_activity(
         &self,
         activity: &Activity,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         self.activities_repo.save_activity(activity).await
     }
 
     #[cfg(test)]
-    pub async fn get_activity(&self, id: i32) -> Result<Activity, sqlx::Error> {
+    pub async fn get_activity(&self, id: i32) -> Result<Activity> {
         self.activities_repo.get_activity(id).await
     }
 
@@ -150,15 +596,13 @@ impl ActivityService {
     async fn save_activity_state(
         &self,
         activity_state: &ActivityState,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
         self.activity_state_repo
             .save_activity_state(activity_state)
             .await
     }
 
-    pub async fn get_activities_since_last_activity_state(
-        &self,
-    ) -> Result<Vec<Activity>, sqlx::Error> {
+    pub async fn get_activities_since_last_activity_state(&self) -> Result<Vec<Activity>> {
         self.activities_repo
             .get_activities_since_last_activity_state()
             .await
@@ -167,31 +611,36 @@ impl ActivityService {
     async fn create_idle_activity_state(
         &self,
         activity_period: ActivityPeriod,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
-        self.activity_state_repo
-            .create_idle_activity_state(&activity_period)
-            .await
-            .expect("Failed to create idle activity state");
-
-        let activity_state = self
-            .activity_state_service
-            .get_last_activity_state()
-            .await
-            .expect("Failed to get activity state");
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        retry_transient("create idle activity state", || {
+            self.activity_state_repo
+                .create_idle_activity_state(&activity_period)
+        })
+        .await?;
+
+        let activity_state = retry_transient("get activity state", || {
+            self.activity_state_service.get_last_activity_state()
+        })
+        .await?;
 
         if let Some(activity_state_id) = activity_state.id {
-            self.app_service.create_idle_tag(activity_state_id).await
+            retry_transient("create idle tag", || {
+                self.app_service.create_idle_tag(activity_state_id)
+            })
+            .await
         } else {
             log::error!("Cannot create tags: activity state has no ID");
-            Err(sqlx::Error::RowNotFound)
+            Err(Error::Validation("activity state has no id".to_string()))
         }
     }
 
     /**
      * Creates an activity state from a list of activities.
-     * If the activities are empty, it creates an idle activity state.
+     * If the activities don't pass the idle thresholds (by default: if they are empty), it creates an idle activity state.
      * It also creates an idle tag for the idle activity state.
-     * If the activities are not empty, it creates an active activity state.
+     * If an app marked passive (e.g. a video call) was focused instead, it creates a passive activity state.
+     * If recording was paused for at least half of the period, it creates a paused activity state without apps or tags.
+     * Otherwise it creates an active activity state, with its active seconds if partial active states are recorded.
      * For tags, we get all matching tags for the activites and create those tags for the activity state.
      * If there were no window activities, we use the last window activity to create the tags (writing code to a single file for more than 30 seconds).
      */
@@ -199,56 +648,150 @@ impl ActivityService {
         &self,
         activities: Vec<Activity>,
         activity_period: ActivityPeriod,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        self.create_activity_state_with_app_switches(activities, activity_period, None)
+            .await
+    }
+
+    /**
+     * Like `create_activity_state_from_activities`, with `app_switches` counted by the caller instead
+     * of the live app switch count, which is then left untouched (e.g. for past intervals).
+     */
+    async fn create_activity_state_with_app_switches(
+        &self,
+        activities: Vec<Activity>,
+        activity_period: ActivityPeriod,
+        app_switches: Option<i64>,
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let reset_live_app_switches = || {
+            if app_switches.is_none() {
+                self.app_switch_state.lock().reset_app_switches();
+            }
+        };
         // iterate over the activities to create the start, end, context_switches, and activity_state_type
         log::trace!(
             "create_activity_state_from_activities: {}",
             activities.len()
         );
 
-        if activities.is_empty() {
-            log::trace!("  create_activity_state_from_activities: empty");
-            self.create_idle_activity_state(activity_period).await
+        let period_seconds =
+            (activity_period.end_time - activity_period.start_time).as_seconds_f64();
+        let paused_seconds = self
+            .pause_state
+            .lock()
+            .paused_seconds(&activity_period, self.clock.now());
+        if period_seconds > 0.0 && paused_seconds * 2.0 >= period_seconds {
+            log::trace!("  create_activity_state_from_activities: paused");
+            let result = retry_transient("create paused activity state", || {
+                self.activity_state_repo
+                    .create_paused_activity_state(&activity_period)
+            })
+            .await?;
+            reset_live_app_switches();
+            return Ok(result);
+        }
+
+        let active_seconds = self
+            .idle_thresholds
+            .active_seconds(&activities, &activity_period);
+        if let Some(active_seconds) = active_seconds {
+            log::trace!("  active_seconds: {}", active_seconds);
+        }
+        let partial_active_seconds = active_seconds
+            .filter(|active_seconds| {
+                self.idle_thresholds.records_partial_active_states()
+                    && *active_seconds < period_seconds
+            })
+            .map(|active_seconds| active_seconds.round() as i64);
+
+        // without enough input, a focused video call or video keeps the state from being idle
+        let is_passive = active_seconds.is_none()
+            && self
+                .app_service
+                .has_passive_focus(&activities, &activity_period)
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to check for passive apps: {}", err);
+                    false
+                });
+
+        if active_seconds.is_none() && !is_passive {
+            log::trace!("  create_activity_state_from_activities: idle");
+            let result = self.create_idle_activity_state(activity_period).await;
+            if !activities.is_empty() {
+                // window switches of an interval below the thresholds don't carry over to the next one
+                reset_live_app_switches();
+            }
+            result
         } else {
-            log::trace!("  create_activity_state_from_activities: not empty");
+            log::trace!("  create_activity_state_from_activities: active or passive");
             // First lock: Get the context switches
-            let context_switches = {
-                let app_switch = APP_SWITCH_STATE.lock();
-                app_switch.app_switches.clone()
-            }; // lock is released here
+            let context_switches = app_switches.unwrap_or_else(|| {
+                let app_switch = self.app_switch_state.lock();
+                app_switch.app_switches
+            }); // lock is released here
             log::trace!("  context_switches: {:?}", context_switches);
-            let result = self
-                .activity_state_repo
-                .create_active_activity_state(context_switches, &activity_period)
-                .await;
+            let result = retry_transient("create activity state", || async {
+                if is_passive {
+                    self.activity_state_repo
+                        .create_passive_activity_state(context_switches, &activity_period)
+                        .await
+                } else {
+                    self.activity_state_repo
+                        .create_active_activity_state(
+                            context_switches,
+                            partial_active_seconds,
+                            &activity_period,
+                        )
+                        .await
+                }
+            })
+            .await?;
             log::trace!("  created activity state");
 
-            let activity_state = self
-                .activity_state_service
-                .get_last_activity_state()
-                .await
-                .expect("Failed to get activity state");
+            let activity_state = retry_transient("get activity state", || {
+                self.activity_state_service.get_last_activity_state()
+            })
+            .await?;
 
             // Only create tags if we have a valid activity state ID
             if let Some(activity_state_id) = activity_state.id {
-                self.app_service
-                    .create_tags_from_activities(&activities, activity_state_id)
-                    .await
-                    .expect("Failed to create activity state tags");
+                let period_windows = retry_transient("get period windows", || {
+                    self.app_service
+                        .get_period_windows(&activities, &activity_period)
+                })
+                .await?;
+                if let Err(err) = retry_transient("create activity state apps", || {
+                    self.app_service.create_activity_state_apps(
+                        &period_windows,
+                        activity_state_id,
+                        &activity_period,
+                    )
+                })
+                .await
+                {
+                    log::error!("Failed to create activity state apps: {}", err);
+                }
+                retry_transient("create activity state tags", || {
+                    self.app_service.create_tags_from_activities(
+                        &activities,
+                        &period_windows,
+                        activity_state_id,
+                        &activity_period,
+                    )
+                })
+                .await?;
             } else {
                 log::error!("Cannot create tags: activity state has no ID");
             }
-            {
-                let mut app_switch = APP_SWITCH_STATE.lock();
-                app_switch.reset_app_switches();
-            } // lock is released here
+            reset_live_app_switches();
             log::trace!("  reset_app_switches");
-            result
+            Ok(result)
         }
     }
 
     #[cfg(test)]
-    async fn get_last_activity_state(&self) -> Result<ActivityState, sqlx::Error> {
+    async fn get_last_activity_state(&self) -> Result<ActivityState> {
         self.activity_state_repo.get_last_activity_state().await
     }
 
@@ -257,44 +800,203 @@ impl ActivityService {
         &self,
         start_time: OffsetDateTime,
         end_time: OffsetDateTime,
-    ) -> Result<Vec<ActivityState>, sqlx::Error> {
+    ) -> Result<Vec<ActivityState>> {
         self.activity_state_repo
             .get_activity_states_starting_between(start_time, end_time)
             .await
     }
 
-    pub fn start_activity_state_loop(&self, activity_state_interval: Duration) {
+    /**
+     * Runs one iteration of the activity state loop: flushes buffered activities and creates the
+     * activity state for the interval that just completed according to the service clock.
+     * Time skipped since the last activity state (sleep, downtime, clock changes) is recorded as a gap.
+     */
+    pub(crate) async fn process_activity_state_tick(
+        &self,
+        activity_state_interval: Duration,
+    ) -> Result<()> {
+        self.record_paused_window().await;
+        if let Err(err) = retry_transient("flush activities", || self.flush_activities()).await {
+            log::error!("Failed to flush activities before tick: {}", err);
+        }
+        let activities = retry_transient("get activities", || {
+            self.get_activities_since_last_activity_state()
+        })
+        .await?;
+        log::trace!("retrieved latest activities");
+        let since_last_tick = {
+            let mut last_tick = self.last_tick.lock();
+            let since_last_tick = last_tick.map(|last_tick| last_tick.elapsed());
+            *last_tick = Some(Instant::now());
+            since_last_tick
+        }; // lock is released here
+        let (activity_period, activity_gap) = retry_transient("get activity period", || {
+            self.activity_state_service
+                .get_just_completed_activity_state_with_gap(
+                    activity_state_interval,
+                    since_last_tick,
+                )
+        })
+        .await?;
+        log::trace!("retrieved next activity state times");
+        if let Some(activity_gap) = activity_gap {
+            if let Err(err) = retry_transient("save activity gap", || {
+                self.activity_gap_repo.save_activity_gap(&activity_gap)
+            })
+            .await
+            {
+                log::error!("Failed to save activity gap: {}", err);
+            }
+        }
+        self.create_activity_state_from_activities(activities, activity_period)
+            .await?;
+        log::trace!("activity_state_created");
+        self.publish_activity_state_closed().await;
+        Ok(())
+    }
+
+    /**
+     * Creates the activity states missed since the last one, e.g. when the process stopped with
+     * activities already stored or the loop was starved. Walks from the last activity state's end in
+     * `activity_state_interval` steps up to now and creates each step's state (active or inactive)
+     * from the stored activities. It stops after the last step with stored activities: time after it
+     * has nothing recorded and is left to the loop's gap detection. App switches are counted from each
+     * step's window activities rather than the live count.
+     * Safe to run repeatedly since it always continues from the last activity state. Returns the
+     * number of activity states created.
+     */
+    pub async fn backfill_activity_states(
+        &self,
+        activity_state_interval: Duration,
+    ) -> Result<usize> {
+        self.flush_activities().await?;
+        let last_end_time = match self.activity_state_repo.get_last_activity_state().await {
+            Ok(activity_state) => activity_state.end_time,
+            Err(err) if err.is_not_found() => None,
+            Err(err) => return Err(err),
+        };
+        let Some(mut start_time) = last_end_time else {
+            return Ok(0);
+        };
+        let mut activities = self
+            .get_activities_since_last_activity_state()
+            .await?
+            .into_iter()
+            .filter(|activity| activity.timestamp.is_some())
+            .peekable();
+        let now = self.clock.now();
+        let switch_clock = Arc::new(ManualClock::new(start_time));
+        let mut app_switch_state =
+            AppSwitchState::new(MIN_TIME_BETWEEN_APP_SWITCHES, switch_clock.clone());
+        let mut created = 0;
+        while start_time + activity_state_interval <= now && activities.peek().is_some() {
+            let activity_period = ActivityPeriod {
+                start_time,
+                end_time: start_time + activity_state_interval,
+            };
+            let mut step_activities = Vec::new();
+            while let Some(activity) =
+                activities.next_if(|activity| activity.timestamp <= Some(activity_period.end_time))
+            {
+                if activity.activity_type == ActivityType::Window {
+                    switch_clock.set(activity.timestamp.unwrap_or(activity_period.end_time));
+                    app_switch_state.new_window_activity(activity.clone());
+                }
+                step_activities.push(activity);
+            }
+            let app_switches = app_switch_state.app_switches;
+            app_switch_state.reset_app_switches();
+            self.create_activity_state_with_app_switches(
+                step_activities,
+                activity_period.clone(),
+                Some(app_switches),
+            )
+            .await?;
+            created += 1;
+            start_time = activity_period.end_time;
+        }
+        if created > 0 {
+            log::warn!(
+                "Backfilled {} activity states up to {}",
+                created,
+                start_time
+            );
+        }
+        Ok(created)
+    }
+
+    /**
+     * Creates an activity state every `activity_state_interval` until `stop` is called. Failed or
+     * panicking ticks are recorded in the loop status (see `with_loop_status`) and the loop carries
+     * on with the next one.
+     */
+    pub fn start_activity_state_loop(&self, activity_state_interval: Duration) -> JoinHandle<()> {
         let activity_service_clone = self.clone();
-        let activity_state_service_clone = self.activity_state_service.clone();
+        let mut shutdown = self.shutdown.subscribe();
         tokio::spawn(async move {
             let mut wait_interval = tokio::time::interval(activity_state_interval);
             wait_interval.tick().await;
             loop {
                 log::trace!("tick");
-                wait_interval.tick().await;
-                let activities = activity_service_clone
-                    .get_activities_since_last_activity_state()
-                    .await
-                    .unwrap();
-                log::trace!("retrieved latest activities");
-                let activity_period = activity_state_service_clone
-                    .get_just_completed_activity_state(activity_state_interval)
-                    .await;
-                log::trace!("retrieved next activity state times");
+                tokio::select! {
+                    _ = wait_interval.tick() => {}
+                    _ = stopped(&mut shutdown) => break,
+                }
                 activity_service_clone
-                    .create_activity_state_from_activities(activities, activity_period)
-                    .await
-                    .expect("Failed to create activity state");
-                log::trace!("activity_state_created");
+                    .run_activity_state_tick(activity_state_interval)
+                    .await;
             }
-        });
+        })
     }
-}
 
-pub async fn start_activities_monitoring(db_path: String) -> ActivityService {
-    let db_manager = db_manager::DbManager::new(&db_path).await.unwrap();
+    /**
+     * Ends the interval in progress now instead of at the next tick, so its activities aren't lost
+     * when monitoring stops. The activity state is shorter than the interval. Does nothing if there
+     * is no activity state yet or the last one ended less than a second ago.
+     */
+    pub(crate) async fn close_activity_state(&self) -> Result<()> {
+        self.flush_activities().await?;
+        let start_time = match self.activity_state_repo.get_last_activity_state().await {
+            Ok(activity_state) => activity_state.end_time,
+            Err(err) if err.is_not_found() => None,
+            Err(err) => return Err(err),
+        };
+        let end_time = self.clock.now();
+        let Some(start_time) =
+            start_time.filter(|start_time| end_time - *start_time >= Duration::from_secs(1))
+        else {
+            return Ok(());
+        };
+        let activities = self.get_activities_since_last_activity_state().await?;
+        self.create_activity_state_from_activities(
+            activities,
+            ActivityPeriod {
+                start_time,
+                end_time,
+            },
+        )
+        .await?;
+        self.publish_activity_state_closed().await;
+        Ok(())
+    }
 
-    ActivityService::new(db_manager.pool)
+    /// Runs a tick on its own task, so a panic is contained and reported like any other failure.
+    async fn run_activity_state_tick(&self, activity_state_interval: Duration) {
+        let activity_service = self.clone();
+        let result = tokio::spawn(async move {
+            activity_service
+                .process_activity_state_tick(activity_state_interval)
+                .await
+        })
+        .await;
+        let failure = match result {
+            Ok(Ok(())) => return self.loop_status.record_success(self.clock.now()),
+            Ok(Err(err)) => err.to_string(),
+            Err(join_err) => format!("activity state tick panicked: {}", join_err),
+        };
+        log::error!("Activity state tick failed: {}", failure);
+        self.loop_status.record_failure(failure, self.clock.now());
+    }
 }
 
 #[cfg(test)]
@@ -305,9 +1007,19 @@ mod tests {
     use uuid::Uuid;
 
     use super::*;
-    use crate::db::{
-        db_manager,
-        models::{ActivityStateType, ActivityType},
+    use crate::{
+        db::{
+            db_manager,
+            models::{ActivityStateType, TagRule},
+            tag_repo::TagRepo,
+            tag_rule_repo::TagRuleRepo,
+        },
+        services::{
+            report_service::ReportService,
+            tag_service::TagService,
+            title_policy_service::{TitlePolicy, TitleRedaction},
+        },
+        utils::clock::ManualClock,
     };
 
     #[tokio::test]
@@ -344,7 +1056,10 @@ mod tests {
             bundle_id: Some("com.ebb.app".to_string()),
             platform: Platform::Mac,
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
 
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
@@ -368,7 +1083,10 @@ mod tests {
             bundle_id: Some("com.new.new".to_string()),
             platform: Platform::Mac,
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
 
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
@@ -387,6 +1105,31 @@ mod tests {
         assert_eq!(tag.name, "neutral");
     }
 
+    #[tokio::test]
+    async fn test_window_event_is_published_to_live_stream() {
+        let pool = db_manager::create_test_db().await;
+        let live_stream = Arc::new(LiveStreamPublisher::new(16));
+        let mut live_stream_receiver = live_stream.subscribe();
+        let activity_service = ActivityService::new(pool).with_live_stream(live_stream);
+        activity_service
+            .handle_window_activity(WindowEvent {
+                app_name: "New App".to_string(),
+                window_title: "main.rs - app-codeclimbers".to_string(),
+                url: None,
+                bundle_id: Some("com.new.new".to_string()),
+                platform: Platform::Mac,
+            })
+            .await
+            .unwrap();
+
+        let line = live_stream_receiver.recv().await.unwrap();
+        let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(message["type"], "window_focus");
+        assert_eq!(message["app_external_id"], "com.new.new");
+        assert_eq!(message["window_title"], "main.rs - app-codeclimbers");
+        assert_eq!(message["tags"][0]["name"], "neutral");
+    }
+
     #[tokio::test]
     async fn test_on_window_event_new_app_has_url() {
         let pool = db_manager::create_test_db().await;
@@ -398,7 +1141,10 @@ mod tests {
             bundle_id: None,
             platform: Platform::Mac,
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
 
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
@@ -412,19 +1158,83 @@ mod tests {
         assert_eq!(app.app_external_id, "mail.google.com");
     }
 
+    #[tokio::test]
+    async fn test_window_title_is_redacted() {
+        let pool = db_manager::create_test_db().await;
+        // tag rules match the title before it is redacted
+        let tag = TagRepo::new(pool.clone())
+            .get_tag_by_name("consuming")
+            .await
+            .unwrap();
+        let tag_rule = TagRule::new(
+            Some("mail.google.com".to_string()),
+            Some("Offer letter".to_string()),
+            None,
+            tag.id.unwrap(),
+            1.0,
+            0,
+        );
+        TagRuleRepo::new(pool.clone())
+            .save_tag_rule(&tag_rule)
+            .await
+            .unwrap();
+        let activity_service = ActivityService::new(pool).with_title_redactor(
+            TitleRedactor::new(vec![TitlePolicy::new(
+                Some("mail.google.com".to_string()),
+                TitleRedaction::Drop,
+            )])
+            .unwrap(),
+        );
+        activity_service
+            .handle_window_activity(WindowEvent {
+                app_name: "Google Chrome".to_string(),
+                window_title: "Offer letter - Gmail".to_string(),
+                url: Some("https://mail.google.com/mail/u/0".to_string()),
+                bundle_id: Some("com.google.Chrome".to_string()),
+                platform: Platform::Mac,
+            })
+            .await
+            .unwrap();
+
+        let activity = activity_service.get_activity(1).await.unwrap();
+        assert_eq!(activity.app_window_title, None);
+        assert_eq!(activity.tag_rule_ids, Some(tag_rule.id));
+    }
+
     #[tokio::test]
     async fn test_on_keyboard_event() {
         let pool = db_manager::create_test_db().await;
         let activity_service = ActivityService::new(pool);
         let event = KeyboardEvent {};
         activity_service.handle_keyboard_activity(event).await;
-
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        activity_service.flush_activities().await.unwrap();
 
         let activity = activity_service.get_activity(1).await.unwrap();
         assert_eq!(activity.activity_type, ActivityType::Keyboard);
     }
 
+    #[tokio::test]
+    async fn test_input_activity_is_buffered_until_flush() {
+        let pool = db_manager::create_test_db().await;
+        let activity_service =
+            ActivityService::new(pool).with_write_buffer(2, Duration::from_secs(60));
+        activity_service
+            .handle_keyboard_activity(KeyboardEvent {})
+            .await;
+        assert!(activity_service.get_activity(1).await.is_err());
+
+        // the mouse event triggers the size threshold, the repeated keyboard event is coalesced
+        activity_service
+            .handle_keyboard_activity(KeyboardEvent {})
+            .await;
+        activity_service.handle_mouse_activity(MouseEvent {}).await;
+        assert!(activity_service.get_activity(1).await.is_ok());
+        assert!(activity_service.get_activity(2).await.is_ok());
+        assert!(activity_service.get_activity(3).await.is_err());
+
+        assert_eq!(activity_service.flush_activities().await.unwrap(), 0);
+    }
+
     #[tokio::test]
     async fn test_create_activity_state_from_activities_inactive() {
         let pool = db_manager::create_test_db().await;
@@ -468,6 +1278,179 @@ mod tests {
         assert_eq!(activity_state.app_switches, 0);
     }
 
+    #[tokio::test]
+    async fn test_create_activity_state_from_activities_idle_thresholds() {
+        let pool = db_manager::create_test_db().await;
+        let activity_service = ActivityService::new(pool).with_idle_thresholds(
+            IdleThresholds::default()
+                .with_min_input_events(2)
+                .with_idle_after(Duration::from_secs(10))
+                .with_partial_active_states(),
+        );
+        let start_time = OffsetDateTime::now_utc();
+        let activity_period = ActivityPeriod {
+            start_time,
+            end_time: start_time + Duration::from_secs(60),
+        };
+        let keyboard = |seconds| {
+            Activity::create_keyboard_activity(
+                &KeyboardEvent {},
+                start_time + Duration::from_secs(seconds),
+            )
+        };
+
+        activity_service
+            .create_activity_state_from_activities(vec![keyboard(30)], activity_period.clone())
+            .await
+            .unwrap();
+        let activity_state = activity_service.get_last_activity_state().await.unwrap();
+        assert_eq!(activity_state.state, ActivityStateType::Inactive);
+
+        activity_service
+            .create_activity_state_from_activities(
+                vec![keyboard(0), keyboard(5), keyboard(40)],
+                activity_period,
+            )
+            .await
+            .unwrap();
+        let activity_state = activity_service.get_last_activity_state().await.unwrap();
+        assert_eq!(activity_state.state, ActivityStateType::Active);
+        assert_eq!(activity_state.active_seconds, Some(25));
+        assert_eq!(activity_state.active_duration_seconds(), 25);
+    }
+
+    #[tokio::test]
+    async fn test_create_activity_state_from_activities_passive() {
+        let pool = db_manager::create_test_db().await;
+        let activity_service = ActivityService::new(pool.clone());
+        let start_time = OffsetDateTime::now_utc();
+        let activity_period = ActivityPeriod {
+            start_time,
+            end_time: start_time + Duration::from_secs(60),
+        };
+        // a zoom.us meeting was focused before the interval and there was no input since
+        let zoom_app_id = "546d594c-7bf0-4c4b-bb1f-a4a2d9a43ed1";
+        let mut meeting = Activity::__create_test_window(None, Some(zoom_app_id.to_string()));
+        meeting.timestamp = Some(start_time - Duration::from_secs(300));
+        activity_service.save_activity(&meeting).await.unwrap();
+
+        activity_service
+            .create_activity_state_from_activities(vec![], activity_period.clone())
+            .await
+            .unwrap();
+        let activity_state = activity_service.get_last_activity_state().await.unwrap();
+        assert_eq!(activity_state.state, ActivityStateType::Passive);
+        let tags = activity_service
+            .app_service
+            .get_tags_for_activity_state(activity_state.id.unwrap())
+            .await
+            .unwrap();
+        assert!(tags.iter().any(|tag| tag.name == "communication"));
+        assert!(!tags.iter().any(|tag| tag.name == "idle"));
+
+        TagService::new(pool)
+            .set_app_passive(zoom_app_id, false)
+            .await
+            .unwrap();
+        activity_service
+            .create_activity_state_from_activities(
+                vec![],
+                ActivityPeriod {
+                    start_time: activity_period.end_time,
+                    end_time: activity_period.end_time + Duration::from_secs(60),
+                },
+            )
+            .await
+            .unwrap();
+        let activity_state = activity_service.get_last_activity_state().await.unwrap();
+        assert_eq!(activity_state.state, ActivityStateType::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_private_apps() {
+        let pool = db_manager::create_test_db().await;
+        let start = OffsetDateTime::now_utc() - Duration::from_secs(60 * 60);
+        let clock = Arc::new(ManualClock::new(start));
+        let activity_service = ActivityService::new(pool)
+            .with_private_apps(vec!["1Password".to_string()])
+            .with_clock(clock.clone());
+        let window = |app_name: &str, window_title: &str| {
+            AppEvent::Window(WindowEvent {
+                app_name: app_name.to_string(),
+                window_title: window_title.to_string(),
+                bundle_id: None,
+                url: None,
+                platform: Platform::Mac,
+            })
+        };
+
+        // focusing a private app pauses recording, then a timed pause keeps it paused after leaving it
+        activity_service
+            .receive_event(window("1Password", "Bank login"))
+            .await;
+        activity_service
+            .receive_event(AppEvent::Keyboard(true))
+            .await;
+        assert!(activity_service.is_paused());
+        clock.advance(Duration::from_secs(40));
+        activity_service.pause(Some(Duration::from_secs(30)));
+        activity_service
+            .receive_event(window("Cursor", "main.rs"))
+            .await;
+        activity_service
+            .receive_event(AppEvent::Keyboard(true))
+            .await;
+        activity_service.flush_activities().await.unwrap();
+        assert!(activity_service
+            .get_activities_since_last_activity_state()
+            .await
+            .unwrap()
+            .is_empty());
+
+        clock.advance(Duration::from_secs(20));
+        activity_service
+            .create_activity_state_from_activities(
+                vec![],
+                ActivityPeriod {
+                    start_time: start,
+                    end_time: start + Duration::from_secs(60),
+                },
+            )
+            .await
+            .unwrap();
+        let activity_state = activity_service.get_last_activity_state().await.unwrap();
+        assert_eq!(activity_state.state, ActivityStateType::Paused);
+
+        // once the pause ran out, the window focused meanwhile is recorded first
+        clock.advance(Duration::from_secs(10));
+        assert!(!activity_service.is_paused());
+        activity_service
+            .receive_event(AppEvent::Keyboard(true))
+            .await;
+        activity_service.flush_activities().await.unwrap();
+        let activities = activity_service
+            .get_activities_since_last_activity_state()
+            .await
+            .unwrap();
+        assert_eq!(activities.len(), 2);
+        assert_eq!(activities[0].activity_type, ActivityType::Window);
+        assert_eq!(activities[0].app_window_title, Some("main.rs".to_string()));
+
+        clock.advance(Duration::from_secs(50));
+        activity_service
+            .create_activity_state_from_activities(
+                activities,
+                ActivityPeriod {
+                    start_time: start + Duration::from_secs(60),
+                    end_time: start + Duration::from_secs(120),
+                },
+            )
+            .await
+            .unwrap();
+        let activity_state = activity_service.get_last_activity_state().await.unwrap();
+        assert_eq!(activity_state.state, ActivityStateType::Active);
+    }
+
     #[tokio::test]
     async fn test_get_activities_since_last_activity_state_edge_time_case() {
         let pool = db_manager::create_test_db().await;
@@ -511,21 +1494,288 @@ mod tests {
     #[tokio::test]
     async fn test_activity_state_loop() {
         let pool = db_manager::create_test_db().await;
-        let activity_service = ActivityService::new(pool);
         let start = OffsetDateTime::now_utc();
+        let clock = Arc::new(ManualClock::new(start));
+        let activity_service = ActivityService::new(pool).with_clock(clock.clone());
+        let interval = Duration::from_secs(60);
+
+        // time only moves on advance while the loop waits for its next tick, and runs normally
+        // while the tick writes to the database so sqlx timeouts don't fire early
+        activity_service.start_activity_state_loop(interval);
+        tokio::task::yield_now().await;
+        for ticks in 1..=3 {
+            tokio::time::pause();
+            clock.advance(interval);
+            tokio::time::advance(interval).await;
+            tokio::time::resume();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while activity_service
+                    .get_activity_starting_states_between(start - interval, clock.now())
+                    .await
+                    .unwrap()
+                    .len()
+                    < ticks
+                {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            })
+            .await
+            .unwrap();
+        }
 
-        activity_service.start_activity_state_loop(Duration::from_millis(100));
+        let activity_states = activity_service
+            .get_activity_starting_states_between(start - interval, clock.now())
+            .await
+            .unwrap();
+        assert_eq!(activity_states.len(), 3);
+        assert_eq!(
+            activity_states.last().unwrap().end_time,
+            Some(start + interval * 3)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_activity_state_tick_failures_are_reported() {
+        let pool = db_manager::create_test_db().await;
+        let loop_status = ActivityStateLoopStatus::default();
+        let activity_service =
+            ActivityService::new(pool.clone()).with_loop_status(loop_status.clone());
+        let interval = Duration::from_millis(100);
+
+        activity_service.run_activity_state_tick(interval).await;
+        assert!(loop_status.is_healthy());
+        assert!(loop_status.snapshot().last_success_at.is_some());
+
+        pool.close().await;
+        activity_service.run_activity_state_tick(interval).await;
+        activity_service.run_activity_state_tick(interval).await;
+
+        let snapshot = loop_status.snapshot();
+        assert!(!loop_status.is_healthy());
+        assert_eq!(snapshot.consecutive_failures, 2);
+        assert_eq!(snapshot.total_failures, 2);
+        assert!(snapshot.last_error.unwrap().starts_with("failed to"));
+    }
 
-        // Wait for a few iterations
-        tokio::time::sleep(Duration::from_millis(350)).await;
+    #[tokio::test]
+    async fn test_register_receiver_survives_lag() {
+        let pool = db_manager::create_test_db().await;
+        let activity_service = ActivityService::new(pool.clone());
+        let (sender, receiver) = tokio::sync::broadcast::channel(2);
+
+        // overflow the channel before the receiver starts consuming
+        for _ in 0..5 {
+            sender.send(AppEvent::Keyboard(true)).unwrap();
+        }
+        activity_service.register_receiver(receiver).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // the receiver keeps consuming after the lag
+        sender.send(AppEvent::Mouse(true)).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let ingest_stats = &activity_service.ingest_stats;
+        assert_eq!(ingest_stats.dropped_events(), 3);
+        assert_eq!(ingest_stats.lag_occurrences(), 1);
+        let total_dropped = activity_service
+            .ingest_lag_repo
+            .get_total_dropped_events()
+            .await
+            .unwrap();
+        assert_eq!(total_dropped, 3);
+
+        activity_service.flush_activities().await.unwrap();
+        let activity = activity_service.get_activity(2).await.unwrap();
+        assert_eq!(activity.activity_type, ActivityType::Mouse);
+    }
+
+    #[tokio::test]
+    async fn test_activity_state_ticks_with_manual_clock() {
+        let pool = db_manager::create_test_db().await;
+        let start = OffsetDateTime::now_utc() - Duration::from_secs(24 * 60 * 60);
+        let clock = Arc::new(ManualClock::new(start));
+        let activity_service = ActivityService::new(pool).with_clock(clock.clone());
+        let interval = Duration::from_secs(60);
+        activity_service
+            .handle_window_activity(WindowEvent {
+                app_name: "Cursor".to_string(),
+                window_title: "main.rs - app-codeclimbers".to_string(),
+                url: None,
+                bundle_id: Some("com.todesktop.230313mzl4w4u92".to_string()),
+                platform: Platform::Mac,
+            })
+            .await
+            .unwrap();
+
+        activity_service
+            .process_activity_state_tick(interval)
+            .await
+            .unwrap();
+        // three hours of usage where the user types every other minute
+        for minute in 0..180 {
+            clock.advance(interval / 2);
+            if minute % 2 == 0 {
+                activity_service
+                    .handle_keyboard_activity(KeyboardEvent {})
+                    .await;
+            }
+            clock.advance(interval / 2);
+            activity_service
+                .process_activity_state_tick(interval)
+                .await
+                .unwrap();
+        }
 
-        // Verify the results
         let activity_states = activity_service
-            .get_activity_starting_states_between(start, OffsetDateTime::now_utc())
+            .get_activity_starting_states_between(start - interval, clock.now())
             .await
             .unwrap();
+        assert_eq!(activity_states.len(), 181);
+        assert_eq!(
+            activity_states
+                .iter()
+                .filter(|s| s.state == ActivityStateType::Active)
+                .count(),
+            90
+        );
+        let last_activity_state = activity_states.last().unwrap();
+        assert_eq!(last_activity_state.end_time, Some(clock.now()));
+    }
 
-        assert_eq!(activity_states.len(), 3);
+    #[tokio::test]
+    async fn test_clock_jump_back_is_not_counted_twice() {
+        let pool = db_manager::create_test_db().await;
+        let start = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap()
+            - Duration::from_secs(24 * 60 * 60);
+        let clock = Arc::new(ManualClock::new(start));
+        let activity_service = ActivityService::new(pool.clone()).with_clock(clock.clone());
+        let interval = Duration::from_secs(60);
+        for _ in 0..3 {
+            activity_service
+                .process_activity_state_tick(interval)
+                .await
+                .unwrap();
+            clock.advance(interval);
+        }
+
+        // the clock is set back 30 minutes and the loop carries on from there
+        clock.set(start - Duration::from_secs(30 * 60));
+        for _ in 0..2 {
+            activity_service
+                .process_activity_state_tick(interval)
+                .await
+                .unwrap();
+            clock.advance(interval);
+        }
+
+        let summary = ReportService::new(pool)
+            .get_usage_summary(start - Duration::from_secs(60 * 60), start + 3 * interval)
+            .await
+            .unwrap();
+        // three minutes before the jump and one after it, the jump itself takes no time
+        assert_eq!(summary.inactive_seconds, 4 * 60);
+        assert!(summary.gaps.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_activity_states() {
+        let pool = db_manager::create_test_db().await;
+        let start = OffsetDateTime::now_utc() - Duration::from_secs(24 * 60 * 60);
+        let clock = Arc::new(ManualClock::new(start + Duration::from_secs(20 * 60)));
+        let activity_service = ActivityService::new(pool.clone()).with_clock(clock.clone());
+        let interval = Duration::from_secs(60);
+        activity_service
+            .save_activity_state(&ActivityState {
+                start_time: Some(start - interval),
+                end_time: Some(start),
+                ..ActivityState::new()
+            })
+            .await
+            .unwrap();
+        // input stored by a previous run that stopped before creating its activity states
+        for seconds in [30, 90, 200] {
+            activity_service
+                .save_activity(&Activity::create_keyboard_activity(
+                    &KeyboardEvent {},
+                    start + Duration::from_secs(seconds),
+                ))
+                .await
+                .unwrap();
+        }
+        // two switches in the second interval, the last one too soon after the previous to count
+        let terminal_app_id = "b6791fe7-909b-4491-8554-6c00db38575e";
+        let zara_app_id = "e4d3a9b8-7622-438c-9d4e-b0bf4c9dbad2";
+        for (seconds, app_id) in [
+            (65, terminal_app_id),
+            (70, zara_app_id),
+            (80, terminal_app_id),
+            (81, zara_app_id),
+        ] {
+            let mut window = Activity::__create_test_window(None, Some(app_id.to_string()));
+            window.timestamp = Some(start + Duration::from_secs(seconds));
+            activity_service.save_activity(&window).await.unwrap();
+        }
+        // switches seen live since startup don't belong to the backfilled states
+        activity_service.app_switch_state.lock().app_switches = 7;
+
+        assert_eq!(
+            activity_service
+                .backfill_activity_states(interval)
+                .await
+                .unwrap(),
+            4
+        );
+        assert_eq!(
+            activity_service
+                .backfill_activity_states(interval)
+                .await
+                .unwrap(),
+            0
+        );
+        let activity_states = activity_service
+            .get_activity_starting_states_between(start, clock.now())
+            .await
+            .unwrap();
+        assert_eq!(
+            activity_states
+                .iter()
+                .map(|activity_state| &activity_state.state)
+                .collect::<Vec<_>>(),
+            vec![
+                &ActivityStateType::Active,
+                &ActivityStateType::Active,
+                &ActivityStateType::Inactive,
+                &ActivityStateType::Active,
+            ]
+        );
+        assert_eq!(
+            activity_states.last().unwrap().end_time,
+            Some(start + interval * 4)
+        );
+        assert_eq!(
+            activity_states
+                .iter()
+                .map(|activity_state| activity_state.app_switches)
+                .collect::<Vec<_>>(),
+            vec![0, 2, 0, 0]
+        );
+        assert_eq!(activity_service.app_switch_state.lock().app_switches, 7);
+
+        // the rest of the downtime has nothing stored and becomes a gap on the first tick
+        activity_service
+            .process_activity_state_tick(interval)
+            .await
+            .unwrap();
+        let activity_gaps = ActivityGapRepo::new(pool)
+            .get_activity_gaps_between(start, clock.now())
+            .await
+            .unwrap();
+        assert_eq!(activity_gaps.len(), 1);
+        assert_eq!(activity_gaps[0].start_time, start + interval * 4);
+        assert_eq!(
+            activity_gaps[0].reason,
+            crate::db::models::ActivityGapReason::ServiceNotRunning
+        );
     }
 
     #[tokio::test]
@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use crate::db::models::{Activity, ActivityType};
+
+/// Pending activities that trigger a write unless configured otherwise.
+pub(crate) const DEFAULT_MAX_SIZE: usize = 256;
+/// Age of the oldest pending activity that triggers a write unless configured otherwise.
+pub(crate) const DEFAULT_MAX_AGE: Duration = Duration::from_secs(10);
+
+/**
+ * In-memory ingest buffer for raw activities.
+ * Input activities are coalesced (repeated mouse/keyboard events for the same app within
+ * `coalesce_window` are dropped) and held until either `max_size` activities are pending
+ * or the oldest pending activity is older than `max_age`, at which point the owner flushes
+ * them to the database in a single transaction.
+ */
+pub struct ActivityBuffer {
+    pending: Vec<Activity>,
+    last_window_app_id: Option<String>,
+    max_size: usize,
+    max_age: Duration,
+    coalesce_window: Duration,
+}
+
+impl ActivityBuffer {
+    pub fn new(max_size: usize, max_age: Duration) -> Self {
+        ActivityBuffer {
+            pending: Vec::new(),
+            last_window_app_id: None,
+            max_size: max_size.max(1),
+            max_age,
+            coalesce_window: Duration::from_secs(1),
+        }
+    }
+
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /**
+     * Adds an activity to the buffer. Input activities without an app_id inherit the app of the
+     * last window activity seen, so the repo doesn't need to look it up on every insert.
+     * Returns true if the buffer should be flushed.
+     */
+    pub fn push(&mut self, mut activity: Activity, now: OffsetDateTime) -> bool {
+        if activity.app_id.is_none() {
+            activity.app_id = self.last_window_app_id.clone();
+        }
+        if activity.activity_type == ActivityType::Window {
+            self.last_window_app_id = activity.app_id.clone();
+        } else if self.is_duplicate_input(&activity) {
+            log::trace!("coalesced {:?} activity", activity.activity_type);
+            return self.should_flush(now);
+        }
+        self.pending.push(activity);
+        self.should_flush(now)
+    }
+
+    pub fn should_flush(&self, now: OffsetDateTime) -> bool {
+        if self.pending.len() >= self.max_size {
+            return true;
+        }
+        match self.pending.first().and_then(|a| a.timestamp) {
+            Some(oldest) => now - oldest >= self.max_age,
+            None => false,
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<Activity> {
+        std::mem::take(&mut self.pending)
+    }
+
+    // puts back activities that failed to be written so they are retried on the next flush
+    pub fn restore(&mut self, mut activities: Vec<Activity>) {
+        activities.append(&mut self.pending);
+        self.pending = activities;
+    }
+
+    fn is_duplicate_input(&self, activity: &Activity) -> bool {
+        let Some(last) = self
+            .pending
+            .iter()
+            .rev()
+            .find(|a| a.activity_type == activity.activity_type)
+        else {
+            return false;
+        };
+        match (last.timestamp, activity.timestamp) {
+            (Some(last_timestamp), Some(timestamp)) => {
+                last.app_id == activity.app_id && timestamp - last_timestamp < self.coalesce_window
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::db::types::Platform;
+
+    use super::*;
+
+    fn input_activity(activity_type: ActivityType, timestamp: OffsetDateTime) -> Activity {
+        Activity::new(activity_type, None, timestamp, Platform::Mac, None)
+    }
+
+    #[test]
+    fn test_push_inherits_last_window_app_id() {
+        let mut buffer = ActivityBuffer::new(10, Duration::from_secs(60));
+        let now = OffsetDateTime::now_utc();
+        let app_id = Uuid::new_v4().to_string();
+        buffer.push(
+            Activity::__create_test_window(None, Some(app_id.clone())),
+            now,
+        );
+        buffer.push(input_activity(ActivityType::Keyboard, now), now);
+
+        let activities = buffer.take();
+        assert_eq!(activities.len(), 2);
+        assert_eq!(activities[1].app_id, Some(app_id));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_push_coalesces_repeated_input() {
+        let mut buffer = ActivityBuffer::new(10, Duration::from_secs(60));
+        let now = OffsetDateTime::now_utc();
+        buffer.push(input_activity(ActivityType::Mouse, now), now);
+        buffer.push(
+            input_activity(ActivityType::Mouse, now + Duration::from_millis(200)),
+            now,
+        );
+        buffer.push(
+            input_activity(ActivityType::Keyboard, now + Duration::from_millis(300)),
+            now,
+        );
+        buffer.push(
+            input_activity(ActivityType::Mouse, now + Duration::from_secs(2)),
+            now,
+        );
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_should_flush_on_size() {
+        let mut buffer = ActivityBuffer::new(2, Duration::from_secs(60));
+        let now = OffsetDateTime::now_utc();
+        assert!(!buffer.push(input_activity(ActivityType::Mouse, now), now));
+        assert!(buffer.push(input_activity(ActivityType::Keyboard, now), now));
+    }
+
+    #[test]
+    fn test_should_flush_on_age() {
+        let mut buffer = ActivityBuffer::new(10, Duration::from_secs(5));
+        let now = OffsetDateTime::now_utc();
+        assert!(!buffer.push(input_activity(ActivityType::Mouse, now), now));
+        assert!(!buffer.should_flush(now + Duration::from_secs(4)));
+        assert!(buffer.should_flush(now + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_restore_keeps_order() {
+        let mut buffer = ActivityBuffer::new(10, Duration::from_secs(60));
+        let now = OffsetDateTime::now_utc();
+        buffer.push(input_activity(ActivityType::Mouse, now), now);
+        let failed = buffer.take();
+        buffer.push(
+            input_activity(ActivityType::Keyboard, now + Duration::from_secs(1)),
+            now,
+        );
+        buffer.restore(failed);
+        let activities = buffer.take();
+        assert_eq!(activities[0].activity_type, ActivityType::Mouse);
+        assert_eq!(activities[1].activity_type, ActivityType::Keyboard);
+    }
+}
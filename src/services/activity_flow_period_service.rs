@@ -0,0 +1,376 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use time::OffsetDateTime;
+use tokio::sync::watch;
+
+use crate::{
+    db::{
+        activity_flow_period_repo::ActivityFlowPeriodRepo,
+        activity_state_repo::ActivityStateRepo,
+        models::{ActivityFlowPeriod, ActivityState, ActivityStateType},
+        tag_repo::TagRepo,
+    },
+    error::Result,
+    services::activity_state_service::ActivityPeriod,
+    utils::{
+        clock::{Clock, SystemClock},
+        shutdown::stopped,
+    },
+};
+
+const MAX_SCORE: f64 = 10.0;
+const MAX_SWITCH_PENALTY: f64 = 0.5;
+
+/**
+ * Aggregates the activity states of a period (10 minutes by default) into a scored
+ * `ActivityFlowPeriod`. See `calculate_flow_score` for how the score is derived.
+ */
+#[derive(Clone)]
+pub struct ActivityFlowPeriodService {
+    activity_flow_period_repo: ActivityFlowPeriodRepo,
+    activity_state_repo: ActivityStateRepo,
+    tag_repo: TagRepo,
+    clock: Arc<dyn Clock>,
+}
+
+impl ActivityFlowPeriodService {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        ActivityFlowPeriodService {
+            activity_flow_period_repo: ActivityFlowPeriodRepo::new(pool.clone()),
+            activity_state_repo: ActivityStateRepo::new(pool.clone()),
+            tag_repo: TagRepo::new(pool),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub async fn get_last_activity_flow_period(&self) -> Result<ActivityFlowPeriod> {
+        self.activity_flow_period_repo
+            .get_last_activity_flow_period()
+            .await
+    }
+
+    pub async fn get_activity_flow_periods_between(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<Vec<ActivityFlowPeriod>> {
+        self.activity_flow_period_repo
+            .get_activity_flow_periods_between(start_time, end_time)
+            .await
+    }
+
+    /**
+     * The flow period that just completed. Continues from the end of the last flow period unless
+     * it is more than an interval behind (e.g. the service was not running), in which case the
+     * period is the last interval before now.
+     */
+    pub async fn get_just_completed_activity_flow_period(
+        &self,
+        interval: Duration,
+    ) -> Result<ActivityPeriod> {
+        let now = self.clock.now();
+        let start_time = match self.get_last_activity_flow_period().await {
+            Ok(last_period) if last_period.end_time + Duration::from_secs(5) > now - interval => {
+                last_period.end_time
+            }
+            Ok(_) => now - interval,
+            Err(err) if err.is_not_found() => now - interval,
+            Err(err) => return Err(err),
+        };
+        Ok(ActivityPeriod {
+            start_time,
+            end_time: start_time + interval,
+        })
+    }
+
+    pub async fn create_activity_flow_period(
+        &self,
+        activity_period: &ActivityPeriod,
+    ) -> Result<ActivityFlowPeriod> {
+        let activity_states = self
+            .activity_state_repo
+            .get_activity_states_for_activity_period(activity_period)
+            .await?;
+        let state_tags = self
+            .tag_repo
+            .get_default_tag_names_for_activity_states_between(
+                activity_period.start_time,
+                activity_period.end_time,
+            )
+            .await?;
+
+        let mut activity_flow_period =
+            summarize_activity_states(activity_period, &activity_states, &state_tags);
+        activity_flow_period.score = calculate_flow_score(&activity_flow_period);
+        self.activity_flow_period_repo
+            .save_activity_flow_period(&activity_flow_period)
+            .await?;
+        log::trace!(
+            "activity flow period created with score {}",
+            activity_flow_period.score
+        );
+        Ok(activity_flow_period)
+    }
+
+    /**
+     * Creates an activity flow period every `flow_period_interval` until `true` is sent on `shutdown`.
+     * A period being written completes before the loop stops.
+     */
+    pub fn start_flow_period_loop(
+        &self,
+        flow_period_interval: Duration,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> tokio::task::JoinHandle<()> {
+        let activity_flow_period_service = self.clone();
+        tokio::spawn(async move {
+            let mut wait_interval = tokio::time::interval(flow_period_interval);
+            wait_interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = wait_interval.tick() => {}
+                    _ = stopped(&mut shutdown) => break,
+                }
+                let activity_period = match activity_flow_period_service
+                    .get_just_completed_activity_flow_period(flow_period_interval)
+                    .await
+                {
+                    Ok(activity_period) => activity_period,
+                    Err(err) => {
+                        log::error!("Failed to get next activity flow period: {}", err);
+                        continue;
+                    }
+                };
+                if let Err(err) = activity_flow_period_service
+                    .create_activity_flow_period(&activity_period)
+                    .await
+                {
+                    log::error!("Failed to create activity flow period: {}", err);
+                }
+            }
+        })
+    }
+}
+
+/**
+ * Sums the activity states of a period into active/inactive time, app switches and the time
+ * spent in states tagged `creating` or `consuming`. Paused states are skipped. The score is left at 0.
+ */
+fn summarize_activity_states(
+    activity_period: &ActivityPeriod,
+    activity_states: &[ActivityState],
+    state_tags: &[(i64, String)],
+) -> ActivityFlowPeriod {
+    let mut tags_by_state: HashMap<i64, Vec<&str>> = HashMap::new();
+    for (activity_state_id, tag_name) in state_tags {
+        tags_by_state
+            .entry(*activity_state_id)
+            .or_default()
+            .push(tag_name);
+    }
+
+    let mut activity_flow_period = ActivityFlowPeriod {
+        id: None,
+        start_time: activity_period.start_time,
+        end_time: activity_period.end_time,
+        score: 0.0,
+        app_switches: 0,
+        active_time: 0,
+        inactive_time: 0,
+        creating_time: 0,
+        consuming_time: 0,
+        created_at: None,
+    };
+    for activity_state in activity_states {
+        // paused time wasn't recorded, so it's left out instead of counting as inactive
+        if activity_state.state == ActivityStateType::Paused {
+            continue;
+        }
+        let seconds = activity_state.duration_seconds();
+        // passive time (e.g. a video call) counts as active, its tags tell creating from consuming
+        let active_seconds = match activity_state.state {
+            ActivityStateType::Passive => seconds,
+            _ => activity_state.active_duration_seconds(),
+        };
+        activity_flow_period.app_switches += activity_state.app_switches;
+        activity_flow_period.active_time += active_seconds;
+        activity_flow_period.inactive_time += seconds - active_seconds;
+        let tags = activity_state
+            .id
+            .and_then(|id| tags_by_state.get(&id))
+            .map(|tags| tags.as_slice())
+            .unwrap_or_default();
+        if tags.contains(&"creating") {
+            activity_flow_period.creating_time += seconds;
+        }
+        if tags.contains(&"consuming") {
+            activity_flow_period.consuming_time += seconds;
+        }
+    }
+    activity_flow_period
+}
+
+/**
+ * Scores a flow period from 0 to 10:
+ * - the share of the period that was active
+ * - scaled by intent: 1 when all tagged time was creating, 0 when all of it was consuming, 0.5 when untagged
+ * - reduced by up to half for app switching, reaching the maximum penalty at one switch per active minute
+ */
+pub fn calculate_flow_score(activity_flow_period: &ActivityFlowPeriod) -> f64 {
+    let total_time = activity_flow_period.active_time + activity_flow_period.inactive_time;
+    if total_time <= 0 || activity_flow_period.active_time <= 0 {
+        return 0.0;
+    }
+    let active_ratio = activity_flow_period.active_time as f64 / total_time as f64;
+
+    let tagged_time = activity_flow_period.creating_time + activity_flow_period.consuming_time;
+    let intent = if tagged_time > 0 {
+        let balance = (activity_flow_period.creating_time - activity_flow_period.consuming_time)
+            as f64
+            / tagged_time as f64;
+        (balance + 1.0) / 2.0
+    } else {
+        0.5
+    };
+
+    let active_minutes = activity_flow_period.active_time as f64 / 60.0;
+    let switch_rate = (activity_flow_period.app_switches as f64 / active_minutes).min(1.0);
+    let switch_penalty = switch_rate * MAX_SWITCH_PENALTY;
+
+    MAX_SCORE * active_ratio * intent * (1.0 - switch_penalty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::db_manager, utils::clock::ManualClock};
+
+    fn flow_period(
+        active_time: i64,
+        inactive_time: i64,
+        creating_time: i64,
+        consuming_time: i64,
+        app_switches: i64,
+    ) -> ActivityFlowPeriod {
+        let now = OffsetDateTime::now_utc();
+        ActivityFlowPeriod {
+            id: None,
+            start_time: now - Duration::from_secs(600),
+            end_time: now,
+            score: 0.0,
+            app_switches,
+            active_time,
+            inactive_time,
+            creating_time,
+            consuming_time,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_calculate_flow_score() {
+        assert_eq!(calculate_flow_score(&flow_period(600, 0, 600, 0, 0)), 10.0);
+        assert_eq!(calculate_flow_score(&flow_period(600, 0, 0, 600, 0)), 0.0);
+        assert_eq!(calculate_flow_score(&flow_period(0, 600, 0, 0, 0)), 0.0);
+        assert_eq!(calculate_flow_score(&flow_period(300, 300, 300, 0, 0)), 5.0);
+        assert_eq!(calculate_flow_score(&flow_period(600, 0, 0, 0, 0)), 5.0);
+        // one switch per active minute halves the score
+        assert_eq!(calculate_flow_score(&flow_period(600, 0, 600, 0, 10)), 5.0);
+        assert_eq!(calculate_flow_score(&flow_period(600, 0, 600, 0, 50)), 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_create_activity_flow_period() {
+        let pool = db_manager::create_test_db().await;
+        let activity_state_repo = ActivityStateRepo::new(pool.clone());
+        let tag_repo = TagRepo::new(pool.clone());
+        let activity_flow_period_service = ActivityFlowPeriodService::new(pool.clone());
+        let creating_tag = tag_repo.get_tag_by_name("creating").await.unwrap();
+        let start_time =
+            OffsetDateTime::now_utc().replace_nanosecond(0).unwrap() - Duration::from_secs(600);
+
+        for minute in 0..10 {
+            activity_state_repo
+                .create_active_activity_state(
+                    if minute < 5 { 1 } else { 0 },
+                    None,
+                    &ActivityPeriod {
+                        start_time: start_time + Duration::from_secs(minute * 60),
+                        end_time: start_time + Duration::from_secs((minute + 1) * 60),
+                    },
+                )
+                .await
+                .unwrap();
+            let activity_state = activity_state_repo.get_last_activity_state().await.unwrap();
+            if minute < 8 {
+                tag_repo
+                    .create_activity_state_tags(
+                        activity_state.id.unwrap(),
+                        std::slice::from_ref(&creating_tag),
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let activity_flow_period = activity_flow_period_service
+            .create_activity_flow_period(&ActivityPeriod {
+                start_time,
+                end_time: start_time + Duration::from_secs(600),
+            })
+            .await
+            .unwrap();
+        assert_eq!(activity_flow_period.active_time, 600);
+        assert_eq!(activity_flow_period.inactive_time, 0);
+        assert_eq!(activity_flow_period.creating_time, 480);
+        assert_eq!(activity_flow_period.consuming_time, 0);
+        assert_eq!(activity_flow_period.app_switches, 5);
+        assert_eq!(activity_flow_period.score, 7.5);
+
+        let saved = activity_flow_period_service
+            .get_last_activity_flow_period()
+            .await
+            .unwrap();
+        assert_eq!(saved.score, 7.5);
+    }
+
+    #[tokio::test]
+    async fn test_get_just_completed_activity_flow_period() {
+        let pool = db_manager::create_test_db().await;
+        let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+        let clock = Arc::new(ManualClock::new(now));
+        let activity_flow_period_service =
+            ActivityFlowPeriodService::new(pool).with_clock(clock.clone());
+        let interval = Duration::from_secs(600);
+
+        let first_period = activity_flow_period_service
+            .get_just_completed_activity_flow_period(interval)
+            .await
+            .unwrap();
+        assert_eq!(first_period.start_time, now - interval);
+        assert_eq!(first_period.end_time, now);
+        activity_flow_period_service
+            .create_activity_flow_period(&first_period)
+            .await
+            .unwrap();
+
+        clock.advance(interval);
+        let next_period = activity_flow_period_service
+            .get_just_completed_activity_flow_period(interval)
+            .await
+            .unwrap();
+        assert_eq!(next_period.start_time, now);
+
+        // after downtime the period restarts an interval before now
+        clock.advance(interval * 3);
+        let period_after_gap = activity_flow_period_service
+            .get_just_completed_activity_flow_period(interval)
+            .await
+            .unwrap();
+        assert_eq!(period_after_gap.start_time, clock.now() - interval);
+    }
+}
@@ -1,8 +1,18 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use time::OffsetDateTime;
 
-use crate::db::{activity_state_repo::ActivityStateRepo, models::ActivityState};
+use crate::{
+    db::{
+        activity_state_repo::ActivityStateRepo,
+        models::{Activity, ActivityGap, ActivityGapReason, ActivityState, ActivityType},
+    },
+    error::Result,
+    utils::clock::{Clock, SystemClock},
+};
+
+/// How far apart the last activity state's end and the next period's start may be without a gap.
+const CONTINUITY_TOLERANCE: Duration = Duration::from_secs(5);
 
 #[derive(Clone, Debug)]
 pub struct ActivityPeriod {
@@ -10,49 +20,249 @@ pub struct ActivityPeriod {
     pub end_time: OffsetDateTime,
 }
 
+/**
+ * Splits a period between the windows focused during it, in order. `carried_over` is the last window
+ * focused before the period started; without one, the first window is assumed focused from the start.
+ * `windows` must be sorted by timestamp. Windows focused within the period are kept even with 0 seconds.
+ */
+pub(crate) fn window_focus_seconds<'a>(
+    activity_period: &ActivityPeriod,
+    carried_over: Option<&'a Activity>,
+    windows: &'a [Activity],
+) -> Vec<(&'a Activity, f64)> {
+    let mut focus_seconds = Vec::new();
+    let mut focused = carried_over.or(windows.first());
+    let mut cursor = activity_period.start_time;
+    for window in windows {
+        let focused_at = window
+            .timestamp
+            .unwrap_or(cursor)
+            .clamp(activity_period.start_time, activity_period.end_time)
+            .max(cursor);
+        let seconds = (focused_at - cursor).as_seconds_f64();
+        match focused {
+            // the carried over window doesn't count when another one was focused right at the start
+            Some(focused)
+                if seconds == 0.0 && carried_over.is_some_and(|c| std::ptr::eq(c, focused)) => {}
+            Some(focused) => focus_seconds.push((focused, seconds)),
+            None => {}
+        }
+        focused = Some(window);
+        cursor = focused_at;
+    }
+    if let Some(focused) = focused {
+        focus_seconds.push((
+            focused,
+            (activity_period.end_time - cursor)
+                .as_seconds_f64()
+                .max(0.0),
+        ));
+    }
+    focus_seconds
+}
+
+/**
+ * Decides whether an interval's activities make it an active state. By default any activity does.
+ * Only keyboard and mouse activities count as input; window activities never make a state active on
+ * their own once `idle_after` is set.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdleThresholds {
+    min_input_events: usize,
+    min_active_seconds: u64,
+    idle_after: Option<Duration>,
+    partial_active_states: bool,
+}
+
+impl IdleThresholds {
+    /// Intervals with fewer keyboard and mouse events are idle.
+    pub fn with_min_input_events(mut self, min_input_events: usize) -> Self {
+        self.min_input_events = min_input_events;
+        self
+    }
+
+    /// Intervals with fewer active seconds are idle. Only meaningful together with `with_idle_after`.
+    pub fn with_min_active_seconds(mut self, min_active_seconds: u64) -> Self {
+        self.min_active_seconds = min_active_seconds;
+        self
+    }
+
+    /// Each input event keeps the user active for `idle_after`; time past that without input is idle.
+    pub fn with_idle_after(mut self, idle_after: Duration) -> Self {
+        self.idle_after = Some(idle_after);
+        self
+    }
+
+    /// Records the active seconds of active states that were only active for part of the interval.
+    pub fn with_partial_active_states(mut self) -> Self {
+        self.partial_active_states = true;
+        self
+    }
+
+    pub fn records_partial_active_states(&self) -> bool {
+        self.partial_active_states
+    }
+
+    /**
+     * Returns the active seconds of the period, or `None` when the period is idle.
+     * Without `idle_after` the whole period is active as soon as there is any activity.
+     */
+    pub(crate) fn active_seconds(
+        &self,
+        activities: &[Activity],
+        activity_period: &ActivityPeriod,
+    ) -> Option<f64> {
+        if activities.is_empty() {
+            return None;
+        }
+        let mut input_times = activities
+            .iter()
+            .filter(|activity| activity.activity_type != ActivityType::Window)
+            .filter_map(|activity| activity.timestamp)
+            .collect::<Vec<_>>();
+        if input_times.len() < self.min_input_events {
+            return None;
+        }
+        let period_seconds = (activity_period.end_time - activity_period.start_time)
+            .as_seconds_f64()
+            .max(0.0);
+        let active_seconds = match self.idle_after {
+            None => period_seconds,
+            Some(idle_after) => {
+                input_times.sort();
+                let mut active_seconds = 0.0;
+                let mut covered_until = activity_period.start_time;
+                for input_time in input_times {
+                    let active_from = input_time
+                        .clamp(activity_period.start_time, activity_period.end_time)
+                        .max(covered_until);
+                    let active_until = (input_time + idle_after).min(activity_period.end_time);
+                    if active_until > active_from {
+                        active_seconds += (active_until - active_from).as_seconds_f64();
+                        covered_until = active_until;
+                    }
+                }
+                active_seconds
+            }
+        };
+        if active_seconds <= 0.0 || active_seconds < self.min_active_seconds as f64 {
+            return None;
+        }
+        Some(active_seconds)
+    }
+}
+
 #[derive(Clone)]
 pub struct ActivityStateService {
     activity_state_repo: ActivityStateRepo,
+    clock: Arc<dyn Clock>,
 }
 
 impl ActivityStateService {
     pub fn new(pool: sqlx::SqlitePool) -> Self {
         ActivityStateService {
             activity_state_repo: ActivityStateRepo::new(pool.clone()),
+            clock: Arc::new(SystemClock),
         }
     }
 
-    pub async fn get_last_activity_state(&self) -> Result<ActivityState, sqlx::Error> {
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub async fn get_last_activity_state(&self) -> Result<ActivityState> {
         self.activity_state_repo.get_last_activity_state().await
     }
 
-    pub async fn get_just_completed_activity_state(&self, interval: Duration) -> ActivityPeriod {
-        let (start_time, end_time) = match self.activity_state_repo.get_last_activity_state().await
-        {
-            Ok(last_state) => {
-                let start_time = if last_state.end_time.unwrap_or(OffsetDateTime::now_utc())
-                    + Duration::from_secs(5)
-                    > OffsetDateTime::now_utc() - interval
-                {
-                    log::trace!("start time is last state end time");
-                    last_state.end_time.unwrap_or(OffsetDateTime::now_utc())
-                } else {
-                    log::trace!("start time is an interval before now");
-                    OffsetDateTime::now_utc() - interval
-                };
-                (start_time, start_time + interval)
-            }
-            Err(sqlx::Error::RowNotFound) => {
+    #[cfg(test)]
+    pub async fn get_just_completed_activity_state(
+        &self,
+        interval: Duration,
+    ) -> Result<ActivityPeriod> {
+        Ok(self
+            .get_just_completed_activity_state_with_gap(interval, None)
+            .await?
+            .0)
+    }
+
+    /**
+     * Returns the period that just completed, and the gap between it and the last activity state if
+     * the loop skipped time. `since_last_tick` is the monotonic time since the loop's previous tick,
+     * `None` on its first tick after the service started. If the clock moved back before the end of
+     * the last activity state, both are empty and at now.
+     */
+    pub async fn get_just_completed_activity_state_with_gap(
+        &self,
+        interval: Duration,
+        since_last_tick: Option<Duration>,
+    ) -> Result<(ActivityPeriod, Option<ActivityGap>)> {
+        let now = self.clock.now();
+        let last_end_time = match self.activity_state_repo.get_last_activity_state().await {
+            Ok(last_state) => Some(last_state.end_time.unwrap_or(now)),
+            Err(err) if err.is_not_found() => {
                 log::trace!("no last activity state");
-                let now = OffsetDateTime::now_utc();
-                (now - interval, now)
+                None
             }
-            Err(e) => panic!("Database error: {}", e),
+            Err(err) => return Err(err),
         };
-        ActivityPeriod {
-            start_time,
-            end_time,
+        let Some(last_end_time) = last_end_time else {
+            return Ok((
+                ActivityPeriod {
+                    start_time: now - interval,
+                    end_time: now,
+                },
+                None,
+            ));
+        };
+        let period_start_time = now - interval;
+        if last_end_time > now + CONTINUITY_TOLERANCE {
+            log::warn!(
+                "clock moved back to {}, last state ended {}",
+                now,
+                last_end_time
+            );
+            // the time up to the last state is already recorded, so the jump is only marked with an
+            // empty gap and state at now, and the next period starts from there
+            return Ok((
+                ActivityPeriod {
+                    start_time: now,
+                    end_time: now,
+                },
+                Some(ActivityGap::new(now, now, ActivityGapReason::ClockJump)),
+            ));
+        }
+        if last_end_time + CONTINUITY_TOLERANCE > period_start_time {
+            log::trace!("start time is last state end time");
+            return Ok((
+                ActivityPeriod {
+                    start_time: last_end_time,
+                    end_time: last_end_time + interval,
+                },
+                None,
+            ));
         }
+        log::trace!("start time is an interval before now");
+        let gap_duration = period_start_time - last_end_time;
+        // asleep, the monotonic clock stops while the wall clock keeps going (a clock set forward
+        // looks the same). a loop that wasn't running, or stalled, misses the time on both clocks
+        let reason = match since_last_tick {
+            Some(since_last_tick) if since_last_tick < gap_duration => ActivityGapReason::Suspend,
+            _ => ActivityGapReason::ServiceNotRunning,
+        };
+        log::warn!(
+            "no activity states from {} to {} ({:?})",
+            last_end_time,
+            period_start_time,
+            reason
+        );
+        Ok((
+            ActivityPeriod {
+                start_time: period_start_time,
+                end_time: now,
+            },
+            Some(ActivityGap::new(last_end_time, period_start_time, reason)),
+        ))
     }
 }
 
@@ -62,18 +272,165 @@ mod tests {
 
     use crate::{
         db::{db_manager, models::ActivityState},
-        utils::test_utils::assert_datetime_eq,
+        utils::{clock::ManualClock, test_utils::assert_datetime_eq},
     };
 
     use super::*;
 
+    #[test]
+    fn test_window_focus_seconds() {
+        let start_time = OffsetDateTime::UNIX_EPOCH;
+        let window = |app_id: &str, seconds: i64| Activity {
+            timestamp: Some(start_time + time::Duration::seconds(seconds)),
+            ..Activity::__create_test_window(None, Some(app_id.to_string()))
+        };
+        let activity_period = ActivityPeriod {
+            start_time,
+            end_time: start_time + time::Duration::seconds(60),
+        };
+        let carried_over = window("editor", -300);
+        let windows = vec![window("browser", 20), window("editor", 50)];
+
+        let focus_seconds = window_focus_seconds(&activity_period, Some(&carried_over), &windows)
+            .into_iter()
+            .map(|(window, seconds)| (window.app_id.clone().unwrap(), seconds))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            focus_seconds,
+            vec![
+                ("editor".to_string(), 20.0),
+                ("browser".to_string(), 30.0),
+                ("editor".to_string(), 10.0),
+            ]
+        );
+
+        // without a carried over window the first one is assumed focused from the start
+        let focus_seconds = window_focus_seconds(&activity_period, None, &windows);
+        assert_eq!(focus_seconds[0].0.app_id.as_deref(), Some("browser"));
+        assert_eq!(
+            focus_seconds
+                .iter()
+                .map(|(_, seconds)| seconds)
+                .sum::<f64>(),
+            60.0
+        );
+        assert!(window_focus_seconds(&activity_period, None, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_idle_thresholds_active_seconds() {
+        let start_time = OffsetDateTime::UNIX_EPOCH;
+        let activity_period = ActivityPeriod {
+            start_time,
+            end_time: start_time + time::Duration::seconds(60),
+        };
+        let at = |activity: Activity, seconds: i64| Activity {
+            timestamp: Some(start_time + time::Duration::seconds(seconds)),
+            ..activity
+        };
+        let mouse = |seconds| {
+            at(
+                Activity::create_mouse_activity(&os_monitor::MouseEvent {}, start_time),
+                seconds,
+            )
+        };
+        let window = at(Activity::__create_test_window(None, None), 0);
+
+        let defaults = IdleThresholds::default();
+        assert_eq!(defaults.active_seconds(&[], &activity_period), None);
+        assert_eq!(
+            defaults.active_seconds(std::slice::from_ref(&window), &activity_period),
+            Some(60.0)
+        );
+
+        let thresholds = IdleThresholds::default()
+            .with_min_input_events(2)
+            .with_idle_after(Duration::from_secs(10));
+        // a single stray mouse event doesn't make the minute active
+        assert_eq!(
+            thresholds.active_seconds(&[window.clone(), mouse(30)], &activity_period),
+            None
+        );
+        // overlapping input counts once and input near the end is cut off at the period end
+        assert_eq!(
+            thresholds.active_seconds(&[mouse(5), mouse(10), mouse(55)], &activity_period),
+            Some(20.0)
+        );
+        assert_eq!(
+            thresholds
+                .with_min_active_seconds(30)
+                .active_seconds(&[mouse(5), mouse(10), mouse(55)], &activity_period),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_just_completed_activity_state_with_gap() {
+        let pool = db_manager::create_test_db().await;
+        let now = OffsetDateTime::now_utc();
+        let clock = Arc::new(ManualClock::new(now));
+        let activity_state_service =
+            ActivityStateService::new(pool.clone()).with_clock(clock.clone());
+        let interval = Duration::from_secs(60);
+        ActivityStateRepo::new(pool)
+            .save_activity_state(&ActivityState {
+                start_time: Some(now - interval),
+                end_time: Some(now),
+                ..ActivityState::new()
+            })
+            .await
+            .unwrap();
+
+        clock.advance(interval);
+        let (activity_period, activity_gap) = activity_state_service
+            .get_just_completed_activity_state_with_gap(interval, Some(interval))
+            .await
+            .unwrap();
+        assert_eq!(activity_period.start_time, now);
+        assert_eq!(activity_gap, None);
+
+        // the loop ticked once a minute on the monotonic clock while two hours passed
+        clock.set(now + Duration::from_secs(2 * 60 * 60));
+        let (activity_period, activity_gap) = activity_state_service
+            .get_just_completed_activity_state_with_gap(interval, Some(interval))
+            .await
+            .unwrap();
+        assert_eq!(activity_period.end_time, clock.now());
+        let activity_gap = activity_gap.unwrap();
+        assert_eq!(activity_gap.reason, ActivityGapReason::Suspend);
+        assert_eq!(activity_gap.start_time, now);
+        assert_eq!(activity_gap.end_time, activity_period.start_time);
+
+        let (_, activity_gap) = activity_state_service
+            .get_just_completed_activity_state_with_gap(interval, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            activity_gap.unwrap().reason,
+            ActivityGapReason::ServiceNotRunning
+        );
+
+        clock.set(now - Duration::from_secs(60 * 60));
+        let (activity_period, activity_gap) = activity_state_service
+            .get_just_completed_activity_state_with_gap(interval, Some(interval))
+            .await
+            .unwrap();
+        assert_eq!(activity_period.start_time, clock.now());
+        assert_eq!(activity_period.end_time, clock.now());
+        let activity_gap = activity_gap.unwrap();
+        assert_eq!(activity_gap.reason, ActivityGapReason::ClockJump);
+        assert_eq!(activity_gap.start_time, clock.now());
+        assert_eq!(activity_gap.end_time, clock.now());
+    }
+
     #[tokio::test]
     async fn test_get_just_completed_activity_state_no_last_activity_state() {
         let pool = db_manager::create_test_db().await;
         let activity_state_service = ActivityStateService::new(pool.clone());
         let activity_period = activity_state_service
             .get_just_completed_activity_state(Duration::from_secs(120))
-            .await;
+            .await
+            .unwrap();
 
         assert_datetime_eq(
             activity_period.start_time,
@@ -87,6 +444,22 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_get_just_completed_activity_state_with_manual_clock() {
+        let pool = db_manager::create_test_db().await;
+        let start = OffsetDateTime::now_utc() - Duration::from_secs(24 * 60 * 60);
+        let clock = Arc::new(ManualClock::new(start));
+        let activity_state_service =
+            ActivityStateService::new(pool.clone()).with_clock(clock.clone());
+        let activity_period = activity_state_service
+            .get_just_completed_activity_state(Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(activity_period.start_time, start - Duration::from_secs(60));
+        assert_eq!(activity_period.end_time, start);
+    }
+
     #[tokio::test]
     async fn test_get_just_completed_activity_state_last_activity_state_within_5_seconds() {
         let pool = db_manager::create_test_db().await;
@@ -106,7 +479,8 @@ mod tests {
 
         let activity_period = activity_state_service
             .get_just_completed_activity_state(Duration::from_secs(interval))
-            .await;
+            .await
+            .unwrap();
 
         assert_datetime_eq(
             activity_period.start_time,
@@ -138,7 +512,8 @@ mod tests {
 
         let activity_period = activity_state_service
             .get_just_completed_activity_state(Duration::from_secs(interval))
-            .await;
+            .await
+            .unwrap();
 
         assert_datetime_eq(
             activity_period.start_time,
@@ -172,7 +547,8 @@ mod tests {
 
         let activity_period = activity_state_service
             .get_just_completed_activity_state(Duration::from_secs(interval))
-            .await;
+            .await
+            .unwrap();
         assert_datetime_eq(
             activity_period.start_time,
             OffsetDateTime::now_utc() - Duration::from_secs(interval),
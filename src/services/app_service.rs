@@ -1,20 +1,46 @@
+use std::collections::{HashMap, HashSet};
+
 use os_monitor::WindowEvent;
 
-use crate::db::{
-    activity_repo::ActivityRepo,
-    app_repo::AppRepo,
-    models::{Activity, ActivityType, App},
-    tag_repo::TagRepo,
+use crate::{
+    db::{
+        activity_repo::ActivityRepo,
+        activity_state_app_repo::ActivityStateAppRepo,
+        app_repo::AppRepo,
+        models::{Activity, ActivityStateApp, ActivityType, App, Tag, TagRule},
+        tag_repo::TagRepo,
+        tag_rule_repo::TagRuleRepo,
+    },
+    error::{Error, Result},
+    services::{
+        activity_state_service::{window_focus_seconds, ActivityPeriod},
+        tag_rule_service::TagRuleMatcherCache,
+    },
 };
 
-#[cfg(test)]
-use crate::db::models::Tag;
+/**
+ * The window focused when a period started and the window activities within it, in order. Loaded once
+ * per activity state and shared by everything that splits the period between windows.
+ */
+pub struct PeriodWindows {
+    carried_over: Option<Activity>,
+    windows: Vec<Activity>,
+}
+
+impl PeriodWindows {
+    fn focus_seconds(&self, activity_period: &ActivityPeriod) -> Vec<(&Activity, f64)> {
+        window_focus_seconds(activity_period, self.carried_over.as_ref(), &self.windows)
+    }
+}
 
 #[derive(Clone)]
 pub struct AppService {
     app_repo: AppRepo,
     tag_repo: TagRepo,
+    tag_rule_repo: TagRuleRepo,
     activity_repo: ActivityRepo,
+    activity_state_app_repo: ActivityStateAppRepo,
+    tag_rule_matcher: TagRuleMatcherCache,
 }
 
 impl AppService {
@@ -22,65 +48,232 @@ impl AppService {
         AppService {
             app_repo: AppRepo::new(pool.clone()),
             tag_repo: TagRepo::new(pool.clone()),
+            tag_rule_repo: TagRuleRepo::new(pool.clone()),
             activity_repo: ActivityRepo::new(pool.clone()),
+            activity_state_app_repo: ActivityStateAppRepo::new(pool.clone()),
+            tag_rule_matcher: TagRuleMatcherCache::default(),
         }
     }
 
+    pub async fn get_tags_for_app(&self, app_id: &str) -> Result<Vec<Tag>> {
+        self.tag_repo.get_tags_by_app_id(app_id).await
+    }
+
     pub async fn create_idle_tag(
         &self,
         activity_state_id: i64,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
-        let idle_tag = self
-            .tag_repo
-            .get_tag_by_name("idle")
-            .await
-            .expect("Failed to get idle tag");
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let idle_tag = self.tag_repo.get_tag_by_name("idle").await?;
         self.tag_repo
-            .create_activity_state_tags(activity_state_id, &vec![idle_tag])
+            .create_activity_state_tags(activity_state_id, &[idle_tag])
+            .await
+    }
+
+    /**
+     * Records how many seconds each app and window title was focused during an activity state.
+     */
+    pub async fn create_activity_state_apps(
+        &self,
+        period_windows: &PeriodWindows,
+        activity_state_id: i64,
+        activity_period: &ActivityPeriod,
+    ) -> Result<()> {
+        let mut focus_seconds: HashMap<(String, Option<String>), f64> = HashMap::new();
+        for (window, seconds) in period_windows.focus_seconds(activity_period) {
+            if let Some(app_id) = &window.app_id {
+                *focus_seconds
+                    .entry((app_id.clone(), window.app_window_title.clone()))
+                    .or_default() += seconds;
+            }
+        }
+        let activity_state_apps = focus_seconds
+            .into_iter()
+            .map(|((app_id, window_title), seconds)| ActivityStateApp {
+                activity_state_id,
+                app_id,
+                window_title,
+                seconds,
+            })
+            .collect::<Vec<_>>();
+        log::trace!("    app focus: {:?}", activity_state_apps);
+        self.activity_state_app_repo
+            .save_activity_state_apps(&activity_state_apps)
             .await
     }
 
+    /**
+     * Ids of the highest priority enabled tag rules matching a window, comma separated, or `None` if
+     * no rule matches. Rules are matched on the raw event when the window is focused, since its url
+     * isn't stored and its title may be redacted. The compiled rules are cached until they change.
+     */
+    pub async fn match_tag_rules(&self, event: &WindowEvent) -> Result<Option<String>> {
+        let matcher = self.tag_rule_matcher.get(&self.tag_rule_repo).await?;
+        if matcher.is_empty() {
+            return Ok(None);
+        }
+        let tag_rule_ids = matcher
+            .matching_rules(
+                &App::new(event).app_external_id,
+                Some(&event.window_title),
+                event.url.as_deref(),
+            )
+            .into_iter()
+            .map(|tag_rule| tag_rule.id.as_str())
+            .collect::<Vec<_>>();
+        Ok((!tag_rule_ids.is_empty()).then(|| tag_rule_ids.join(",")))
+    }
+
+    /**
+     * Tags an activity state from the windows focused during it. Windows that matched enabled tag
+     * rules when they were focused are tagged by those rules first; time in windows that matched no
+     * rule falls back to the app's app_tags.
+     * Every tag records its weighted duration: the seconds its window or app was focused within the
+     * period times the rule or app_tag weight.
+     */
     pub async fn create_tags_from_activities(
         &self,
-        activities: &Vec<Activity>,
+        activities: &[Activity],
+        period_windows: &PeriodWindows,
         activity_state_id: i64,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+        activity_period: &ActivityPeriod,
+    ) -> Result<()> {
         log::trace!("    Creating Tags From Activities");
-        // get apps from activities
-        let mut app_ids = activities
+        // the window activities carry the tag rules they matched when focused
+        let focus_seconds = period_windows.focus_seconds(activity_period);
+
+        let app_ids = focus_seconds
             .iter()
-            .filter_map(|a| a.app_id.clone())
+            .filter_map(|(window, _)| window.app_id.clone())
+            .chain(activities.iter().filter_map(|a| a.app_id.clone()))
+            .collect::<HashSet<String>>()
+            .into_iter()
             .collect::<Vec<String>>();
-        // if there are no names, use the latest window type activity
-        if app_ids.is_empty() {
-            let latest_activity = self
-                .activity_repo
-                .get_last_activity_by_type(ActivityType::Window)
-                .await
-                .expect("Failed to get last activity");
-            log::trace!(
-                "      no window changes, likely were in last activity: {:?}",
-                latest_activity
-            );
-            let app_id = latest_activity.app_id.clone();
-            if let Some(app_id) = app_id {
-                app_ids.push(app_id);
+
+        let tag_rules = self
+            .tag_rule_repo
+            .get_enabled_tag_rules()
+            .await?
+            .into_iter()
+            .map(|tag_rule| (tag_rule.id.clone(), tag_rule))
+            .collect::<HashMap<String, TagRule>>();
+        let mut rule_matches = Vec::new();
+        // seconds per app spent in windows no rule matched
+        let mut unmatched_seconds = app_ids
+            .iter()
+            .map(|app_id| (app_id.clone(), 0.0))
+            .collect::<HashMap<String, f64>>();
+        let mut matched_app_ids = HashSet::new();
+        for (window, seconds) in focus_seconds {
+            let Some(app_id) = &window.app_id else {
+                continue;
+            };
+            // rules disabled or deleted since the window was focused no longer apply
+            let rules = window
+                .tag_rule_ids
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|tag_rule_id| tag_rules.get(tag_rule_id))
+                .collect::<Vec<_>>();
+            if rules.is_empty() {
+                *unmatched_seconds.entry(app_id.clone()).or_default() += seconds;
+            } else {
+                matched_app_ids.insert(app_id.clone());
+                rule_matches.extend(
+                    rules
+                        .into_iter()
+                        .map(|rule| (app_id.clone(), rule, seconds * rule.weight)),
+                );
             }
         }
+        log::trace!("    tag rule matches: {:?}", rule_matches);
 
+        // apps only seen through matched windows don't get their app_tags
+        let unmatched_app_ids = unmatched_seconds
+            .iter()
+            .filter(|(app_id, seconds)| **seconds > 0.0 || !matched_app_ids.contains(*app_id))
+            .map(|(app_id, _)| app_id.clone())
+            .collect::<Vec<String>>();
         let app_tags = self
             .app_repo
-            .get_app_tag_by_app_ids(&app_ids)
-            .await
-            .expect("Failed to get apps");
+            .get_app_tag_by_app_ids(&unmatched_app_ids)
+            .await?
+            .into_iter()
+            .map(|app_tag| {
+                let weighted_duration = unmatched_seconds
+                    .get(&app_tag.app_id)
+                    .copied()
+                    .unwrap_or(0.0)
+                    * app_tag.weight as f64;
+                (app_tag, weighted_duration)
+            })
+            .collect::<Vec<_>>();
 
         log::trace!("    apps: {:?}", app_tags);
 
-        // for each tag, create a tag_activity_state_mapping
-        return self
-            .tag_repo
-            .create_activity_state_tags_with_app_tags(activity_state_id, &app_tags)
-            .await;
+        // for each tag, create a tag_activity_state_mapping, all or none so tagging can be retried
+        self.tag_repo
+            .create_activity_state_tags_with_tag_rules(activity_state_id, &rule_matches, &app_tags)
+            .await
+    }
+
+    /**
+     * Whether an app that is passive itself or through one of its tags (e.g. a video call) was
+     * focused during the period.
+     */
+    pub async fn has_passive_focus(
+        &self,
+        activities: &[Activity],
+        activity_period: &ActivityPeriod,
+    ) -> Result<bool> {
+        let passive_app_ids = self
+            .app_repo
+            .get_passive_app_ids()
+            .await?
+            .into_iter()
+            .collect::<HashSet<String>>();
+        if passive_app_ids.is_empty() {
+            return Ok(false);
+        }
+        Ok(self
+            .get_period_windows(activities, activity_period)
+            .await?
+            .focus_seconds(activity_period)
+            .into_iter()
+            .any(|(window, seconds)| {
+                seconds > 0.0
+                    && window
+                        .app_id
+                        .as_ref()
+                        .is_some_and(|app_id| passive_app_ids.contains(app_id))
+            }))
+    }
+
+    /**
+     * Returns the window focused when the period started (e.g. writing code to a single file for more
+     * than the interval) and the window activities within it, in order. Load them once per activity
+     * state and pass them to `create_activity_state_apps` and `create_tags_from_activities`.
+     */
+    pub async fn get_period_windows(
+        &self,
+        activities: &[Activity],
+        activity_period: &ActivityPeriod,
+    ) -> Result<PeriodWindows> {
+        let windows = activities
+            .iter()
+            .filter(|a| a.activity_type == ActivityType::Window && a.app_id.is_some())
+            .cloned()
+            .collect::<Vec<Activity>>();
+        let carried_over = self
+            .activity_repo
+            .get_last_window_activity_before(activity_period.start_time)
+            .await?
+            .filter(|activity| activity.app_id.is_some());
+        log::trace!("      carried over window: {:?}", carried_over);
+        Ok(PeriodWindows {
+            carried_over,
+            windows,
+        })
     }
 
     /**
@@ -89,78 +282,70 @@ impl AppService {
      * If the app exists in the database (by external id), we return the app.id
      * If the app does not exist, we create a new app and a default tag for it and return the new app.id
      */
-    pub async fn handle_window_event(&self, event: &WindowEvent) -> Result<String, sqlx::Error> {
-        let raw_app = App::new(&event);
-        let app = self.get_app_by_external_id(&raw_app.app_external_id).await;
-        if let Ok(app) = app {
-            return Ok(app.id.unwrap());
-        } else {
-            log::trace!("app not found, creating new app");
-            match self.save_app(&raw_app).await {
-                Ok(_) => {
-                    if let Err(err) = self.create_default_app_tag(raw_app.id.clone()).await {
-                        log::error!("Failed to create default tag for app: {}", err);
-                    }
-                    Ok(raw_app.id.clone().unwrap())
-                }
-                Err(err) => Err(err),
-            }
+    pub async fn handle_window_event(&self, event: &WindowEvent) -> Result<String> {
+        let raw_app = App::new(event);
+        match self.get_app_by_external_id(&raw_app.app_external_id).await {
+            Ok(app) => return app_id(app),
+            Err(err) if err.is_not_found() => log::trace!("app not found, creating new app"),
+            Err(err) => return Err(err),
+        }
+        self.save_app(&raw_app).await?;
+        if let Err(err) = self.create_default_app_tag(raw_app.id.clone()).await {
+            log::error!("Failed to create default tag for app: {}", err);
         }
+        app_id(raw_app)
     }
 
     async fn create_default_app_tag(
         &self,
         app_id: Option<String>,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
-        let neutral_tag = self
-            .tag_repo
-            .get_tag_by_name("neutral")
-            .await
-            .expect("Failed to get neutral tag");
+    ) -> Result<sqlx::sqlite::SqliteQueryResult> {
+        let neutral_tag = self.tag_repo.get_tag_by_name("neutral").await?;
         log::trace!("neutral_tag: {:?}", neutral_tag);
         if let (Some(app_id), Some(tag_id)) = (app_id, neutral_tag.id.clone()) {
             log::trace!("app_id: {:?}", app_id);
             log::trace!("tag_id: {:?}", tag_id);
             self.tag_repo.create_app_tag(app_id, tag_id, 1.0).await
         } else {
-            Err(sqlx::Error::RowNotFound)
+            Err(Error::Validation(
+                "app or neutral tag has no id".to_string(),
+            ))
         }
     }
 
-    pub async fn get_app_by_external_id(&self, external_app_id: &str) -> Result<App, sqlx::Error> {
+    pub async fn get_app_by_external_id(&self, external_app_id: &str) -> Result<App> {
         self.app_repo.get_app_by_external_id(external_app_id).await
     }
 
-    pub async fn save_app(
-        &self,
-        app: &App,
-    ) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    pub async fn save_app(&self, app: &App) -> Result<sqlx::sqlite::SqliteQueryResult> {
         self.app_repo.save_app(app).await
     }
 
     #[cfg(test)]
-    pub async fn get_app_tag_by_app_id(&self, app_id: &str) -> Result<Tag, sqlx::Error> {
+    pub async fn get_app_tag_by_app_id(&self, app_id: &str) -> Result<Tag> {
         self.tag_repo.get_app_tag_by_app_id(app_id).await
     }
 
     #[cfg(test)]
-    pub async fn get_tags_for_activity_state(
-        &self,
-        activity_state_id: i64,
-    ) -> Result<Vec<Tag>, sqlx::Error> {
+    pub async fn get_tags_for_activity_state(&self, activity_state_id: i64) -> Result<Vec<Tag>> {
         self.tag_repo
             .get_tags_for_activity_state(activity_state_id)
             .await
     }
 }
 
+fn app_id(app: App) -> Result<String> {
+    app.id
+        .ok_or_else(|| Error::Validation(format!("app {} has no id", app.app_external_id)))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         db::{
             activity_state_repo::ActivityStateRepo,
             db_manager,
-            models::{ActivityState, ActivityStateType},
+            models::{ActivityState, ActivityStateType, TagRule},
         },
         services::activities_service::ActivityService,
     };
@@ -179,6 +364,7 @@ mod tests {
             id: None,
             state: ActivityStateType::Inactive,
             app_switches: 0,
+            active_seconds: None,
             start_time: Some(OffsetDateTime::now_utc()),
             end_time: Some(OffsetDateTime::now_utc()),
             created_at: Some(OffsetDateTime::now_utc()),
@@ -195,7 +381,10 @@ mod tests {
             platform: OsPlatform::Mac,
             bundle_id: Some("com.todesktop.230313mzl4w4u92".to_string()),
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
 
         let activities = activity_service
             .get_activities_since_last_activity_state()
@@ -205,8 +394,20 @@ mod tests {
             .get_last_activity_state()
             .await
             .expect("Failed to get activity state");
+        let activity_period = ActivityPeriod {
+            start_time: activity_state.end_time.unwrap(),
+            end_time: OffsetDateTime::now_utc() + time::Duration::minutes(1),
+        };
         app_service
-            .create_tags_from_activities(&activities, activity_state.id.unwrap())
+            .create_tags_from_activities(
+                &activities,
+                &app_service
+                    .get_period_windows(&activities, &activity_period)
+                    .await
+                    .unwrap(),
+                activity_state.id.unwrap(),
+                &activity_period,
+            )
             .await
             .expect("Failed to create tags");
 
@@ -228,6 +429,7 @@ mod tests {
             id: None,
             state: ActivityStateType::Inactive,
             app_switches: 0,
+            active_seconds: None,
             start_time: Some(OffsetDateTime::now_utc()),
             end_time: Some(OffsetDateTime::now_utc()),
             created_at: Some(OffsetDateTime::now_utc()),
@@ -244,7 +446,10 @@ mod tests {
             platform: OsPlatform::Mac,
             bundle_id: Some("com.todesktop.230313mzl4w4u92".to_string()),
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
         let event = WindowEvent {
             app_name: "Google Chrome".to_string(),
             window_title: "Google".to_string(),
@@ -252,7 +457,10 @@ mod tests {
             platform: OsPlatform::Mac,
             bundle_id: None,
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
         let event = WindowEvent {
             app_name: "Google Chrome".to_string(),
             window_title: "X - Twitter".to_string(),
@@ -260,7 +468,10 @@ mod tests {
             platform: OsPlatform::Mac,
             bundle_id: None,
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
         let event = WindowEvent {
             app_name: "Ebb".to_string(),
             window_title: "main".to_string(),
@@ -268,7 +479,10 @@ mod tests {
             platform: OsPlatform::Mac,
             bundle_id: Some("com.ebb.app".to_string()),
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
         let event = WindowEvent {
             app_name: "Google Chrome".to_string(),
             window_title: "Instagram".to_string(),
@@ -276,7 +490,10 @@ mod tests {
             platform: OsPlatform::Mac,
             bundle_id: None,
         };
-        activity_service.handle_window_activity(event).await;
+        activity_service
+            .handle_window_activity(event)
+            .await
+            .unwrap();
 
         let activities = activity_service
             .get_activities_since_last_activity_state()
@@ -286,8 +503,20 @@ mod tests {
             .get_last_activity_state()
             .await
             .expect("Failed to get activity state");
+        let activity_period = ActivityPeriod {
+            start_time: activity_state.end_time.unwrap(),
+            end_time: OffsetDateTime::now_utc() + time::Duration::minutes(1),
+        };
         app_service
-            .create_tags_from_activities(&activities, activity_state.id.unwrap())
+            .create_tags_from_activities(
+                &activities,
+                &app_service
+                    .get_period_windows(&activities, &activity_period)
+                    .await
+                    .unwrap(),
+                activity_state.id.unwrap(),
+                &activity_period,
+            )
             .await
             .expect("Failed to create tags");
 
@@ -298,4 +527,178 @@ mod tests {
 
         assert_eq!(tags.len(), 10);
     }
+
+    #[tokio::test]
+    async fn test_create_tags_from_activities_with_tag_rules() {
+        let pool = db_manager::create_test_db().await;
+        let app_service = AppService::new(pool.clone());
+        let tag_repo = TagRepo::new(pool.clone());
+        let tag_rule_repo = TagRuleRepo::new(pool.clone());
+        let activity_state_repo = ActivityStateRepo::new(pool.clone());
+        let activity_service = ActivityService::new(pool.clone());
+
+        for (url_pattern, tag_name) in [
+            (r"^/[^/]+/[^/]+/pull/\d+", "coding"),
+            ("^/trending", "consuming"),
+        ] {
+            let tag = tag_repo.get_tag_by_name(tag_name).await.unwrap();
+            tag_rule_repo
+                .save_tag_rule(&TagRule::new(
+                    Some("github.com".to_string()),
+                    None,
+                    Some(url_pattern.to_string()),
+                    tag.id.unwrap(),
+                    1.0,
+                    0,
+                ))
+                .await
+                .unwrap();
+        }
+
+        let mut tag_names = Vec::new();
+        for url in [
+            "https://github.com/org/repo/pull/42",
+            "https://github.com/trending",
+        ] {
+            let now = OffsetDateTime::now_utc();
+            activity_state_repo
+                .save_activity_state(&ActivityState {
+                    start_time: Some(now),
+                    end_time: Some(now),
+                    ..ActivityState::new()
+                })
+                .await
+                .unwrap();
+            activity_service
+                .handle_window_activity(WindowEvent {
+                    app_name: "Google Chrome".to_string(),
+                    window_title: "GitHub".to_string(),
+                    url: Some(url.to_string()),
+                    platform: OsPlatform::Mac,
+                    bundle_id: None,
+                })
+                .await
+                .unwrap();
+            let activities = activity_service
+                .get_activities_since_last_activity_state()
+                .await
+                .unwrap();
+            let activity_state_id = activity_state_repo
+                .get_last_activity_state()
+                .await
+                .unwrap()
+                .id
+                .unwrap();
+            // the previous window is left right as the period starts
+            let start_time = activities[0].timestamp.unwrap();
+            let activity_period = ActivityPeriod {
+                start_time,
+                end_time: start_time + time::Duration::minutes(1),
+            };
+            app_service
+                .create_tags_from_activities(
+                    &activities,
+                    &app_service
+                        .get_period_windows(&activities, &activity_period)
+                        .await
+                        .unwrap(),
+                    activity_state_id,
+                    &activity_period,
+                )
+                .await
+                .unwrap();
+            tag_names.push(
+                app_service
+                    .get_tags_for_activity_state(activity_state_id)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|tag| tag.name)
+                    .collect::<Vec<String>>(),
+            );
+        }
+
+        assert_eq!(tag_names, vec![vec!["coding"], vec!["consuming"]]);
+        let now = OffsetDateTime::now_utc();
+        let weighted_durations = tag_repo
+            .get_activity_state_tag_details_between(
+                now - time::Duration::hours(1),
+                now + time::Duration::hours(1),
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|detail| detail.weighted_duration)
+            .collect::<Vec<_>>();
+        assert_eq!(weighted_durations, vec![Some(60.0), Some(60.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_create_activity_state_apps() {
+        let pool = db_manager::create_test_db().await;
+        let app_service = AppService::new(pool.clone());
+        let activity_service = ActivityService::new(pool.clone());
+        let activity_state_repo = ActivityStateRepo::new(pool.clone());
+        let activity_state_app_repo = ActivityStateAppRepo::new(pool.clone());
+
+        let now = OffsetDateTime::now_utc();
+        activity_state_repo
+            .save_activity_state(&ActivityState {
+                start_time: Some(now),
+                end_time: Some(now),
+                ..ActivityState::new()
+            })
+            .await
+            .unwrap();
+        for (app_name, window_title) in
+            [("Cursor", "main.rs"), ("Cursor", "lib.rs"), ("Ebb", "main")]
+        {
+            activity_service
+                .handle_window_activity(WindowEvent {
+                    app_name: app_name.to_string(),
+                    window_title: window_title.to_string(),
+                    url: None,
+                    platform: OsPlatform::Mac,
+                    bundle_id: Some(format!("com.{}.app", app_name.to_lowercase())),
+                })
+                .await
+                .unwrap();
+        }
+        let activities = activity_service
+            .get_activities_since_last_activity_state()
+            .await
+            .unwrap();
+        let activity_state_id = activity_state_repo
+            .get_last_activity_state()
+            .await
+            .unwrap()
+            .id
+            .unwrap();
+        let start_time = activities[0].timestamp.unwrap();
+        let activity_period = ActivityPeriod {
+            start_time,
+            end_time: start_time + time::Duration::minutes(1),
+        };
+        let period_windows = app_service
+            .get_period_windows(&activities, &activity_period)
+            .await
+            .unwrap();
+        app_service
+            .create_activity_state_apps(&period_windows, activity_state_id, &activity_period)
+            .await
+            .unwrap();
+
+        let activity_state_apps = activity_state_app_repo
+            .get_activity_state_apps(activity_state_id)
+            .await
+            .unwrap();
+        assert_eq!(activity_state_apps.len(), 3);
+        // the last window is focused for the rest of the minute
+        assert_eq!(activity_state_apps[0].window_title.as_deref(), Some("main"));
+        let total_seconds = activity_state_apps
+            .iter()
+            .map(|activity_state_app| activity_state_app.seconds)
+            .sum::<f64>();
+        assert!((total_seconds - 60.0).abs() < 1e-6);
+    }
 }
@@ -1,33 +1,33 @@
-use std::time::{Duration, SystemTime};
+use std::{sync::Arc, time::Duration};
 
-use crate::db::models::Activity;
+use time::OffsetDateTime;
+
+use crate::{db::models::Activity, utils::clock::Clock};
 
 pub struct AppSwitchState {
     current_activity: Option<Activity>,
     pub app_switches: i64,
-    last_switch_time: SystemTime,
+    last_switch_time: OffsetDateTime,
     min_time_between_switches: Duration,
+    clock: Arc<dyn Clock>,
 }
 
 impl AppSwitchState {
-    pub fn new(min_time_between_switches: Duration) -> Self {
+    pub fn new(min_time_between_switches: Duration, clock: Arc<dyn Clock>) -> Self {
         AppSwitchState {
             current_activity: None,
             app_switches: 0,
-            last_switch_time: SystemTime::now(),
+            last_switch_time: clock.now(),
             min_time_between_switches,
+            clock,
         }
     }
 
     pub fn new_window_activity(&mut self, activity: Activity) {
         if let Some(current_activity) = self.current_activity.clone() {
             if current_activity.app_id != activity.app_id {
-                let now = SystemTime::now();
-                if now
-                    .duration_since(self.last_switch_time)
-                    .unwrap_or(Duration::from_secs(0))
-                    >= self.min_time_between_switches
-                {
+                let now = self.clock.now();
+                if now - self.last_switch_time >= self.min_time_between_switches {
                     self.app_switches += 1;
                     self.last_switch_time = now;
                 }
@@ -35,7 +35,7 @@ impl AppSwitchState {
             }
         } else {
             self.current_activity = Some(activity);
-            self.last_switch_time = SystemTime::now();
+            self.last_switch_time = self.clock.now();
         }
     }
 
@@ -49,13 +49,17 @@ mod tests {
     use time::OffsetDateTime;
     use uuid::Uuid;
 
-    use crate::db::{models::ActivityType, types::Platform};
+    use crate::{
+        db::{models::ActivityType, types::Platform},
+        utils::clock::{ManualClock, SystemClock},
+    };
 
     use super::*;
 
     #[test]
     fn test_app_switch() {
-        let mut app_switch_state = AppSwitchState::new(Duration::from_millis(1));
+        let mut app_switch_state =
+            AppSwitchState::new(Duration::from_millis(1), Arc::new(SystemClock));
         let app_1_id = Uuid::new_v4().to_string();
         let app_2_id = Uuid::new_v4().to_string();
         app_switch_state.new_window_activity(Activity::new(
@@ -102,7 +106,8 @@ mod tests {
 
     #[test]
     fn test_spam_app_switch() {
-        let mut app_switch_state = AppSwitchState::new(Duration::from_millis(1));
+        let mut app_switch_state =
+            AppSwitchState::new(Duration::from_millis(1), Arc::new(SystemClock));
         for i in 0..5 {
             app_switch_state.new_window_activity(Activity::new(
                 ActivityType::Window,
@@ -115,9 +120,29 @@ mod tests {
         assert_eq!(app_switch_state.app_switches, 0);
     }
 
+    #[test]
+    fn test_app_switch_with_manual_clock() {
+        let clock = Arc::new(ManualClock::new(OffsetDateTime::now_utc()));
+        let mut app_switch_state = AppSwitchState::new(Duration::from_secs(2), clock.clone());
+        let app_ids = [Uuid::new_v4().to_string(), Uuid::new_v4().to_string()];
+        for i in 0..6 {
+            app_switch_state.new_window_activity(Activity::new(
+                ActivityType::Window,
+                Some(format!("window{}", i)),
+                clock.now(),
+                Platform::Mac,
+                Some(app_ids[i % 2].clone()),
+            ));
+            // the first switch comes 1 second after the first window and is debounced
+            clock.advance(Duration::from_secs(if i < 2 { 1 } else { 3 }));
+        }
+        assert_eq!(app_switch_state.app_switches, 4);
+    }
+
     #[test]
     fn test_app_switch_reset() {
-        let mut app_switch_state = AppSwitchState::new(Duration::from_millis(1));
+        let mut app_switch_state =
+            AppSwitchState::new(Duration::from_millis(1), Arc::new(SystemClock));
         app_switch_state.new_window_activity(Activity::new(
             ActivityType::Window,
             Some("window1".to_string()),
@@ -1,4 +1,12 @@
 pub(crate) mod activities_service;
+pub(crate) mod activity_buffer_service;
+pub mod activity_flow_period_service;
 pub(crate) mod activity_state_service;
 pub(crate) mod app_service;
 pub(crate) mod app_switch_service;
+pub(crate) mod pause_service;
+pub mod report_service;
+pub mod retention_service;
+pub mod tag_rule_service;
+pub mod tag_service;
+pub mod title_policy_service;
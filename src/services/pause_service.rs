@@ -0,0 +1,191 @@
+use os_monitor::WindowEvent;
+use time::OffsetDateTime;
+
+use crate::db::models::App;
+
+use super::activity_state_service::ActivityPeriod;
+
+/**
+ * Tracks when recording is paused: manually (until resumed or until a given time) or while a private
+ * app or domain is focused. The paused spans are kept until the activity states they overlap are
+ * created, so those states can be recorded as paused.
+ */
+pub(crate) struct PauseState {
+    private_apps: Vec<String>,
+    // Some(None) is paused until resumed, Some(Some(until)) until that time
+    manual_pause: Option<Option<OffsetDateTime>>,
+    private_app_focused: bool,
+    paused_since: Option<OffsetDateTime>,
+    paused_spans: Vec<(OffsetDateTime, OffsetDateTime)>,
+}
+
+impl PauseState {
+    /// `private_apps` are app names, bundle ids or domains (see `App::window_is_named`).
+    pub fn new(private_apps: Vec<String>) -> Self {
+        PauseState {
+            private_apps,
+            manual_pause: None,
+            private_app_focused: false,
+            paused_since: None,
+            paused_spans: Vec::new(),
+        }
+    }
+
+    pub fn pause(&mut self, now: OffsetDateTime, until: Option<OffsetDateTime>) {
+        self.expire(now);
+        self.manual_pause = Some(until);
+        self.update(now);
+    }
+
+    pub fn resume(&mut self, now: OffsetDateTime) {
+        self.manual_pause = None;
+        self.update(now);
+    }
+
+    pub fn set_private_app_focused(&mut self, now: OffsetDateTime, private_app_focused: bool) {
+        self.expire(now);
+        self.private_app_focused = private_app_focused;
+        self.update(now);
+    }
+
+    pub fn is_paused(&mut self, now: OffsetDateTime) -> bool {
+        self.expire(now);
+        self.paused_since.is_some()
+    }
+
+    pub fn is_private(&self, event: &WindowEvent) -> bool {
+        self.private_apps
+            .iter()
+            .any(|private_app| App::window_is_named(event, private_app))
+    }
+
+    /**
+     * Seconds of the period during which recording was paused. Spans that ended before the period
+     * are dropped, since activity states are created in order.
+     */
+    pub fn paused_seconds(&mut self, activity_period: &ActivityPeriod, now: OffsetDateTime) -> f64 {
+        self.expire(now);
+        self.paused_spans
+            .retain(|(_, end_time)| *end_time > activity_period.start_time);
+        self.paused_spans
+            .iter()
+            .copied()
+            .chain(
+                self.paused_since
+                    .map(|since| (since, activity_period.end_time)),
+            )
+            .map(|(start_time, end_time)| {
+                let start_time = start_time.max(activity_period.start_time);
+                let end_time = end_time.min(activity_period.end_time);
+                (end_time - start_time).as_seconds_f64().max(0.0)
+            })
+            .sum()
+    }
+
+    // ends a timed pause at its end time rather than when it is noticed
+    fn expire(&mut self, now: OffsetDateTime) {
+        if let Some(Some(until)) = self.manual_pause {
+            if until <= now {
+                self.manual_pause = None;
+                self.update(until);
+            }
+        }
+    }
+
+    fn update(&mut self, at: OffsetDateTime) {
+        let is_paused = self.manual_pause.is_some() || self.private_app_focused;
+        match (is_paused, self.paused_since) {
+            (true, None) => self.paused_since = Some(at),
+            (false, Some(paused_since)) => {
+                self.paused_spans.push((paused_since, at));
+                self.paused_since = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use os_monitor::Platform;
+
+    use super::*;
+
+    fn window(app_name: &str, bundle_id: Option<&str>, url: Option<&str>) -> WindowEvent {
+        WindowEvent {
+            app_name: app_name.to_string(),
+            window_title: "title".to_string(),
+            bundle_id: bundle_id.map(str::to_string),
+            url: url.map(str::to_string),
+            platform: Platform::Mac,
+        }
+    }
+
+    #[test]
+    fn test_is_private() {
+        let pause_state = PauseState::new(vec![
+            "1Password".to_string(),
+            "com.apple.keychainaccess".to_string(),
+            "mybank.com".to_string(),
+        ]);
+        assert!(pause_state.is_private(&window("1Password", None, None)));
+        assert!(pause_state.is_private(&window(
+            "Keychain Access",
+            Some("com.apple.keychainaccess"),
+            None
+        )));
+        assert!(pause_state.is_private(&window(
+            "Google Chrome",
+            Some("com.google.Chrome"),
+            Some("https://login.mybank.com/accounts")
+        )));
+        assert!(!pause_state.is_private(&window(
+            "Google Chrome",
+            Some("com.google.Chrome"),
+            Some("https://notmybank.com")
+        )));
+        assert!(!pause_state.is_private(&window("Cursor", Some("com.todesktop.cursor"), None)));
+
+        // a private browser is private on every tab
+        let pause_state = PauseState::new(vec!["com.google.Chrome".to_string()]);
+        assert!(pause_state.is_private(&window(
+            "Google Chrome",
+            Some("com.google.Chrome"),
+            Some("https://github.com/CodeClimbersIO")
+        )));
+    }
+
+    #[test]
+    fn test_paused_seconds() {
+        let start = OffsetDateTime::now_utc();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let period = |from: u64, to: u64| ActivityPeriod {
+            start_time: at(from),
+            end_time: at(to),
+        };
+        let mut pause_state = PauseState::new(Vec::new());
+        assert!(!pause_state.is_paused(at(0)));
+
+        // paused for 20 seconds from 10s, expiring on its own
+        pause_state.pause(at(10), Some(at(30)));
+        assert!(pause_state.is_paused(at(20)));
+        assert!(!pause_state.is_paused(at(40)));
+        assert_eq!(pause_state.paused_seconds(&period(0, 30), at(40)), 20.0);
+
+        // a private app focused during a manual pause keeps it paused after resuming
+        pause_state.pause(at(45), None);
+        pause_state.set_private_app_focused(at(50), true);
+        pause_state.resume(at(55));
+        assert!(pause_state.is_paused(at(55)));
+        pause_state.set_private_app_focused(at(70), false);
+        assert!(!pause_state.is_paused(at(70)));
+        assert_eq!(pause_state.paused_seconds(&period(30, 60), at(70)), 15.0);
+        assert_eq!(pause_state.paused_seconds(&period(60, 90), at(90)), 10.0);
+
+        // an ongoing pause counts up to the end of the period
+        pause_state.pause(at(100), None);
+        assert_eq!(pause_state.paused_seconds(&period(90, 120), at(120)), 20.0);
+    }
+}
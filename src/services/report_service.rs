@@ -0,0 +1,695 @@
+use std::collections::{HashMap, HashSet};
+
+use time::{Date, Duration, OffsetDateTime, UtcOffset};
+
+use crate::{
+    db::{
+        activity_gap_repo::ActivityGapRepo,
+        activity_state_app_repo::ActivityStateAppRepo,
+        activity_state_repo::ActivityStateRepo,
+        models::{
+            ActivityGap, ActivityGapReason, ActivityState, ActivityStateAppDetail,
+            ActivityStateTagDetail, ActivityStateType, Tag,
+        },
+        tag_repo::TagRepo,
+    },
+    error::{Error, Result},
+    services::activity_state_service::ActivityPeriod,
+};
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct AppUsage {
+    pub app_id: String,
+    pub app_name: Option<String>,
+    pub app_external_id: Option<String>,
+    pub seconds: f64,
+}
+
+/// `seconds` includes the time of the tag's descendants, e.g. `coding` includes `rust`.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct TagUsage {
+    pub tag_id: String,
+    pub name: String,
+    pub tag_type: String,
+    pub parent_tag_id: Option<String>,
+    pub seconds: f64,
+}
+
+/// Seconds of the range not covered by activity states, e.g. while the laptop was closed.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct GapUsage {
+    pub reason: ActivityGapReason,
+    pub seconds: i64,
+}
+
+/**
+ * Usage between `start_time` and `end_time`, built from the activity states starting in that range.
+ * `apps` and `tags` are sorted by time spent, most used first. `gaps` are clipped to the range, so
+ * active, passive, paused, inactive and gap seconds add up to the tracked wall-clock time.
+ */
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct UsageSummary {
+    #[serde(with = "time::serde::rfc3339")]
+    pub start_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub end_time: OffsetDateTime,
+    pub active_seconds: i64,
+    pub inactive_seconds: i64,
+    /// Seconds without input while a passive app (e.g. a video call) was focused.
+    pub passive_seconds: i64,
+    /// Seconds during which recording was paused, manually or while a private app was focused.
+    pub paused_seconds: i64,
+    pub app_switches: i64,
+    pub apps: Vec<AppUsage>,
+    pub tags: Vec<TagUsage>,
+    pub gaps: Vec<GapUsage>,
+}
+
+/**
+ * Read API for usage reports. Apps count the seconds they were focused in each activity state; states
+ * recorded without focus times are split evenly between the apps tagged on them.
+ * Tags count their weighted duration (focused time times the app_tag or tag rule weight); tags recorded
+ * without one count the app's time times the weight, or the full state time when they don't come from
+ * an app (e.g. idle). Time rolls up to parent tags, counted once even when several children of the
+ * same parent were tagged.
+ */
+#[derive(Clone)]
+pub struct ReportService {
+    activity_state_repo: ActivityStateRepo,
+    activity_state_app_repo: ActivityStateAppRepo,
+    activity_gap_repo: ActivityGapRepo,
+    tag_repo: TagRepo,
+}
+
+impl ReportService {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        ReportService {
+            activity_state_repo: ActivityStateRepo::new(pool.clone()),
+            activity_state_app_repo: ActivityStateAppRepo::new(pool.clone()),
+            activity_gap_repo: ActivityGapRepo::new(pool.clone()),
+            tag_repo: TagRepo::new(pool),
+        }
+    }
+
+    pub async fn get_usage_summary(
+        &self,
+        start_time: OffsetDateTime,
+        end_time: OffsetDateTime,
+    ) -> Result<UsageSummary> {
+        // timestamps are stored and compared as UTC strings
+        let (start_time, end_time) = (
+            start_time.to_offset(UtcOffset::UTC),
+            end_time.to_offset(UtcOffset::UTC),
+        );
+        let activity_states = self
+            .activity_state_repo
+            .get_activity_states_for_activity_period(&ActivityPeriod {
+                start_time,
+                end_time,
+            })
+            .await?;
+        let tag_details = self
+            .tag_repo
+            .get_activity_state_tag_details_between(start_time, end_time)
+            .await?;
+        let app_details = self
+            .activity_state_app_repo
+            .get_activity_state_app_details_between(start_time, end_time)
+            .await?;
+        let all_tags = self.tag_repo.get_all_tags().await?;
+        let activity_gaps = self
+            .activity_gap_repo
+            .get_activity_gaps_between(start_time, end_time)
+            .await?;
+        let mut summary = summarize_usage(
+            start_time,
+            end_time,
+            &activity_states,
+            &app_details,
+            &tag_details,
+            &all_tags,
+        );
+        summary.gaps = summarize_gaps(start_time, end_time, &activity_gaps);
+        Ok(summary)
+    }
+
+    /**
+     * One summary per calendar day from `start_date` to `end_date` (inclusive). `start_of_day` returns
+     * when a date starts in the caller's time zone, so days around a DST change last 23 or 25 hours;
+     * each day ends when the next one starts.
+     */
+    pub async fn get_daily_summaries(
+        &self,
+        start_date: Date,
+        end_date: Date,
+        start_of_day: impl Fn(Date) -> OffsetDateTime,
+    ) -> Result<Vec<UsageSummary>> {
+        let mut summaries = Vec::new();
+        let mut date = start_date;
+        let mut start_time = start_of_day(date);
+        while date <= end_date {
+            let next_date = date.next_day().ok_or_else(|| out_of_range(date))?;
+            let end_time = start_of_day(next_date);
+            summaries.push(self.get_usage_summary(start_time, end_time).await?);
+            date = next_date;
+            start_time = end_time;
+        }
+        Ok(summaries)
+    }
+
+    /**
+     * One summary per week (Monday to Sunday) covering `start_date` to `end_date`, where weeks start
+     * when `start_of_day` says their Monday starts (see `get_daily_summaries`). The first and last
+     * weeks are not truncated.
+     */
+    pub async fn get_weekly_summaries(
+        &self,
+        start_date: Date,
+        end_date: Date,
+        start_of_day: impl Fn(Date) -> OffsetDateTime,
+    ) -> Result<Vec<UsageSummary>> {
+        let mut summaries = Vec::new();
+        let mut week_start = start_of_week(start_date).ok_or_else(|| out_of_range(start_date))?;
+        let mut start_time = start_of_day(week_start);
+        while week_start <= end_date {
+            let next_week_start = week_start
+                .checked_add(Duration::weeks(1))
+                .ok_or_else(|| out_of_range(week_start))?;
+            let end_time = start_of_day(next_week_start);
+            summaries.push(self.get_usage_summary(start_time, end_time).await?);
+            week_start = next_week_start;
+            start_time = end_time;
+        }
+        Ok(summaries)
+    }
+}
+
+fn out_of_range(date: Date) -> Error {
+    Error::Validation(format!("{} is out of the supported date range", date))
+}
+
+fn start_of_week(date: Date) -> Option<Date> {
+    date.checked_sub(Duration::days(
+        date.weekday().number_days_from_monday() as i64
+    ))
+}
+
+/// Ids of the tag and all its ancestors, stopping at a cycle.
+fn tag_with_ancestors<'a>(tag_id: &'a str, tags_by_id: &HashMap<&str, &'a Tag>) -> Vec<&'a str> {
+    let mut lineage = vec![tag_id];
+    let mut current = tag_id;
+    while let Some(parent_tag_id) = tags_by_id
+        .get(current)
+        .and_then(|tag| tag.parent_tag_id.as_deref())
+    {
+        if lineage.contains(&parent_tag_id) {
+            break;
+        }
+        lineage.push(parent_tag_id);
+        current = parent_tag_id;
+    }
+    lineage
+}
+
+/// Seconds per gap reason within the range, most first.
+fn summarize_gaps(
+    start_time: OffsetDateTime,
+    end_time: OffsetDateTime,
+    activity_gaps: &[ActivityGap],
+) -> Vec<GapUsage> {
+    let mut seconds_by_reason: HashMap<ActivityGapReason, i64> = HashMap::new();
+    for activity_gap in activity_gaps {
+        let seconds = (activity_gap.end_time.min(end_time)
+            - activity_gap.start_time.max(start_time))
+        .whole_seconds();
+        if seconds > 0 {
+            *seconds_by_reason.entry(activity_gap.reason).or_default() += seconds;
+        }
+    }
+    let mut gaps = seconds_by_reason
+        .into_iter()
+        .map(|(reason, seconds)| GapUsage { reason, seconds })
+        .collect::<Vec<_>>();
+    gaps.sort_by_key(|gap| std::cmp::Reverse(gap.seconds));
+    gaps
+}
+
+fn summarize_usage(
+    start_time: OffsetDateTime,
+    end_time: OffsetDateTime,
+    activity_states: &[ActivityState],
+    app_details: &[ActivityStateAppDetail],
+    tag_details: &[ActivityStateTagDetail],
+    all_tags: &[Tag],
+) -> UsageSummary {
+    let tags_by_id = all_tags
+        .iter()
+        .filter_map(|tag| tag.id.as_deref().map(|id| (id, tag)))
+        .collect::<HashMap<&str, &Tag>>();
+    let mut focus_by_state: HashMap<i64, Vec<&ActivityStateAppDetail>> = HashMap::new();
+    for app_detail in app_details {
+        focus_by_state
+            .entry(app_detail.activity_state_id)
+            .or_default()
+            .push(app_detail);
+    }
+    let mut details_by_state: HashMap<i64, Vec<&ActivityStateTagDetail>> = HashMap::new();
+    for tag_detail in tag_details {
+        details_by_state
+            .entry(tag_detail.activity_state_id)
+            .or_default()
+            .push(tag_detail);
+    }
+
+    let mut summary = UsageSummary {
+        start_time,
+        end_time,
+        active_seconds: 0,
+        inactive_seconds: 0,
+        passive_seconds: 0,
+        paused_seconds: 0,
+        app_switches: 0,
+        apps: Vec::new(),
+        tags: Vec::new(),
+        gaps: Vec::new(),
+    };
+    let mut apps: HashMap<String, AppUsage> = HashMap::new();
+    let mut tags: HashMap<String, TagUsage> = HashMap::new();
+    let mut tag_names: HashMap<String, (String, String)> = HashMap::new();
+
+    for activity_state in activity_states {
+        let seconds = activity_state.duration_seconds();
+        let active_seconds = activity_state.active_duration_seconds();
+        summary.app_switches += activity_state.app_switches;
+        match activity_state.state {
+            ActivityStateType::Passive => summary.passive_seconds += seconds,
+            ActivityStateType::Paused => summary.paused_seconds += seconds,
+            _ => {
+                summary.active_seconds += active_seconds;
+                summary.inactive_seconds += seconds - active_seconds;
+            }
+        }
+
+        let state_focus = activity_state.id.and_then(|id| focus_by_state.get(&id));
+        for app_detail in state_focus.into_iter().flatten() {
+            apps.entry(app_detail.app_id.clone())
+                .or_insert_with(|| AppUsage {
+                    app_id: app_detail.app_id.clone(),
+                    app_name: app_detail.app_name.clone(),
+                    app_external_id: Some(app_detail.app_external_id.clone()),
+                    seconds: 0.0,
+                })
+                .seconds += app_detail.seconds;
+        }
+
+        let Some(state_details) = activity_state.id.and_then(|id| details_by_state.get(&id)) else {
+            continue;
+        };
+        let mut state_apps = state_details
+            .iter()
+            .filter_map(|detail| detail.app_id.as_ref())
+            .collect::<Vec<_>>();
+        state_apps.sort();
+        state_apps.dedup();
+        let even_app_seconds = seconds as f64 / state_apps.len().max(1) as f64;
+        let app_seconds = |app_id: &String| match state_focus {
+            Some(state_focus) => state_focus
+                .iter()
+                .find(|app_detail| &app_detail.app_id == app_id)
+                .map_or(0.0, |app_detail| app_detail.seconds),
+            None => even_app_seconds,
+        };
+
+        // seconds per tag for each app, or for the whole state
+        let mut direct_tag_seconds: HashMap<Option<&String>, HashMap<&str, f64>> = HashMap::new();
+        for detail in state_details {
+            let tag_seconds = match &detail.app_id {
+                Some(app_id) => {
+                    apps.entry(app_id.clone()).or_insert_with(|| AppUsage {
+                        app_id: app_id.clone(),
+                        app_name: detail.app_name.clone(),
+                        app_external_id: detail.app_external_id.clone(),
+                        seconds: 0.0,
+                    });
+                    // tags created before weighted durations were recorded split the state evenly
+                    detail
+                        .weighted_duration
+                        .unwrap_or_else(|| app_seconds(app_id) * detail.weight.unwrap_or(1.0))
+                }
+                None => detail.weighted_duration.unwrap_or(seconds as f64),
+            };
+            *direct_tag_seconds
+                .entry(detail.app_id.as_ref())
+                .or_default()
+                .entry(&detail.tag_id)
+                .or_default() += tag_seconds;
+            tag_names
+                .entry(detail.tag_id.clone())
+                .or_insert_with(|| (detail.tag_name.clone(), detail.tag_type.clone()));
+        }
+        // roll each unit's seconds up to the ancestors of its tags
+        let mut state_tag_seconds: HashMap<Option<&String>, HashMap<&str, f64>> = HashMap::new();
+        for (unit, unit_direct_tag_seconds) in &direct_tag_seconds {
+            let unit_tag_seconds = state_tag_seconds.entry(*unit).or_default();
+            for (tag_id, tag_seconds) in unit_direct_tag_seconds {
+                for tag_id in tag_with_ancestors(tag_id, &tags_by_id) {
+                    let rolled_up_seconds = unit_tag_seconds.entry(tag_id).or_default();
+                    *rolled_up_seconds = rolled_up_seconds.max(*tag_seconds);
+                }
+            }
+        }
+        let state_tag_ids = state_tag_seconds
+            .values()
+            .flat_map(|unit_tag_seconds| unit_tag_seconds.keys().copied())
+            .collect::<HashSet<&str>>();
+        for tag_id in state_tag_ids {
+            let tag_seconds = state_tag_seconds
+                .values()
+                .filter_map(|unit_tag_seconds| unit_tag_seconds.get(tag_id))
+                .sum::<f64>();
+            let tag = tags_by_id.get(tag_id);
+            tags.entry(tag_id.to_string())
+                .or_insert_with(|| {
+                    let (name, tag_type) = tag
+                        .map(|tag| (tag.name.clone(), tag.tag_type.clone().unwrap_or_default()))
+                        .or_else(|| tag_names.get(tag_id).cloned())
+                        .unwrap_or_default();
+                    TagUsage {
+                        tag_id: tag_id.to_string(),
+                        name,
+                        tag_type,
+                        parent_tag_id: tag.and_then(|tag| tag.parent_tag_id.clone()),
+                        seconds: 0.0,
+                    }
+                })
+                .seconds += tag_seconds;
+        }
+        if state_focus.is_none() {
+            for app_id in state_apps {
+                if let Some(app_usage) = apps.get_mut(app_id) {
+                    app_usage.seconds += even_app_seconds;
+                }
+            }
+        }
+    }
+
+    summary.apps = apps.into_values().collect();
+    summary.apps.sort_by(|a, b| b.seconds.total_cmp(&a.seconds));
+    summary.tags = tags.into_values().collect();
+    summary.tags.sort_by(|a, b| b.seconds.total_cmp(&a.seconds));
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, offset};
+
+    use super::*;
+    use crate::db::{
+        activity_state_repo::ActivityStateRepo,
+        app_repo::AppRepo,
+        db_manager,
+        models::{ActivityStateApp, App},
+    };
+
+    #[test]
+    fn test_start_of_week() {
+        assert_eq!(
+            start_of_week(date!(2025 - 01 - 15)),
+            Some(date!(2025 - 01 - 13))
+        );
+        assert_eq!(
+            start_of_week(date!(2025 - 01 - 13)),
+            Some(date!(2025 - 01 - 13))
+        );
+        assert_eq!(
+            start_of_week(date!(2025 - 01 - 12)),
+            Some(date!(2025 - 01 - 06))
+        );
+    }
+
+    #[test]
+    fn test_summarize_gaps() {
+        let start_time = OffsetDateTime::UNIX_EPOCH;
+        let at = |minutes: i64| start_time + Duration::minutes(minutes);
+        let gaps = summarize_gaps(
+            start_time,
+            at(24 * 60),
+            &[
+                ActivityGap::new(at(-60), at(30), ActivityGapReason::ServiceNotRunning),
+                ActivityGap::new(at(120), at(600), ActivityGapReason::Suspend),
+                ActivityGap::new(at(700), at(760), ActivityGapReason::Suspend),
+                ActivityGap::new(at(-120), at(-60), ActivityGapReason::ClockJump),
+            ],
+        );
+        assert_eq!(
+            gaps,
+            vec![
+                GapUsage {
+                    reason: ActivityGapReason::Suspend,
+                    seconds: 540 * 60,
+                },
+                GapUsage {
+                    reason: ActivityGapReason::ServiceNotRunning,
+                    seconds: 30 * 60,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summarize_usage_rolls_up_to_parent_tags() {
+        let tag = |id: &str, parent_tag_id: Option<&str>| Tag {
+            id: Some(id.to_string()),
+            created_at: None,
+            updated_at: None,
+            name: id.to_string(),
+            tag_type: Some("category".to_string()),
+            parent_tag_id: parent_tag_id.map(str::to_string),
+            is_default: false,
+            is_blocked: false,
+            is_passive: false,
+        };
+        let all_tags = vec![
+            tag("coding", None),
+            tag("rust", Some("coding")),
+            tag("frontend", Some("coding")),
+        ];
+        let detail = |tag_id: &str, app_id: &str| ActivityStateTagDetail {
+            activity_state_id: 1,
+            tag_id: tag_id.to_string(),
+            tag_name: tag_id.to_string(),
+            tag_type: "category".to_string(),
+            app_id: Some(app_id.to_string()),
+            app_name: None,
+            app_external_id: None,
+            weight: Some(1.0),
+            weighted_duration: None,
+        };
+        let start_time = OffsetDateTime::UNIX_EPOCH;
+        let activity_state = ActivityState {
+            id: Some(1),
+            state: ActivityStateType::Active,
+            app_switches: 1,
+            active_seconds: None,
+            start_time: Some(start_time),
+            end_time: Some(start_time + Duration::minutes(1)),
+            created_at: None,
+        };
+
+        // the editor is tagged both rust and frontend, its 30 seconds count once toward coding
+        let summary = summarize_usage(
+            start_time,
+            start_time + Duration::hours(1),
+            &[activity_state],
+            &[],
+            &[
+                detail("rust", "editor"),
+                detail("frontend", "editor"),
+                detail("frontend", "browser"),
+            ],
+            &all_tags,
+        );
+        let seconds = |tag_id: &str| {
+            summary
+                .tags
+                .iter()
+                .find(|tag| tag.tag_id == tag_id)
+                .map(|tag| tag.seconds)
+        };
+        assert_eq!(seconds("coding"), Some(60.0));
+        assert_eq!(seconds("frontend"), Some(60.0));
+        assert_eq!(seconds("rust"), Some(30.0));
+        let rust = summary
+            .tags
+            .iter()
+            .find(|tag| tag.tag_id == "rust")
+            .unwrap();
+        assert_eq!(rust.parent_tag_id.as_deref(), Some("coding"));
+    }
+
+    #[tokio::test]
+    async fn test_get_daily_summaries() {
+        let pool = db_manager::create_test_db().await;
+        let activity_state_repo = ActivityStateRepo::new(pool.clone());
+        let app_repo = AppRepo::new(pool.clone());
+        let tag_repo = TagRepo::new(pool.clone());
+        let activity_state_app_repo = ActivityStateAppRepo::new(pool.clone());
+        let report_service = ReportService::new(pool.clone());
+
+        let mut apps = App::__create_test_apps(&["editor".to_string(), "browser".to_string()]);
+        for app in apps.iter_mut() {
+            app.app_external_id = app.name.clone().unwrap();
+        }
+        let creating_tag = tag_repo.get_tag_by_name("creating").await.unwrap();
+        let consuming_tag = tag_repo.get_tag_by_name("consuming").await.unwrap();
+        for app in apps.iter() {
+            app_repo.save_app(app).await.unwrap();
+        }
+        let editor_id = apps[0].id.clone().unwrap();
+        let browser_id = apps[1].id.clone().unwrap();
+        tag_repo
+            .create_app_tag(editor_id.clone(), creating_tag.id.clone().unwrap(), 1.0)
+            .await
+            .unwrap();
+        tag_repo
+            .create_app_tag(browser_id.clone(), consuming_tag.id.clone().unwrap(), 0.5)
+            .await
+            .unwrap();
+        let app_tags = app_repo
+            .get_app_tag_by_app_ids(&vec![editor_id.clone(), browser_id.clone()])
+            .await
+            .unwrap();
+
+        // 23:00 to 23:02 at UTC-5 on January 14th, one minute in the editor and one with both apps
+        let start_time = date!(2025 - 01 - 14)
+            .with_hms(23, 0, 0)
+            .unwrap()
+            .assume_offset(offset!(-5))
+            .to_offset(UtcOffset::UTC);
+        for minute in 0..2 {
+            activity_state_repo
+                .create_active_activity_state(
+                    minute,
+                    None,
+                    &ActivityPeriod {
+                        start_time: start_time + Duration::minutes(minute),
+                        end_time: start_time + Duration::minutes(minute + 1),
+                    },
+                )
+                .await
+                .unwrap();
+            let activity_state = activity_state_repo.get_last_activity_state().await.unwrap();
+            // the editor is focused for the first minute and 45 seconds of the second one
+            let state_app_tags = app_tags
+                .iter()
+                .filter_map(|app_tag| {
+                    let focus_seconds = match (minute, app_tag.app_id == editor_id) {
+                        (0, true) => 60.0,
+                        (0, false) => return None,
+                        (_, true) => 45.0,
+                        (_, false) => 15.0,
+                    };
+                    Some((app_tag.clone(), focus_seconds * app_tag.weight as f64))
+                })
+                .collect::<Vec<_>>();
+            tag_repo
+                .create_activity_state_tags_with_app_tags(
+                    activity_state.id.unwrap(),
+                    &state_app_tags,
+                )
+                .await
+                .unwrap();
+            // only the second minute has its focus times recorded
+            if minute == 1 {
+                activity_state_app_repo
+                    .save_activity_state_apps(&[
+                        ActivityStateApp {
+                            activity_state_id: activity_state.id.unwrap(),
+                            app_id: editor_id.clone(),
+                            window_title: Some("main.rs".to_string()),
+                            seconds: 45.0,
+                        },
+                        ActivityStateApp {
+                            activity_state_id: activity_state.id.unwrap(),
+                            app_id: browser_id.clone(),
+                            window_title: None,
+                            seconds: 15.0,
+                        },
+                    ])
+                    .await
+                    .unwrap();
+            }
+        }
+        activity_state_repo
+            .create_idle_activity_state(&ActivityPeriod {
+                start_time: start_time + Duration::minutes(2),
+                end_time: start_time + Duration::minutes(3),
+            })
+            .await
+            .unwrap();
+
+        let summaries = report_service
+            .get_daily_summaries(date!(2025 - 01 - 14), date!(2025 - 01 - 15), |date| {
+                date.midnight().assume_offset(offset!(-5))
+            })
+            .await
+            .unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[1].active_seconds, 0);
+
+        let summary = &summaries[0];
+        assert_eq!(summary.active_seconds, 120);
+        assert_eq!(summary.inactive_seconds, 60);
+        assert_eq!(summary.app_switches, 1);
+        assert_eq!(summary.apps.len(), 2);
+        assert_eq!(summary.apps[0].app_id, editor_id);
+        assert_eq!(summary.apps[0].seconds, 105.0);
+        assert_eq!(summary.apps[1].seconds, 15.0);
+        assert_eq!(summary.tags[0].name, "creating");
+        assert_eq!(summary.tags[0].seconds, 105.0);
+        assert_eq!(summary.tags[1].name, "consuming");
+        assert_eq!(summary.tags[1].seconds, 7.5);
+
+        // in UTC the same states fall on January 15th
+        let utc_summaries = report_service
+            .get_daily_summaries(date!(2025 - 01 - 15), date!(2025 - 01 - 15), |date| {
+                date.midnight().assume_utc()
+            })
+            .await
+            .unwrap();
+        assert_eq!(utc_summaries[0].active_seconds, 120);
+
+        let weekly_summaries = report_service
+            .get_weekly_summaries(date!(2025 - 01 - 14), date!(2025 - 01 - 14), |date| {
+                date.midnight().assume_offset(offset!(-5))
+            })
+            .await
+            .unwrap();
+        assert_eq!(weekly_summaries.len(), 1);
+        assert_eq!(weekly_summaries[0].active_seconds, 120);
+
+        // days follow the time zone across a DST change, e.g. New York on March 9th 2025 at 2am
+        let new_york = |date: Date| {
+            let offset = if date <= date!(2025 - 03 - 09) {
+                offset!(-5)
+            } else {
+                offset!(-4)
+            };
+            date.midnight().assume_offset(offset)
+        };
+        let dst_summaries = report_service
+            .get_daily_summaries(date!(2025 - 03 - 08), date!(2025 - 03 - 09), new_york)
+            .await
+            .unwrap();
+        assert_eq!(dst_summaries[0].end_time, dst_summaries[1].start_time);
+        assert_eq!(
+            dst_summaries[1].end_time - dst_summaries[1].start_time,
+            Duration::hours(23)
+        );
+        assert!(report_service
+            .get_daily_summaries(Date::MAX, Date::MAX, new_york)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use std::{sync::Arc, time::Duration};
+
+use time::{OffsetDateTime, UtcOffset};
+use tokio::sync::watch;
+
+use crate::{
+    db::{
+        activity_repo::ActivityRepo, activity_rollup_repo::ActivityRollupRepo,
+        activity_state_app_repo::ActivityStateAppRepo, activity_state_repo::ActivityStateRepo,
+        blocked_activity_repo::BlockedActivityRepo,
+    },
+    error::Result,
+    utils::{
+        clock::{Clock, SystemClock},
+        shutdown::stopped,
+    },
+};
+
+/**
+ * How long data is kept. Raw activities are rolled up into per-minute counts (`activity_rollup`)
+ * after 30 days by default; activity states, blocked activities and window titles are kept forever
+ * unless a maximum age is set.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionPolicy {
+    activity_rollup_after: Duration,
+    activity_state_max_age: Option<Duration>,
+    blocked_activity_max_age: Option<Duration>,
+    window_title_max_age: Option<Duration>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            activity_rollup_after: Duration::from_secs(30 * 24 * 60 * 60),
+            activity_state_max_age: None,
+            blocked_activity_max_age: None,
+            window_title_max_age: None,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Activities older than this are rolled up and deleted. Activities not yet part of an activity
+    /// state are never rolled up.
+    pub fn with_activity_rollup_after(mut self, activity_rollup_after: Duration) -> Self {
+        self.activity_rollup_after = activity_rollup_after;
+        self
+    }
+
+    /// Activity states that ended longer ago are deleted, with their apps and tags.
+    pub fn with_activity_state_max_age(mut self, max_age: Duration) -> Self {
+        self.activity_state_max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_blocked_activity_max_age(mut self, max_age: Duration) -> Self {
+        self.blocked_activity_max_age = Some(max_age);
+        self
+    }
+
+    /// Window titles of activities and activity states older than this are cleared.
+    pub fn with_window_title_max_age(mut self, max_age: Duration) -> Self {
+        self.window_title_max_age = Some(max_age);
+        self
+    }
+}
+
+/// What a retention run removed.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+pub struct RetentionReport {
+    pub activities_rolled_up: u64,
+    pub activity_states_deleted: u64,
+    pub blocked_activities_deleted: u64,
+    pub window_titles_cleared: u64,
+}
+
+/**
+ * Applies a `RetentionPolicy`, once or periodically, so the database doesn't grow forever with one
+ * row per input event.
+ */
+#[derive(Clone)]
+pub struct RetentionService {
+    activity_repo: ActivityRepo,
+    activity_rollup_repo: ActivityRollupRepo,
+    activity_state_repo: ActivityStateRepo,
+    activity_state_app_repo: ActivityStateAppRepo,
+    blocked_activity_repo: BlockedActivityRepo,
+    clock: Arc<dyn Clock>,
+}
+
+impl RetentionService {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        RetentionService {
+            activity_repo: ActivityRepo::new(pool.clone()),
+            activity_rollup_repo: ActivityRollupRepo::new(pool.clone()),
+            activity_state_repo: ActivityStateRepo::new(pool.clone()),
+            activity_state_app_repo: ActivityStateAppRepo::new(pool.clone()),
+            blocked_activity_repo: BlockedActivityRepo::new(pool),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /**
+     * Rolls up and deletes what the policy no longer keeps. Activities are rolled up in whole
+     * minutes, up to the end of the last activity state at most, so later runs don't split a minute.
+     */
+    pub async fn apply_retention(&self, policy: &RetentionPolicy) -> Result<RetentionReport> {
+        let now = self.clock.now();
+        let mut retention_report = RetentionReport::default();
+        let last_end_time = match self.activity_state_repo.get_last_activity_state().await {
+            Ok(activity_state) => activity_state.end_time,
+            Err(err) if err.is_not_found() => None,
+            Err(err) => return Err(err),
+        };
+
+        if let Some(max_age) = policy.window_title_max_age {
+            retention_report.window_titles_cleared += self
+                .activity_repo
+                .clear_window_titles_before(now - max_age)
+                .await?;
+            retention_report.window_titles_cleared += self
+                .activity_state_app_repo
+                .clear_window_titles_before(now - max_age)
+                .await?;
+        }
+        if let Some(last_end_time) = last_end_time {
+            let rollup_before =
+                start_of_minute((now - policy.activity_rollup_after).min(last_end_time));
+            retention_report.activities_rolled_up = self
+                .activity_rollup_repo
+                .roll_up_activities_before(rollup_before)
+                .await?;
+        }
+        if let Some(max_age) = policy.activity_state_max_age {
+            retention_report.activity_states_deleted = self
+                .activity_state_repo
+                .delete_activity_states_ending_before(now - max_age)
+                .await?;
+        }
+        if let Some(max_age) = policy.blocked_activity_max_age {
+            retention_report.blocked_activities_deleted = self
+                .blocked_activity_repo
+                .delete_blocked_activities_before(now - max_age)
+                .await?;
+        }
+        log::trace!("retention applied: {:?}", retention_report);
+        Ok(retention_report)
+    }
+
+    /**
+     * Applies the policy now and then every `retention_interval` until `true` is sent on `shutdown`.
+     * A run in progress completes before the loop stops.
+     */
+    pub fn start_retention_loop(
+        &self,
+        policy: RetentionPolicy,
+        retention_interval: Duration,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> tokio::task::JoinHandle<()> {
+        let retention_service = self.clone();
+        tokio::spawn(async move {
+            let mut wait_interval = tokio::time::interval(retention_interval);
+            loop {
+                tokio::select! {
+                    _ = wait_interval.tick() => {}
+                    _ = stopped(&mut shutdown) => break,
+                }
+                if let Err(err) = retention_service.apply_retention(&policy).await {
+                    log::error!("Failed to apply retention: {}", err);
+                }
+            }
+        })
+    }
+}
+
+fn start_of_minute(time: OffsetDateTime) -> OffsetDateTime {
+    let time = time.to_offset(UtcOffset::UTC);
+    time.replace_second(0)
+        .and_then(|time| time.replace_nanosecond(0))
+        .unwrap_or(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::{
+            app_repo::AppRepo,
+            db_manager,
+            models::{Activity, ActivityStateApp, ActivityType, App, BlockedActivity},
+            tag_repo::TagRepo,
+        },
+        services::{activity_state_service::ActivityPeriod, report_service::ReportService},
+        utils::clock::ManualClock,
+    };
+
+    #[tokio::test]
+    async fn test_apply_retention() {
+        let pool = db_manager::create_test_db().await;
+        let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let old = start_of_minute(now - 40 * day);
+        let activity_repo = ActivityRepo::new(pool.clone());
+        let activity_state_repo = ActivityStateRepo::new(pool.clone());
+        let activity_rollup_repo = ActivityRollupRepo::new(pool.clone());
+
+        let mut window = Activity::__create_test_window(None, None);
+        window.timestamp = Some(old);
+        let mut keyboard = Activity::__create_test_window(None, None);
+        keyboard.activity_type = ActivityType::Keyboard;
+        keyboard.app_window_title = None;
+        let mut activities = vec![window.clone()];
+        for seconds in [1, 2, 61] {
+            let mut keyboard = keyboard.clone();
+            keyboard.timestamp = Some(old + Duration::from_secs(seconds));
+            activities.push(keyboard);
+        }
+        let mut recent_window = window.clone();
+        recent_window.timestamp = Some(now - Duration::from_secs(90));
+        activities.push(recent_window);
+        let mut unprocessed = keyboard.clone();
+        unprocessed.timestamp = Some(now - Duration::from_secs(30));
+        activities.push(unprocessed);
+        activity_repo.save_activities(&activities).await.unwrap();
+        let tag_repo = TagRepo::new(pool.clone());
+        let coding_tag = tag_repo.get_tag_by_name("coding").await.unwrap();
+        let app = App::__create_test_apps(&["editor".to_string()]).remove(0);
+        AppRepo::new(pool.clone()).save_app(&app).await.unwrap();
+        // both states have their tags and app focus times recorded
+        for (start_time, end_time) in [
+            (old, old + Duration::from_secs(120)),
+            (
+                now - Duration::from_secs(120),
+                now - Duration::from_secs(60),
+            ),
+        ] {
+            activity_state_repo
+                .create_idle_activity_state(&ActivityPeriod {
+                    start_time,
+                    end_time,
+                })
+                .await
+                .unwrap();
+            let activity_state_id = activity_state_repo
+                .get_last_activity_state()
+                .await
+                .unwrap()
+                .id
+                .unwrap();
+            tag_repo
+                .create_activity_state_tags(activity_state_id, std::slice::from_ref(&coding_tag))
+                .await
+                .unwrap();
+            ActivityStateAppRepo::new(pool.clone())
+                .save_activity_state_apps(&[ActivityStateApp {
+                    activity_state_id,
+                    app_id: app.id.clone().unwrap(),
+                    window_title: None,
+                    seconds: 60.0,
+                }])
+                .await
+                .unwrap();
+        }
+        BlockedActivityRepo::new(pool.clone())
+            .save_blocked_activity(&BlockedActivity {
+                id: uuid::Uuid::new_v4().to_string(),
+                external_app_id: "youtube.com".to_string(),
+                created_at: Some(old),
+                updated_at: Some(old),
+            })
+            .await
+            .unwrap();
+
+        let retention_service =
+            RetentionService::new(pool.clone()).with_clock(Arc::new(ManualClock::new(now)));
+        let policy = RetentionPolicy::default()
+            .with_activity_state_max_age(30 * day)
+            .with_blocked_activity_max_age(30 * day)
+            .with_window_title_max_age(day);
+        let report_service = ReportService::new(pool.clone());
+        let retained_summaries = || {
+            report_service.get_daily_summaries(
+                (now - day).date(),
+                now.date(),
+                |date: time::Date| date.midnight().assume_utc(),
+            )
+        };
+        let summaries_before = retained_summaries().await.unwrap();
+        let retention_report = retention_service.apply_retention(&policy).await.unwrap();
+        assert_eq!(
+            retention_report,
+            RetentionReport {
+                activities_rolled_up: 4,
+                activity_states_deleted: 1,
+                blocked_activities_deleted: 1,
+                window_titles_cleared: 1,
+            }
+        );
+
+        // the old state's tags and apps are deleted with it, the retained range reports the same
+        for table in ["activity_state_tag", "activity_state_app"] {
+            let (orphans, retained): (i64, i64) = sqlx::query_as(&format!(
+                "SELECT
+                    COUNT(*) FILTER (WHERE activity_state_id NOT IN (SELECT id FROM activity_state)),
+                    COUNT(*) FILTER (WHERE activity_state_id IN (SELECT id FROM activity_state))
+                FROM {}",
+                table
+            ))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            assert_eq!((orphans, retained), (0, 1), "{}", table);
+        }
+        let summaries_after = retained_summaries().await.unwrap();
+        assert_eq!(summaries_after, summaries_before);
+        let retained_tags = summaries_after
+            .iter()
+            .flat_map(|summary| summary.tags.iter().map(|tag| tag.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(retained_tags, vec!["coding"]);
+
+        // two keyboard activities in the first minute, one in the next and the window
+        let rollups = activity_rollup_repo
+            .get_activity_rollups_between(old, now)
+            .await
+            .unwrap();
+        let counts = rollups
+            .iter()
+            .map(|rollup| {
+                (
+                    rollup.minute,
+                    rollup.activity_type.clone(),
+                    rollup.event_count,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(counts.len(), 3);
+        assert!(counts.contains(&(old, ActivityType::Keyboard, 2)));
+        assert!(counts.contains(&(old, ActivityType::Window, 1)));
+        assert!(counts.contains(&(old + Duration::from_secs(60), ActivityType::Keyboard, 1)));
+
+        // the last window and the activities after the last activity state are kept
+        let remaining = activity_repo
+            .get_activities_since_last_activity_state()
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(
+            activity_repo
+                .get_last_activity_by_type(ActivityType::Window)
+                .await
+                .unwrap()
+                .app_window_title,
+            Some("main.rs - app-codeclimbers".to_string())
+        );
+
+        // running again removes nothing more
+        assert_eq!(
+            retention_service.apply_retention(&policy).await.unwrap(),
+            RetentionReport::default()
+        );
+
+        // once rolled up, the title of the kept last window is cleared as well
+        activity_state_repo
+            .create_idle_activity_state(&ActivityPeriod {
+                start_time: now - Duration::from_secs(60),
+                end_time: now + Duration::from_secs(60),
+            })
+            .await
+            .unwrap();
+        RetentionService::new(pool.clone())
+            .with_clock(Arc::new(ManualClock::new(now + 40 * day)))
+            .apply_retention(&RetentionPolicy::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            activity_repo
+                .get_last_activity_by_type(ActivityType::Window)
+                .await
+                .unwrap()
+                .app_window_title,
+            None
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use parking_lot::Mutex;
+use regex::Regex;
+
+use crate::{
+    db::{models::TagRule, tag_repo::TagRepo, tag_rule_repo::TagRuleRepo},
+    error::{Error, Result},
+};
+
+// bumped whenever tag rules change, so every cached matcher rebuilds on its next use
+static TAG_RULES_VERSION: AtomicU64 = AtomicU64::new(0);
+
+/// Makes every `TagRuleMatcherCache` reload the enabled tag rules on its next use.
+pub(crate) fn invalidate_tag_rule_matchers() {
+    TAG_RULES_VERSION.fetch_add(1, Ordering::SeqCst);
+}
+
+/**
+ * Manages user defined tag rules. Rules are matched when a window is focused
+ * (see `AppService::match_tag_rules`) and tag the activity states it was focused in.
+ */
+#[derive(Clone)]
+pub struct TagRuleService {
+    tag_rule_repo: TagRuleRepo,
+    tag_repo: TagRepo,
+}
+
+impl TagRuleService {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        TagRuleService {
+            tag_rule_repo: TagRuleRepo::new(pool.clone()),
+            tag_repo: TagRepo::new(pool),
+        }
+    }
+
+    /**
+     * Validates the rule's patterns and tag before saving it. Fails with `Validation` for a pattern
+     * that doesn't compile and `NotFound` for an unknown tag.
+     */
+    pub async fn create_tag_rule(&self, tag_rule: TagRule) -> Result<TagRule> {
+        for pattern in [&tag_rule.title_pattern, &tag_rule.url_pattern]
+            .into_iter()
+            .flatten()
+        {
+            Regex::new(pattern)
+                .map_err(|err| Error::Validation(format!("invalid tag rule pattern: {}", err)))?;
+        }
+        self.tag_repo.get_tag_by_id(&tag_rule.tag_id).await?;
+        self.tag_rule_repo.save_tag_rule(&tag_rule).await?;
+        invalidate_tag_rule_matchers();
+        self.tag_rule_repo.get_tag_rule(&tag_rule.id).await
+    }
+
+    pub async fn get_tag_rules(&self) -> Result<Vec<TagRule>> {
+        self.tag_rule_repo.get_all_tag_rules().await
+    }
+
+    pub async fn set_tag_rule_enabled(&self, id: &str, is_enabled: bool) -> Result<()> {
+        self.tag_rule_repo
+            .set_tag_rule_enabled(id, is_enabled)
+            .await?;
+        invalidate_tag_rule_matchers();
+        Ok(())
+    }
+
+    pub async fn delete_tag_rule(&self, id: &str) -> Result<()> {
+        self.tag_rule_repo.delete_tag_rule(id).await?;
+        invalidate_tag_rule_matchers();
+        Ok(())
+    }
+}
+
+struct CompiledTagRule {
+    tag_rule: TagRule,
+    title_regex: Option<Regex>,
+    url_regex: Option<Regex>,
+}
+
+/**
+ * Evaluates tag rules against focused windows. Rules with a pattern that doesn't compile are skipped.
+ */
+pub(crate) struct TagRuleMatcher {
+    rules: Vec<CompiledTagRule>,
+}
+
+impl TagRuleMatcher {
+    pub fn new(tag_rules: Vec<TagRule>) -> Self {
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>, regex::Error> {
+            pattern.as_deref().map(Regex::new).transpose()
+        };
+        let rules = tag_rules
+            .into_iter()
+            .filter_map(|tag_rule| {
+                match (
+                    compile(&tag_rule.title_pattern),
+                    compile(&tag_rule.url_pattern),
+                ) {
+                    (Ok(title_regex), Ok(url_regex)) => Some(CompiledTagRule {
+                        tag_rule,
+                        title_regex,
+                        url_regex,
+                    }),
+                    (Err(err), _) | (_, Err(err)) => {
+                        log::error!("Skipping tag rule {}: {}", tag_rule.id, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        TagRuleMatcher { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /**
+     * Returns the highest priority rules matching a window of the given app. `url` is matched on its
+     * path only, so rules for a site are written against e.g. `/owner/repo/pull/1`.
+     */
+    pub fn matching_rules(
+        &self,
+        app_external_id: &str,
+        window_title: Option<&str>,
+        url: Option<&str>,
+    ) -> Vec<&TagRule> {
+        let url_path = url.and_then(url_path);
+        let matching = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.tag_rule
+                    .app_external_id
+                    .as_deref()
+                    .is_none_or(|external_id| external_id == app_external_id)
+            })
+            .filter(|rule| pattern_matches(&rule.title_regex, window_title))
+            .filter(|rule| pattern_matches(&rule.url_regex, url_path.as_deref()))
+            .map(|rule| &rule.tag_rule)
+            .collect::<Vec<_>>();
+        let Some(top_priority) = matching.iter().map(|rule| rule.priority).max() else {
+            return matching;
+        };
+        matching
+            .into_iter()
+            .filter(|rule| rule.priority == top_priority)
+            .collect()
+    }
+}
+
+type VersionedMatcher = (u64, Arc<TagRuleMatcher>);
+
+/**
+ * The matcher for the enabled tag rules, compiled once and shared by clones. It is rebuilt after the
+ * rules change through `TagRuleService` or a tag is deleted (see `invalidate_tag_rule_matchers`).
+ */
+#[derive(Clone, Default)]
+pub(crate) struct TagRuleMatcherCache {
+    cached: Arc<Mutex<Option<VersionedMatcher>>>,
+}
+
+impl TagRuleMatcherCache {
+    pub async fn get(&self, tag_rule_repo: &TagRuleRepo) -> Result<Arc<TagRuleMatcher>> {
+        // read before loading, so a change made while loading triggers another reload
+        let version = TAG_RULES_VERSION.load(Ordering::SeqCst);
+        if let Some((cached_version, matcher)) = self.cached.lock().as_ref() {
+            if *cached_version == version {
+                return Ok(matcher.clone());
+            }
+        }
+        let matcher = Arc::new(TagRuleMatcher::new(
+            tag_rule_repo.get_enabled_tag_rules().await?,
+        ));
+        *self.cached.lock() = Some((version, matcher.clone()));
+        Ok(matcher)
+    }
+}
+
+fn pattern_matches(regex: &Option<Regex>, value: Option<&str>) -> bool {
+    match (regex, value) {
+        (None, _) => true,
+        (Some(regex), Some(value)) => regex.is_match(value),
+        (Some(_), None) => false,
+    }
+}
+
+fn url_path(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .or_else(|_| url::Url::parse(&format!("https://{}", url)))
+        .ok()
+        .map(|url| url.path().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db_manager;
+
+    fn rule(
+        app_external_id: Option<&str>,
+        title_pattern: Option<&str>,
+        url_pattern: Option<&str>,
+        tag_id: &str,
+        priority: i64,
+    ) -> TagRule {
+        TagRule::new(
+            app_external_id.map(str::to_string),
+            title_pattern.map(str::to_string),
+            url_pattern.map(str::to_string),
+            tag_id.to_string(),
+            1.0,
+            priority,
+        )
+    }
+
+    #[test]
+    fn test_matching_rules() {
+        let matcher = TagRuleMatcher::new(vec![
+            rule(
+                Some("github.com"),
+                None,
+                Some(r"^/[^/]+/[^/]+/pull/\d+"),
+                "coding",
+                10,
+            ),
+            rule(
+                Some("github.com"),
+                None,
+                Some("^/trending"),
+                "consuming",
+                10,
+            ),
+            rule(Some("github.com"), None, None, "neutral", 0),
+            rule(None, Some("(?i)standup"), None, "communication", 5),
+            rule(None, Some("("), None, "broken", 100),
+        ]);
+
+        let tag_ids = |rules: Vec<&TagRule>| {
+            rules
+                .into_iter()
+                .map(|rule| rule.tag_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tag_ids(matcher.matching_rules(
+                "github.com",
+                Some("Fix parser by someone"),
+                Some("https://github.com/org/repo/pull/42/files"),
+            )),
+            vec!["coding"]
+        );
+        assert_eq!(
+            tag_ids(matcher.matching_rules(
+                "github.com",
+                Some("Trending repositories"),
+                Some("github.com/trending"),
+            )),
+            vec!["consuming"]
+        );
+        assert_eq!(
+            tag_ids(matcher.matching_rules("github.com", None, Some("https://github.com/org"))),
+            vec!["neutral"]
+        );
+        assert_eq!(
+            tag_ids(matcher.matching_rules("us.zoom.xos", Some("Daily Standup"), None)),
+            vec!["communication"]
+        );
+        assert!(matcher
+            .matching_rules("com.apple.Terminal", Some("zsh"), None)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_matcher_cache_follows_tag_rule_changes() {
+        let pool = db_manager::create_test_db().await;
+        let tag_rule_service = TagRuleService::new(pool.clone());
+        let tag_rule_repo = TagRuleRepo::new(pool.clone());
+        let coding_tag = TagRepo::new(pool).get_tag_by_name("coding").await.unwrap();
+        let matcher_cache = TagRuleMatcherCache::default();
+        let matches = |matcher: &TagRuleMatcher| {
+            matcher
+                .matching_rules("com.apple.Terminal", Some("cargo test"), None)
+                .len()
+        };
+        assert!(matcher_cache.get(&tag_rule_repo).await.unwrap().is_empty());
+        // reused until the rules change
+        assert!(Arc::ptr_eq(
+            &matcher_cache.get(&tag_rule_repo).await.unwrap(),
+            &matcher_cache.get(&tag_rule_repo).await.unwrap()
+        ));
+
+        let tag_rule = tag_rule_service
+            .create_tag_rule(rule(None, Some("cargo"), None, &coding_tag.id.unwrap(), 0))
+            .await
+            .unwrap();
+        assert_eq!(
+            matches(&matcher_cache.get(&tag_rule_repo).await.unwrap()),
+            1
+        );
+        tag_rule_service
+            .set_tag_rule_enabled(&tag_rule.id, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            matches(&matcher_cache.get(&tag_rule_repo).await.unwrap()),
+            0
+        );
+        tag_rule_service
+            .set_tag_rule_enabled(&tag_rule.id, true)
+            .await
+            .unwrap();
+        tag_rule_service
+            .delete_tag_rule(&tag_rule.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            matches(&matcher_cache.get(&tag_rule_repo).await.unwrap()),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_tag_rule_validates() {
+        let pool = db_manager::create_test_db().await;
+        let tag_rule_service = TagRuleService::new(pool.clone());
+        let coding_tag = TagRepo::new(pool).get_tag_by_name("coding").await.unwrap();
+
+        let invalid_pattern = tag_rule_service
+            .create_tag_rule(rule(
+                None,
+                Some("("),
+                None,
+                &coding_tag.id.clone().unwrap(),
+                0,
+            ))
+            .await;
+        assert!(matches!(invalid_pattern, Err(Error::Validation(_))));
+        let unknown_tag = tag_rule_service
+            .create_tag_rule(rule(None, Some("rust"), None, "missing", 0))
+            .await;
+        assert!(matches!(unknown_tag, Err(Error::NotFound { .. })));
+
+        let tag_rule = tag_rule_service
+            .create_tag_rule(rule(None, Some("rust"), None, &coding_tag.id.unwrap(), 0))
+            .await
+            .unwrap();
+        assert!(tag_rule.created_at.is_some());
+        assert_eq!(tag_rule_service.get_tag_rules().await.unwrap().len(), 1);
+    }
+}
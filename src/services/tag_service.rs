@@ -0,0 +1,361 @@
+use crate::{
+    db::{
+        app_repo::AppRepo,
+        models::{AppTag, Tag},
+        tag_repo::TagRepo,
+    },
+    error::{Error, Result},
+    services::tag_rule_service::invalidate_tag_rule_matchers,
+};
+
+/// Tags of this type (idle, creating, consuming, neutral) drive scoring and can't be created or changed.
+const DEFAULT_TAG_TYPE: &str = "default";
+const FALLBACK_APP_TAG: &str = "neutral";
+
+/**
+ * Management API for tags, the tags assigned to apps and which apps and tags are passive. Seeded
+ * tags (`is_default`), e.g. `idle` or `coding`, can be blocked but not renamed or deleted.
+ */
+#[derive(Clone)]
+pub struct TagService {
+    tag_repo: TagRepo,
+    app_repo: AppRepo,
+}
+
+impl TagService {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        TagService {
+            tag_repo: TagRepo::new(pool.clone()),
+            app_repo: AppRepo::new(pool),
+        }
+    }
+
+    pub async fn get_tags(&self) -> Result<Vec<Tag>> {
+        self.tag_repo.get_all_tags().await
+    }
+
+    pub async fn get_tag(&self, tag_id: &str) -> Result<Tag> {
+        self.tag_repo.get_tag_by_id(tag_id).await
+    }
+
+    pub async fn create_tag(
+        &self,
+        name: &str,
+        tag_type: &str,
+        parent_tag_id: Option<&str>,
+    ) -> Result<Tag> {
+        let name = validate_name(name)?;
+        let tag_type = tag_type.trim();
+        if tag_type.is_empty() {
+            return Err(Error::Validation("tag type is empty".to_string()));
+        }
+        if tag_type == DEFAULT_TAG_TYPE {
+            return Err(Error::Validation(format!(
+                "tags of type {} are reserved",
+                DEFAULT_TAG_TYPE
+            )));
+        }
+        if let Some(parent_tag_id) = parent_tag_id {
+            self.get_tag(parent_tag_id).await?;
+        }
+        self.ensure_unique(name, tag_type).await?;
+        self.tag_repo
+            .create_tag(name, tag_type, parent_tag_id)
+            .await
+            .map_err(|err| unique_violation(err, name, tag_type))
+    }
+
+    pub async fn rename_tag(&self, tag_id: &str, name: &str) -> Result<Tag> {
+        let name = validate_name(name)?;
+        let tag = self.get_custom_tag(tag_id).await?;
+        let tag_type = tag.tag_type.unwrap_or_default();
+        if tag.name != name {
+            self.ensure_unique(name, &tag_type).await?;
+        }
+        self.tag_repo
+            .rename_tag(tag_id, name)
+            .await
+            .map_err(|err| unique_violation(err, name, &tag_type))?;
+        self.get_tag(tag_id).await
+    }
+
+    /**
+     * Deletes a custom tag, including its app tags, tag rules and the history of activity states
+     * tagged with it. Its children move up to its parent.
+     */
+    pub async fn delete_tag(&self, tag_id: &str) -> Result<()> {
+        self.get_custom_tag(tag_id).await?;
+        self.tag_repo.delete_tag(tag_id).await?;
+        // its tag rules were deleted with it
+        invalidate_tag_rule_matchers();
+        Ok(())
+    }
+
+    pub async fn set_tag_parent(&self, tag_id: &str, parent_tag_id: Option<&str>) -> Result<Tag> {
+        self.get_tag(tag_id).await?;
+        if let Some(parent_tag_id) = parent_tag_id {
+            self.get_tag(parent_tag_id).await?;
+        }
+        self.tag_repo.set_parent_tag(tag_id, parent_tag_id).await?;
+        self.get_tag(tag_id).await
+    }
+
+    pub async fn set_tag_blocked(&self, tag_id: &str, is_blocked: bool) -> Result<Tag> {
+        self.get_tag(tag_id).await?;
+        self.tag_repo.set_tag_blocked(tag_id, is_blocked).await?;
+        self.get_tag(tag_id).await
+    }
+
+    /// Focused windows of apps with a passive tag keep a state without input from being idle.
+    pub async fn set_tag_passive(&self, tag_id: &str, is_passive: bool) -> Result<Tag> {
+        self.get_tag(tag_id).await?;
+        self.tag_repo.set_tag_passive(tag_id, is_passive).await?;
+        self.get_tag(tag_id).await
+    }
+
+    /// Focused windows of a passive app (e.g. video calls) keep a state without input from being idle.
+    pub async fn set_app_passive(&self, app_id: &str, is_passive: bool) -> Result<()> {
+        self.ensure_app_exists(app_id).await?;
+        self.app_repo.set_app_passive(app_id, is_passive).await?;
+        Ok(())
+    }
+
+    pub async fn get_app_tags(&self, app_id: &str) -> Result<Vec<AppTag>> {
+        self.ensure_app_exists(app_id).await?;
+        self.app_repo
+            .get_app_tag_by_app_ids(&vec![app_id.to_string()])
+            .await
+    }
+
+    /// Assigns a tag to an app with a weight between 0 and 1, replacing the weight if it was assigned already.
+    pub async fn assign_app_tag(&self, app_id: &str, tag_id: &str, weight: f32) -> Result<AppTag> {
+        if !(0.0..=1.0).contains(&weight) {
+            return Err(Error::Validation(format!(
+                "weight {} is not between 0 and 1",
+                weight
+            )));
+        }
+        self.ensure_app_exists(app_id).await?;
+        self.get_tag(tag_id).await?;
+        self.tag_repo.upsert_app_tag(app_id, tag_id, weight).await
+    }
+
+    pub async fn remove_app_tag(&self, app_id: &str, tag_id: &str) -> Result<()> {
+        let result = self.tag_repo.delete_app_tag(app_id, tag_id).await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound {
+                entity: "app tag",
+                id: format!("{} for app {}", tag_id, app_id),
+            });
+        }
+        Ok(())
+    }
+
+    /// Restores the app's seeded tags, or the `neutral` tag for apps that weren't seeded.
+    pub async fn reset_app_tags(&self, app_id: &str) -> Result<Vec<AppTag>> {
+        self.ensure_app_exists(app_id).await?;
+        let fallback_tag = self.tag_repo.get_tag_by_name(FALLBACK_APP_TAG).await?;
+        let fallback_tag_id = fallback_tag.id.ok_or_else(|| Error::NotFound {
+            entity: "tag",
+            id: FALLBACK_APP_TAG.to_string(),
+        })?;
+        self.tag_repo
+            .reset_app_tags(app_id, &fallback_tag_id)
+            .await?;
+        self.get_app_tags(app_id).await
+    }
+
+    async fn get_custom_tag(&self, tag_id: &str) -> Result<Tag> {
+        let tag = self.get_tag(tag_id).await?;
+        if tag.is_default {
+            return Err(Error::Validation(format!(
+                "seeded tag {} can't be changed",
+                tag.name
+            )));
+        }
+        Ok(tag)
+    }
+
+    async fn ensure_unique(&self, name: &str, tag_type: &str) -> Result<()> {
+        match self
+            .tag_repo
+            .get_tag_by_name_and_type(name, tag_type)
+            .await?
+        {
+            Some(_) => Err(already_exists(name, tag_type)),
+            None => Ok(()),
+        }
+    }
+
+    async fn ensure_app_exists(&self, app_id: &str) -> Result<()> {
+        let apps = self
+            .app_repo
+            .get_apps_by_ids(&vec![app_id.to_string()])
+            .await?;
+        if apps.is_empty() {
+            return Err(Error::NotFound {
+                entity: "app",
+                id: app_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn validate_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::Validation("tag name is empty".to_string()));
+    }
+    Ok(name)
+}
+
+// the existence check can race with another writer, UNIQUE(name, tag_type) has the final say
+fn unique_violation(err: Error, name: &str, tag_type: &str) -> Error {
+    match &err {
+        Error::Database {
+            source: sqlx::Error::Database(db_err),
+            ..
+        } if db_err.is_unique_violation() => already_exists(name, tag_type),
+        _ => err,
+    }
+}
+
+fn already_exists(name: &str, tag_type: &str) -> Error {
+    Error::Conflict(format!("a {} tag named {} already exists", tag_type, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{db_manager, models::App};
+
+    #[tokio::test]
+    async fn test_manage_tags() {
+        let pool = db_manager::create_test_db().await;
+        let tag_service = TagService::new(pool);
+        let coding = tag_service
+            .tag_repo
+            .get_tag_by_name("coding")
+            .await
+            .unwrap();
+
+        let rust = tag_service
+            .create_tag(" rust ", "category", coding.id.as_deref())
+            .await
+            .unwrap();
+        assert_eq!(rust.name, "rust");
+        assert_eq!(rust.parent_tag_id, coding.id);
+        assert!(matches!(
+            tag_service.create_tag("rust", "category", None).await,
+            Err(Error::Conflict(_))
+        ));
+        assert!(matches!(
+            tag_service.create_tag("focus", "default", None).await,
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            tag_service.create_tag("", "category", None).await,
+            Err(Error::Validation(_))
+        ));
+
+        let rust_id = rust.id.unwrap();
+        let renamed = tag_service.rename_tag(&rust_id, "rustlang").await.unwrap();
+        assert_eq!(renamed.name, "rustlang");
+        assert!(matches!(
+            tag_service.rename_tag(&rust_id, "coding").await,
+            Err(Error::Conflict(_))
+        ));
+        let idle = tag_service.tag_repo.get_tag_by_name("idle").await.unwrap();
+        assert!(matches!(
+            tag_service.rename_tag(&idle.id.unwrap(), "away").await,
+            Err(Error::Validation(_))
+        ));
+        // seeded category tags are kept so reset_app_tags can restore them
+        let coding_id = coding.id.clone().unwrap();
+        assert!(matches!(
+            tag_service.rename_tag(&coding_id, "programming").await,
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            tag_service.delete_tag(&coding_id).await,
+            Err(Error::Validation(_))
+        ));
+        assert_eq!(
+            tag_service.get_tag(&coding_id).await.unwrap().name,
+            "coding"
+        );
+
+        let blocked = tag_service.set_tag_blocked(&rust_id, true).await.unwrap();
+        assert!(blocked.is_blocked);
+        let passive = tag_service.set_tag_passive(&rust_id, true).await.unwrap();
+        assert!(passive.is_passive);
+
+        tag_service.delete_tag(&rust_id).await.unwrap();
+        assert!(matches!(
+            tag_service.get_tag(&rust_id).await,
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_manage_app_tags() {
+        let pool = db_manager::create_test_db().await;
+        let tag_service = TagService::new(pool.clone());
+        let tag_repo = TagRepo::new(pool.clone());
+        let app_repo = AppRepo::new(pool);
+        let consuming_id = tag_repo
+            .get_tag_by_name("consuming")
+            .await
+            .unwrap()
+            .id
+            .unwrap();
+
+        // zoom.us is seeded with consuming (0.5) and communication (1.0)
+        let zoom = app_repo.get_app_by_external_id("zoom.us").await.unwrap();
+        let zoom_id = zoom.id.unwrap();
+        let seeded = tag_service.get_app_tags(&zoom_id).await.unwrap();
+        assert!(seeded.iter().all(|app_tag| app_tag.is_default));
+
+        let reassigned = tag_service
+            .assign_app_tag(&zoom_id, &consuming_id, 0.25)
+            .await
+            .unwrap();
+        assert_eq!(reassigned.weight, 0.25);
+        // a seeded tag with a weight set by the user is no longer a default
+        assert!(!reassigned.is_default);
+        assert!(matches!(
+            tag_service
+                .assign_app_tag(&zoom_id, &consuming_id, 2.0)
+                .await,
+            Err(Error::Validation(_))
+        ));
+        tag_service
+            .remove_app_tag(&zoom_id, &consuming_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            tag_service.get_app_tags(&zoom_id).await.unwrap().len(),
+            seeded.len() - 1
+        );
+
+        let reset = tag_service.reset_app_tags(&zoom_id).await.unwrap();
+        assert_eq!(reset.len(), seeded.len());
+        let consuming = reset
+            .iter()
+            .find(|app_tag| app_tag.tag_id == consuming_id)
+            .unwrap();
+        assert_eq!(consuming.weight, 0.5);
+
+        let mut app = App::__create_test_apps(&["unseeded".to_string()]).remove(0);
+        app.app_external_id = "unseeded".to_string();
+        app_repo.save_app(&app).await.unwrap();
+        let reset = tag_service.reset_app_tags(&app.id.unwrap()).await.unwrap();
+        assert_eq!(reset.len(), 1);
+        assert!(!reset[0].is_default);
+        assert!(matches!(
+            tag_service.reset_app_tags("missing").await,
+            Err(Error::NotFound { .. })
+        ));
+    }
+}
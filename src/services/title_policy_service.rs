@@ -0,0 +1,218 @@
+use hmac::{Hmac, Mac};
+use os_monitor::WindowEvent;
+use regex::Regex;
+use sha2::Sha256;
+
+use crate::db::models::App;
+
+/// How a window title is changed before it is stored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TitleRedaction {
+    /// Stores no title.
+    Drop,
+    /// Keeps the first capture group of the pattern (the whole match without groups), e.g. a project
+    /// name. No title is stored when the pattern doesn't match.
+    Capture(String),
+    /**
+     * Stores an HMAC-SHA256 of the title keyed with a secret generated for this install. Hashes only
+     * tell whether two titles are equal; they can't be reversed or guessed by hashing candidate titles
+     * without the secret, and don't compare across installs.
+     */
+    Hash,
+    /// Replaces email addresses with `[email]` and numbers with `#`.
+    StripSensitive,
+}
+
+/**
+ * Redacts the window titles of an app, or of every app when `app` is `None`. `app` is an app name,
+ * bundle id or domain, which includes its subdomains. When several policies match a window, the
+ * first one applies. Tag rules are matched on the title before it is redacted, so a `title_pattern`
+ * rule still applies to windows whose title is dropped or hashed.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct TitlePolicy {
+    pub app: Option<String>,
+    pub redaction: TitleRedaction,
+}
+
+impl TitlePolicy {
+    pub fn new(app: Option<String>, redaction: TitleRedaction) -> Self {
+        TitlePolicy { app, redaction }
+    }
+}
+
+enum CompiledRedaction {
+    Drop,
+    Capture(Regex),
+    Hash,
+    StripSensitive,
+}
+
+/**
+ * Applies title policies to window events before they are recorded or stored.
+ */
+pub(crate) struct TitleRedactor {
+    policies: Vec<(Option<String>, CompiledRedaction)>,
+    hash_key: Vec<u8>,
+    email_regex: Regex,
+    number_regex: Regex,
+}
+
+impl TitleRedactor {
+    /// Fails if a `Capture` pattern doesn't compile.
+    pub fn new(title_policies: Vec<TitlePolicy>) -> Result<Self, regex::Error> {
+        let policies = title_policies
+            .into_iter()
+            .map(|title_policy| {
+                let redaction = match title_policy.redaction {
+                    TitleRedaction::Drop => CompiledRedaction::Drop,
+                    TitleRedaction::Capture(pattern) => {
+                        CompiledRedaction::Capture(Regex::new(&pattern)?)
+                    }
+                    TitleRedaction::Hash => CompiledRedaction::Hash,
+                    TitleRedaction::StripSensitive => CompiledRedaction::StripSensitive,
+                };
+                Ok((title_policy.app, redaction))
+            })
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+        Ok(TitleRedactor {
+            policies,
+            hash_key: Vec::new(),
+            email_regex: Regex::new(r"[\w.+-]+@[\w-]+(\.[\w-]+)+")?,
+            number_regex: Regex::new(r"\d+([.,:/-]\d+)*")?,
+        })
+    }
+
+    /// The per-install secret `Hash` policies are keyed with.
+    pub fn with_hash_key(mut self, hash_key: Vec<u8>) -> Self {
+        self.hash_key = hash_key;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Returns the title to store for the window, `None` if it is dropped.
+    pub fn redact(&self, event: &WindowEvent) -> Option<String> {
+        let title = event.window_title.as_str();
+        if self.policies.is_empty() {
+            return Some(title.to_string());
+        }
+        let Some((_, redaction)) = self.policies.iter().find(|(name, _)| {
+            name.as_deref()
+                .is_none_or(|name| App::window_is_named(event, name))
+        }) else {
+            return Some(title.to_string());
+        };
+        match redaction {
+            CompiledRedaction::Drop => None,
+            CompiledRedaction::Capture(regex) => regex.captures(title).map(|captures| {
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map_or("", |capture| capture.as_str())
+                    .to_string()
+            }),
+            CompiledRedaction::Hash => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&self.hash_key)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(title.as_bytes());
+                Some(format!("hmac-sha256:{:x}", mac.finalize().into_bytes()))
+            }
+            CompiledRedaction::StripSensitive => {
+                let title = self.email_regex.replace_all(title, "[email]");
+                Some(self.number_regex.replace_all(&title, "#").into_owned())
+            }
+        }
+    }
+}
+
+impl Default for TitleRedactor {
+    fn default() -> Self {
+        TitleRedactor::new(Vec::new()).expect("built-in title patterns are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use os_monitor::Platform;
+
+    use super::*;
+
+    fn window(app_name: &str, window_title: &str, url: Option<&str>) -> WindowEvent {
+        WindowEvent {
+            app_name: app_name.to_string(),
+            window_title: window_title.to_string(),
+            bundle_id: url.map(|_| "com.google.Chrome".to_string()),
+            url: url.map(str::to_string),
+            platform: Platform::Mac,
+        }
+    }
+
+    #[test]
+    fn test_redact() {
+        let title_redactor = TitleRedactor::new(vec![
+            TitlePolicy::new(Some("Slack".to_string()), TitleRedaction::Drop),
+            TitlePolicy::new(
+                Some("Cursor".to_string()),
+                TitleRedaction::Capture(r" - (.+)$".to_string()),
+            ),
+            TitlePolicy::new(Some("mail.google.com".to_string()), TitleRedaction::Hash),
+            TitlePolicy::new(Some("com.google.Chrome".to_string()), TitleRedaction::Drop),
+            TitlePolicy::new(None, TitleRedaction::StripSensitive),
+        ])
+        .unwrap()
+        .with_hash_key(b"install secret".to_vec());
+
+        assert_eq!(
+            title_redactor.redact(&window("Slack", "#general", None)),
+            None
+        );
+        assert_eq!(
+            title_redactor.redact(&window("Cursor", "main.rs - app-codeclimbers", None)),
+            Some("app-codeclimbers".to_string())
+        );
+        assert_eq!(
+            title_redactor.redact(&window("Cursor", "Welcome", None)),
+            None
+        );
+        let gmail = window(
+            "Google Chrome",
+            "Offer letter - Gmail",
+            Some("https://mail.google.com/mail/u/0"),
+        );
+        let hashed = title_redactor.redact(&gmail).unwrap();
+        assert!(hashed.starts_with("hmac-sha256:"));
+        assert!(!hashed.contains("Offer"));
+        assert_eq!(title_redactor.redact(&gmail), Some(hashed.clone()));
+        // another install hashes the same title differently
+        let other_install = TitleRedactor::new(vec![TitlePolicy::new(None, TitleRedaction::Hash)])
+            .unwrap()
+            .with_hash_key(b"other secret".to_vec());
+        assert_ne!(other_install.redact(&gmail), Some(hashed));
+        // policies keyed on a browser's bundle id apply to all of its other tabs
+        assert_eq!(
+            title_redactor.redact(&window(
+                "Google Chrome",
+                "CodeClimbersIO",
+                Some("https://github.com/CodeClimbersIO")
+            )),
+            None
+        );
+        assert_eq!(
+            title_redactor.redact(&window(
+                "Terminal",
+                "ssh jane.doe@example.com port 2222 on 2025-01-13",
+                None
+            )),
+            Some("ssh [email] port # on #".to_string())
+        );
+
+        assert!(TitleRedactor::new(vec![TitlePolicy::new(
+            None,
+            TitleRedaction::Capture("(".to_string())
+        )])
+        .is_err());
+    }
+}
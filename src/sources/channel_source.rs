@@ -0,0 +1,58 @@
+use os_monitor::AppEvent;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+use super::EventSource;
+
+/**
+ * Event source fed by the caller: every event passed to `send` is delivered to the subscribers.
+ * Useful to drive the service from tests, replays or events received from another machine.
+ */
+#[derive(Clone)]
+pub struct ChannelSource {
+    sender: Sender<AppEvent>,
+}
+
+impl ChannelSource {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        ChannelSource { sender }
+    }
+
+    /// Sends an event to all subscribers. Returns false if nobody is subscribed.
+    pub fn send(&self, event: AppEvent) -> bool {
+        self.sender.send(event).is_ok()
+    }
+}
+
+impl Default for ChannelSource {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl EventSource for ChannelSource {
+    fn subscribe(&self) -> Receiver<AppEvent> {
+        self.sender.subscribe()
+    }
+
+    fn start(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_channel_source() {
+        let source = ChannelSource::new(10);
+        assert!(!source.send(AppEvent::Mouse(true)));
+
+        let mut receiver = source.subscribe();
+        source.start();
+        assert!(source.send(AppEvent::Keyboard(true)));
+        match receiver.recv().await {
+            Ok(AppEvent::Keyboard(activity)) => assert!(activity),
+            _ => panic!("did not receive the keyboard event"),
+        }
+    }
+}
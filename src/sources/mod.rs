@@ -0,0 +1,22 @@
+use os_monitor::AppEvent;
+use tokio::sync::broadcast::Receiver;
+
+mod channel_source;
+mod os_monitor_source;
+mod replay_source;
+
+pub use channel_source::ChannelSource;
+pub use os_monitor_source::OsMonitorSource;
+pub use replay_source::{read_recording, EventRecorder, RecordedEvent, ReplayClock, ReplaySource};
+
+/**
+ * A producer of `AppEvent`s for the monitoring service.
+ * The service subscribes to the source before starting it, so no events produced after `start` are missed.
+ * Implementations exist for the os_monitor crate and for events pushed through a channel (tests, replays, remote agents).
+ */
+pub trait EventSource: Send + Sync {
+    fn subscribe(&self) -> Receiver<AppEvent>;
+
+    /// Starts producing events. Must not block the caller.
+    fn start(&self);
+}
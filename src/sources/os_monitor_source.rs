@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use os_monitor::{start_monitoring, AppEvent, Monitor};
+use tokio::sync::broadcast::Receiver;
+
+use super::EventSource;
+
+/// Event source backed by the platform monitor from the os_monitor crate.
+pub struct OsMonitorSource {
+    monitor: Arc<Monitor>,
+}
+
+impl OsMonitorSource {
+    pub fn new(monitor: Arc<Monitor>) -> Self {
+        OsMonitorSource { monitor }
+    }
+}
+
+impl EventSource for OsMonitorSource {
+    fn subscribe(&self) -> Receiver<AppEvent> {
+        self.monitor.subscribe()
+    }
+
+    fn start(&self) {
+        let monitor = self.monitor.clone();
+        std::thread::spawn(move || {
+            start_monitoring(monitor);
+        });
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use os_monitor::AppEvent;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+use super::EventSource;
+use crate::{
+    error::{Error, Result},
+    utils::clock::Clock,
+};
+
+/// One line of a recording: an event and the time the service received it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    #[serde(with = "time::serde::rfc3339")]
+    pub received_at: OffsetDateTime,
+    pub event: AppEvent,
+}
+
+/**
+ * Appends every event it is given to an NDJSON file, one `RecordedEvent` per line.
+ * Each line is written immediately so a recording survives a crash of the service.
+ */
+pub struct EventRecorder {
+    file: Mutex<File>,
+}
+
+impl EventRecorder {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(EventRecorder {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, event: &AppEvent, received_at: OffsetDateTime) -> io::Result<()> {
+        let recorded_event = RecordedEvent {
+            received_at,
+            event: event.clone(),
+        };
+        let mut line = serde_json::to_string(&recorded_event)?;
+        line.push('\n');
+        self.file.lock().write_all(line.as_bytes())
+    }
+}
+
+pub fn read_recording(path: impl AsRef<Path>) -> io::Result<Vec<RecordedEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut recorded_events = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        recorded_events.push(serde_json::from_str(&line)?);
+    }
+    Ok(recorded_events)
+}
+
+/**
+ * The time of a replayed recording: the `received_at` of the last event the subscriber took from the
+ * replay, or of the first event before that. Timestamps then come from the recording rather than from
+ * when it is replayed, no matter how fast it runs. Assumes a single subscriber that handles one event
+ * at a time, like `ActivityService`.
+ */
+pub struct ReplayClock {
+    sender: Sender<AppEvent>,
+    // received_at of the events sent so far, locked while sending so the count matches the channel
+    sent: Mutex<Vec<OffsetDateTime>>,
+    start: OffsetDateTime,
+}
+
+impl Clock for ReplayClock {
+    fn now(&self) -> OffsetDateTime {
+        let sent = self.sent.lock();
+        (sent.len() - self.sender.len())
+            .checked_sub(1)
+            .map_or(self.start, |last_taken| sent[last_taken])
+    }
+}
+
+/**
+ * Replays a recording made by `EventRecorder`.
+ * Events are sent with the same spacing as they were recorded, divided by `speed`, or back to back without delays.
+ * The channel is sized to hold the whole recording so a replay never lags, no matter how fast it runs.
+ * Pass `clock` to the service (`MonitoringConfig::with_clock`) so events are timestamped as recorded.
+ */
+pub struct ReplaySource {
+    recorded_events: Arc<Vec<RecordedEvent>>,
+    sender: Sender<AppEvent>,
+    speed: Option<f64>,
+    clock: Arc<ReplayClock>,
+}
+
+impl ReplaySource {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(read_recording(path)?))
+    }
+
+    pub fn new(recorded_events: Vec<RecordedEvent>) -> Self {
+        let (sender, _) = broadcast::channel(recorded_events.len().max(1));
+        let clock = Arc::new(ReplayClock {
+            sender: sender.clone(),
+            sent: Mutex::new(Vec::with_capacity(recorded_events.len())),
+            start: recorded_events
+                .first()
+                .map_or_else(OffsetDateTime::now_utc, |first| first.received_at),
+        });
+        ReplaySource {
+            recorded_events: Arc::new(recorded_events),
+            sender,
+            speed: Some(1.0),
+            clock,
+        }
+    }
+
+    /**
+     * Replays `speed` times faster than real time, e.g. 60.0 replays an hour in a minute. Fails unless
+     * `speed` is a finite number above zero.
+     */
+    pub fn with_speed(mut self, speed: f64) -> Result<Self> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(Error::Validation(format!(
+                "replay speed must be a positive number, got {}",
+                speed
+            )));
+        }
+        self.speed = Some(speed);
+        Ok(self)
+    }
+
+    pub fn without_delays(mut self) -> Self {
+        self.speed = None;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.recorded_events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recorded_events.is_empty()
+    }
+
+    pub fn clock(&self) -> Arc<ReplayClock> {
+        self.clock.clone()
+    }
+}
+
+impl EventSource for ReplaySource {
+    fn subscribe(&self) -> Receiver<AppEvent> {
+        self.sender.subscribe()
+    }
+
+    fn start(&self) {
+        let recorded_events = self.recorded_events.clone();
+        let sender = self.sender.clone();
+        let speed = self.speed;
+        let clock = self.clock.clone();
+        tokio::spawn(async move {
+            let mut previous: Option<OffsetDateTime> = None;
+            for recorded_event in recorded_events.iter() {
+                if let (Some(speed), Some(previous)) = (speed, previous) {
+                    let delay = (recorded_event.received_at - previous).as_seconds_f64() / speed;
+                    if delay > 0.0 {
+                        tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+                    }
+                }
+                previous = Some(recorded_event.received_at);
+                let mut sent = clock.sent.lock();
+                if sender.send(recorded_event.event.clone()).is_err() {
+                    log::warn!("Replay has no subscribers, stopping");
+                    return;
+                }
+                sent.push(recorded_event.received_at);
+            }
+            log::trace!("Replay finished: {} events", recorded_events.len());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use os_monitor::{Platform, WindowEvent};
+
+    use super::*;
+    use crate::{
+        db::{db_manager, models::ActivityType},
+        services::activities_service::ActivityService,
+    };
+
+    fn recording_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("recording-{}.ndjson", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_record_and_read_recording() {
+        let path = recording_path();
+        let recorder = EventRecorder::open(&path).unwrap();
+        let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+        recorder.record(&AppEvent::Mouse(true), now).unwrap();
+        recorder
+            .record(&AppEvent::Keyboard(true), now + Duration::from_secs(1))
+            .unwrap();
+
+        let recorded_events = read_recording(&path).unwrap();
+        assert_eq!(recorded_events.len(), 2);
+        assert_eq!(recorded_events[0].received_at, now);
+        assert!(matches!(recorded_events[1].event, AppEvent::Keyboard(true)));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_into_activity_service() {
+        let path = recording_path();
+        let recorder = EventRecorder::open(&path).unwrap();
+        let start = OffsetDateTime::now_utc();
+        let events = [
+            AppEvent::Window(WindowEvent {
+                app_name: "Cursor".to_string(),
+                window_title: "main.rs - app-codeclimbers".to_string(),
+                bundle_id: Some("com.todesktop.230313mzl4w4u92".to_string()),
+                url: None,
+                platform: Platform::Mac,
+            }),
+            AppEvent::Keyboard(true),
+            AppEvent::Mouse(true),
+        ];
+        for (i, event) in events.iter().enumerate() {
+            recorder
+                .record(event, start + Duration::from_secs(30 * i as u64))
+                .unwrap();
+        }
+
+        let pool = db_manager::create_test_db().await;
+        // an hour of recording would replay in a second at this speed
+        let source = ReplaySource::open(&path)
+            .unwrap()
+            .with_speed(3600.0)
+            .unwrap();
+        assert_eq!(source.len(), 3);
+        let activity_service = ActivityService::new(pool).with_clock(source.clock());
+        activity_service.register_receiver(source.subscribe()).await;
+        source.start();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        activity_service.flush_activities().await.unwrap();
+
+        let window = activity_service.get_activity(1).await.unwrap();
+        let keyboard = activity_service.get_activity(2).await.unwrap();
+        let mouse = activity_service.get_activity(3).await.unwrap();
+        assert_eq!(window.activity_type, ActivityType::Window);
+        assert_eq!(keyboard.activity_type, ActivityType::Keyboard);
+        assert_eq!(mouse.activity_type, ActivityType::Mouse);
+        assert_eq!(keyboard.app_id, window.app_id);
+        // activities are timestamped as recorded
+        assert_eq!(window.timestamp, Some(start));
+        assert_eq!(keyboard.timestamp, Some(start + Duration::from_secs(30)));
+        assert_eq!(mouse.timestamp, Some(start + Duration::from_secs(60)));
+
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(ReplaySource::open(&path)
+                .unwrap()
+                .with_speed(speed)
+                .is_err());
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use time::OffsetDateTime;
+
+/**
+ * Source of the current time for the service.
+ * Everything that timestamps activities or decides which interval just completed asks the clock
+ * instead of calling `OffsetDateTime::now_utc()`, so tests can simulate hours of usage instantly.
+ */
+pub trait Clock: Send + Sync {
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// The real wall clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<OffsetDateTime>,
+}
+
+impl ManualClock {
+    pub fn new(now: OffsetDateTime) -> Self {
+        ManualClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: OffsetDateTime) {
+        *self.now.lock() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock();
+        *now += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> OffsetDateTime {
+        *self.now.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let start = OffsetDateTime::now_utc();
+        let clock = ManualClock::new(start);
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::from_secs(3 * 60 * 60));
+        assert_eq!(clock.now(), start + Duration::from_secs(3 * 60 * 60));
+        clock.set(start);
+        assert_eq!(clock.now(), start);
+    }
+}
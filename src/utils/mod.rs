@@ -1,2 +1,5 @@
+pub mod clock;
+pub(crate) mod retry;
+pub(crate) mod shutdown;
 #[cfg(test)]
 pub mod test_utils;
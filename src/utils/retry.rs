@@ -0,0 +1,91 @@
+use std::{future::Future, time::Duration};
+
+use crate::error::{Error, Result};
+
+const MAX_RETRIES: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/**
+ * Whether retrying could succeed: SQLite reported the database busy or locked (e.g. another process
+ * is writing), or no pooled connection became available in time.
+ */
+pub(crate) fn is_transient(err: &Error) -> bool {
+    let Error::Database { source, .. } = err else {
+        return false;
+    };
+    match source {
+        sqlx::Error::Database(db_err) => db_err
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            // extended result codes keep the primary code in the low byte
+            .is_some_and(|code| matches!(code & 0xff, 5 | 6)),
+        sqlx::Error::PoolTimedOut => true,
+        _ => false,
+    }
+}
+
+/**
+ * Runs `operation` until it succeeds, fails with a non transient error or has been retried
+ * `MAX_RETRIES` times, doubling the wait between attempts.
+ */
+pub(crate) async fn retry_transient<T, F, Fut>(name: &str, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut retries = 0;
+    loop {
+        match operation().await {
+            Err(err) if retries < MAX_RETRIES && is_transient(&err) => {
+                retries += 1;
+                log::warn!(
+                    "{} failed ({}), retry {} of {} in {:?}",
+                    name,
+                    err,
+                    retries,
+                    MAX_RETRIES,
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_transient() {
+        let attempts = AtomicU32::new(0);
+        let result = retry_transient("test", || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(sqlx::Error::PoolTimedOut.into())
+            } else {
+                Ok(42)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // errors that won't go away are returned right away
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry_transient("test", || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(Error::NotFound {
+                entity: "app",
+                id: "zoom.us".to_string(),
+            })
+        })
+        .await;
+        assert!(matches!(result, Err(Error::NotFound { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
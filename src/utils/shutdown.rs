@@ -0,0 +1,10 @@
+use tokio::sync::watch;
+
+/**
+ * Resolves once `true` is sent on the shutdown channel. Background loops select on it next to their
+ * tick, so they stop between runs rather than in the middle of one. The sender is owned by whoever
+ * spawned the loop, so this only returns once stopped.
+ */
+pub(crate) async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stopped| *stopped).await;
+}